@@ -0,0 +1,222 @@
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+/// What kind of Virpil hardware a device is.
+#[derive(Debug, Copy, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum DeviceFamily {
+    Grip,
+    Base, // Joystick base (carries the USB controller for its grip)
+    Throttle,
+    Panel,
+    Pedals,
+    #[default]
+    Other,
+}
+
+impl std::fmt::Display for DeviceFamily {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DeviceFamily::Grip => write!(f, "grip"),
+            DeviceFamily::Base => write!(f, "base"),
+            DeviceFamily::Throttle => write!(f, "throttle"),
+            DeviceFamily::Panel => write!(f, "panel"),
+            DeviceFamily::Pedals => write!(f, "pedals"),
+            DeviceFamily::Other => write!(f, "other"),
+        }
+    }
+}
+
+/// Shift report layout a model uses.
+#[derive(Debug, Copy, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ShiftFormat {
+    #[default]
+    Auto,        // Decided from the firmware date (util::determine_report_format)
+    Original,    // 2 byte report
+    New,         // 19 byte report
+    Unsupported, // No shift report; can't be a receiver
+}
+
+/// Whether a device is usually used as a source or a receiver.
+#[derive(Debug, Copy, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum DeviceRole {
+    #[default]
+    Source,
+    Receiver,
+}
+
+impl std::fmt::Display for DeviceRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DeviceRole::Source => write!(f, "source"),
+            DeviceRole::Receiver => write!(f, "receiver"),
+        }
+    }
+}
+
+/// A known Virpil model.
+///
+/// Entries match a device by product ID, or by whole words of its product
+/// string, ignoring case. Users can change both in the VPC Configurator, so
+/// the built-in entries carry the factory PID and product name and config
+/// entries can pin a PID of their own.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CatalogEntry {
+    #[serde(default)]
+    pub product_id: Option<u16>, // Matched before any name pattern
+    #[serde(default)]
+    pub name_pattern: String, // Whole words, e.g. "Throttle MT-50CM3"
+    #[serde(default)]
+    pub family: DeviceFamily,
+    #[serde(default)]
+    pub model: String, // Marketing name shown in the UI and logs
+    #[serde(default)]
+    pub format: ShiftFormat,
+    #[serde(default)]
+    pub role: DeviceRole, // Default role when picking devices for new slots
+}
+
+impl CatalogEntry {
+    fn builtin(product_id: u16, name_pattern: &str, family: DeviceFamily, model: &str, role: DeviceRole) -> Self {
+        Self {
+            product_id: Some(product_id),
+            name_pattern: name_pattern.to_string(),
+            family,
+            model: model.to_string(),
+            format: ShiftFormat::Auto,
+            role,
+        }
+    }
+
+    // For models without a shift report
+    fn unsupported(mut self) -> Self {
+        self.format = ShiftFormat::Unsupported;
+        self
+    }
+
+    // The pattern's words must appear in a row in the product string, so
+    // "ACE" doesn't match "Interface" and "Throttle CM3" not "Throttle CM3X".
+    fn matches_name(&self, product_name: &str) -> bool {
+        let words = |s: &str| -> Vec<String> {
+            s.split(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '#'))
+                .filter(|w| !w.is_empty())
+                .map(str::to_ascii_lowercase)
+                .collect()
+        };
+        let pattern = words(&self.name_pattern);
+        !pattern.is_empty() && words(product_name).windows(pattern.len()).any(|w| w == pattern.as_slice())
+    }
+
+    /// False if the model has no shift report to write to.
+    pub fn can_receive(&self) -> bool {
+        self.format != ShiftFormat::Unsupported
+    }
+}
+
+// e.g. "VPC MongoosT-50CM3 Throttle (throttle, receiver)"
+impl std::fmt::Display for CatalogEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} ({}, {})", self.model, self.family, self.role)
+    }
+}
+
+/// Models known out of the box, with their factory PID and product name.
+///
+/// Devices set up as left-hand ("L-VPC") get another PID and match by name.
+/// Models that aren't listed get no entry rather than a guess from a
+/// generic word like "Panel".
+pub fn builtin_catalog() -> &'static [CatalogEntry] {
+    use DeviceFamily::*;
+    use DeviceRole::*;
+    static CATALOG: OnceLock<Vec<CatalogEntry>> = OnceLock::new();
+    CATALOG.get_or_init(|| {
+        vec![
+            CatalogEntry::builtin(0x8194, "Throttle MT-50CM3", Throttle, "VPC MongoosT-50CM3 Throttle", Receiver),
+            CatalogEntry::builtin(0x8193, "Throttle CM3", Throttle, "VPC Throttle CM3", Receiver),
+            CatalogEntry::builtin(0x40CC, "Stick MT-50CM3", Base, "VPC MongoosT-50CM3 Base", Receiver),
+            CatalogEntry::builtin(0x4130, "Stick MT-50CM2", Base, "VPC MongoosT-50CM2 Base", Receiver),
+            CatalogEntry::builtin(0x40FC, "Stick WarBRD", Base, "VPC WarBRD Base", Receiver),
+            CatalogEntry::builtin(0x8259, "Rotor TCS", Throttle, "VPC Rotor TCS Collective", Receiver),
+            CatalogEntry::builtin(0x825B, "SharKa-50", Panel, "VPC SharKa-50 Control Panel", Source),
+            CatalogEntry::builtin(0x8258, "Control Panel #1", Panel, "VPC Control Panel #1", Source).unsupported(),
+            CatalogEntry::builtin(0x825A, "Control Panel #2", Panel, "VPC Control Panel #2", Source),
+            CatalogEntry::builtin(0x825C, "Control Panel #3", Panel, "VPC Control Panel #3", Source),
+            CatalogEntry::builtin(0x01F8, "ACE-Torq", Pedals, "VPC ACE-Torq Rudder", Receiver),
+            CatalogEntry::builtin(0x4137, "ACE Collection", Pedals, "VPC ACE Collection Pedals", Receiver),
+            // First ACE pedals: older controller without the shift report
+            CatalogEntry::builtin(0x01F6, "ACE Flight", Pedals, "VPC ACE Flight Rudder Pedals", Receiver).unsupported(),
+        ]
+    })
+}
+
+/// Finds the catalog entry for a device.
+///
+/// `extra` (from the config) is searched before the built-in catalog, and a
+/// product ID match anywhere beats a name match.
+pub fn lookup<'a>(extra: &'a [CatalogEntry], product_id: u16, product_name: &str) -> Option<&'a CatalogEntry> {
+    let builtin = builtin_catalog();
+    extra
+        .iter()
+        .chain(builtin)
+        .find(|entry| entry.product_id == Some(product_id))
+        .or_else(|| extra.iter().chain(builtin).find(|entry| entry.matches_name(product_name)))
+}
+
+// Catalog lookups for connected devices
+impl crate::ShiftTool {
+    /// The catalog entry for a connected device, using the config's extra entries.
+    pub(crate) fn catalog_entry(&self, device: &crate::device::VpcDevice) -> Option<&CatalogEntry> {
+        if device.vendor_id == 0 && device.product_id == 0 {
+            return None; // "No connection" entry
+        }
+        lookup(&self.config.data.device_catalog, device.product_id, &device.name)
+    }
+
+    /// The catalog entry for the device a slot points at. Falls back to the
+    /// product ID alone when the device isn't connected.
+    pub(crate) fn slot_catalog_entry(&self, slot: &crate::device::SavedDevice) -> Option<&CatalogEntry> {
+        if slot.is_virtual() {
+            return None;
+        }
+        let idx = crate::device::find_device_index_for_saved(&self.device_list, slot);
+        match self.device_list.get(idx) {
+            Some(device) if idx != 0 => self.catalog_entry(device),
+            _ if slot.product_id != 0 => lookup(&self.config.data.device_catalog, slot.product_id, ""),
+            _ => None,
+        }
+    }
+
+    /// Index of the first connected device whose default role is `role` and
+    /// that no slot uses yet, or 0 ("no connection").
+    pub(crate) fn suggest_device(&self, role: DeviceRole) -> usize {
+        let in_use = |device: &crate::device::VpcDevice| {
+            self.config.data.sources.iter().chain(&self.config.data.receivers).any(|slot| {
+                slot.vendor_id == device.vendor_id
+                    && slot.product_id == device.product_id
+                    && slot.serial_number == device.serial_number
+            })
+        };
+        self.device_list
+            .iter()
+            .enumerate()
+            .skip(1) // "No connection"
+            .find(|(_, device)| {
+                !in_use(device)
+                    && device.unsupported.is_none()
+                    && self.catalog_entry(device).is_some_and(|entry| entry.role == role)
+            })
+            .map_or(0, |(idx, _)| idx)
+    }
+
+    /// A new slot bound to the suggested device for `role`, if there is one.
+    pub(crate) fn suggested_slot(&self, role: DeviceRole) -> crate::device::SavedDevice {
+        let mut slot = crate::device::SavedDevice::default();
+        let idx = self.suggest_device(role);
+        if let Some(device) = self.device_list.get(idx).filter(|_| idx != 0) {
+            slot.vendor_id = device.vendor_id;
+            slot.product_id = device.product_id;
+            slot.serial_number = device.serial_number.clone();
+            log::debug!("Suggested {} for the new {} slot.", device, role);
+        }
+        slot
+    }
+}
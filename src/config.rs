@@ -1,28 +1,199 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::ops::{Index, IndexMut};
 
 // Configuration data saved to JSON
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ConfigData {
+    #[serde(default)] // Missing in files written before versioning (schema 0)
+    pub schema_version: u32,
     #[serde(default)] // Ensure field exists even if missing in JSON
     pub sources: Vec<crate::device::SavedDevice>,
     #[serde(default)]
     pub receivers: Vec<crate::device::SavedDevice>,
     #[serde(default)] // Use default if missing
     pub shift_modifiers: ModifiersArray,
+    #[serde(default)]
+    pub virtual_joystick: VirtualJoystickConfig,
+    #[serde(default)]
+    pub key_bindings: Vec<KeyBinding>,
+    #[serde(default)]
+    pub hooks: Vec<HookConfig>,
+    #[serde(default)]
+    pub profiles: Vec<Profile>,
+    #[serde(default)]
+    pub active_profile: String, // Name of the profile loaded into sources/receivers/rules
+    #[serde(default)]
+    pub default_profile: String, // Profile restored when no game rule matches ("" = keep current)
+    #[serde(default)]
+    pub game_rules: Vec<GameRule>,
+    #[serde(default)]
+    pub control_port: u16, // Localhost TCP port for virtual source commands (0 = disabled)
+    #[serde(default)]
+    pub virtual_hotkeys: Vec<VirtualHotkey>, // Global hotkeys that run virtual source commands
+    #[serde(default)]
+    pub device_nicknames: BTreeMap<String, String>, // Nickname per device, keyed by `device::device_key`
+    #[serde(default)]
+    pub device_catalog: Vec<crate::catalog::CatalogEntry>, // Extra models, matched before the built-in catalog
+    #[serde(default)]
+    pub firmware_policy: FirmwarePolicy,
+    #[serde(default)]
+    pub interface_overrides: BTreeMap<String, crate::device::InterfaceOverride>, // HID interface to open per device, keyed by `device::device_key`
+    #[serde(default)]
+    pub auto_rebind: bool, // Rebind a missing device to its only unused replacement without asking
 }
 
 // Default values for a new configuration
 impl Default for ConfigData {
     fn default() -> Self {
         Self {
+            schema_version: crate::migration::CURRENT_SCHEMA_VERSION,
             sources: vec![], // Start with no sources configured
             receivers: vec![],
             shift_modifiers: ModifiersArray::default(), // Defaults to all OR
+            virtual_joystick: VirtualJoystickConfig::default(),
+            key_bindings: vec![], // No keyboard output by default
+            hooks: vec![],
+            profiles: vec![],
+            active_profile: String::from(""),
+            default_profile: String::from(""),
+            game_rules: vec![],
+            control_port: 0,
+            virtual_hotkeys: vec![],
+            device_nicknames: BTreeMap::new(),
+            device_catalog: vec![],
+            firmware_policy: FirmwarePolicy::default(),
+            interface_overrides: BTreeMap::new(),
+            auto_rebind: false,
         }
     }
 }
 
+impl ConfigData {
+    /// Returns true if `other` differs in anything a running worker can only
+    /// pick up by restarting (devices, slot layout and outputs). Rules and
+    /// masks are pushed to it live instead.
+    pub fn worker_settings_changed(&self, other: &ConfigData) -> bool {
+        // Compare slots with their masks and notes blanked out
+        let devices = |slots: &[crate::device::SavedDevice]| -> Vec<crate::device::SavedDevice> {
+            slots.iter().map(|slot| slot.device_settings()).collect()
+        };
+        devices(&self.sources) != devices(&other.sources)
+            || devices(&self.receivers) != devices(&other.receivers)
+            || self.virtual_joystick != other.virtual_joystick
+            || self.key_bindings != other.key_bindings
+            || self.hooks != other.hooks
+            || self.interface_overrides != other.interface_overrides
+    }
+
+    /// The nickname of the device a slot points at, if it has one.
+    pub fn nickname_for(&self, slot: &crate::device::SavedDevice) -> Option<&str> {
+        self.device_nicknames
+            .get(&slot.device_key())
+            .map(String::as_str)
+            .filter(|nickname| !nickname.is_empty())
+    }
+
+    /// `, 'nickname'` for log messages about a slot, or nothing.
+    pub fn nickname_suffix(&self, slot: &crate::device::SavedDevice) -> String {
+        self.nickname_for(slot)
+            .map(|nickname| format!(", '{}'", nickname))
+            .unwrap_or_default()
+    }
+
+    /// Removes receiver slot `idx`. A virtual joystick mirroring a later slot
+    /// follows it to its new index; one mirroring the removed slot falls back
+    /// to the result.
+    pub fn remove_receiver(&mut self, idx: usize) {
+        if idx >= self.receivers.len() {
+            return;
+        }
+        self.receivers.remove(idx);
+        if let JoystickSource::Receiver(mirrored) = self.virtual_joystick.source {
+            if mirrored == idx {
+                self.virtual_joystick.source = JoystickSource::Result;
+            } else if mirrored > idx {
+                self.virtual_joystick.source = JoystickSource::Receiver(mirrored - 1);
+            }
+        }
+    }
+
+    /// Swaps two receiver slots, keeping the virtual joystick on the same receiver.
+    pub fn swap_receivers(&mut self, a: usize, b: usize) {
+        if a >= self.receivers.len() || b >= self.receivers.len() {
+            return;
+        }
+        self.receivers.swap(a, b);
+        if let JoystickSource::Receiver(mirrored) = self.virtual_joystick.source {
+            if mirrored == a {
+                self.virtual_joystick.source = JoystickSource::Receiver(b);
+            } else if mirrored == b {
+                self.virtual_joystick.source = JoystickSource::Receiver(a);
+            }
+        }
+    }
+
+    /// Saves the current sources, receivers and rules into the named profile,
+    /// creating it if needed.
+    pub fn store_profile(&mut self, name: &str) {
+        let profile = Profile {
+            name: name.to_string(),
+            sources: self.sources.clone(),
+            receivers: self.receivers.clone(),
+            shift_modifiers: self.shift_modifiers,
+        };
+        match self.profiles.iter_mut().find(|p| p.name == name) {
+            Some(existing) => *existing = profile,
+            None => self.profiles.push(profile),
+        }
+    }
+
+    /// Replaces the current sources, receivers and rules with the named profile.
+    /// Stored profiles are left as they are; routing edits only reach a
+    /// profile through `store_profile`. Returns false if no profile with
+    /// that name exists.
+    pub fn load_profile(&mut self, name: &str) -> bool {
+        let profile = match self.profiles.iter().find(|p| p.name == name) {
+            Some(profile) => profile.clone(),
+            None => return false,
+        };
+        self.sources = profile.sources;
+        self.receivers = profile.receivers;
+        self.shift_modifiers = profile.shift_modifiers;
+        self.active_profile = profile.name;
+        true
+    }
+}
+
+// A named set of sources, receivers and rules
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Profile {
+    pub name: String,
+    #[serde(default)]
+    pub sources: Vec<crate::device::SavedDevice>,
+    #[serde(default)]
+    pub receivers: Vec<crate::device::SavedDevice>,
+    #[serde(default)]
+    pub shift_modifiers: ModifiersArray,
+}
+
+// Runs a virtual source `command` (control port syntax, e.g. "toggle 2 DTNT")
+// when the `keys` chord (key binding syntax, e.g. "Ctrl+Shift+F1") is pressed
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct VirtualHotkey {
+    #[serde(default)]
+    pub keys: String,
+    #[serde(default)]
+    pub command: String,
+}
+
+// Switches to `profile` while a process named `executable` is running
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct GameRule {
+    pub executable: String, // e.g. "DCS.exe" (matched case-insensitively, Wine/Proton included)
+    pub profile: String,
+}
+
 // Enum for shift modifier logic
 #[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum ShiftModifiers {
@@ -42,8 +213,201 @@ impl std::fmt::Display for ShiftModifiers {
     }
 }
 
+// Which state the virtual joystick mirrors
+#[derive(Debug, Copy, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum JoystickSource {
+    #[default]
+    Result,          // Combined result of all sources
+    Receiver(usize), // State last sent to the receiver slot with this index
+}
+
+// How the joystick source is displayed in the UI
+impl std::fmt::Display for JoystickSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            JoystickSource::Result => write!(f, "Result"),
+            JoystickSource::Receiver(idx) => write!(f, "Receiver {}", idx + 1),
+        }
+    }
+}
+
+// Settings for the optional uinput virtual joystick output (Linux only)
+#[derive(Debug, Copy, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct VirtualJoystickConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub source: JoystickSource,
+}
+
+/// Which device firmware the tool accepts. `--skip-firmware` bypasses it.
+///
+/// Firmware strings are compared as a whole (ignoring case and surrounding
+/// spaces), e.g. "VIRPIL Controls 20241226".
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FirmwarePolicy {
+    #[serde(default, deserialize_with = "deserialize_minimum_date")]
+    pub minimum_date: String, // Oldest accepted build date, "YYYY-MM-DD" or "YYYYMMDD" ("" = no minimum)
+    #[serde(default)]
+    pub known_good: Vec<String>, // Always accepted, even if older than the minimum
+    #[serde(default)]
+    pub known_bad: Vec<String>, // Never accepted
+}
+
+impl FirmwarePolicy {
+    /// Checks a firmware string against the policy. The error is the reason
+    /// shown next to the device in the UI.
+    ///
+    /// Missing firmware strings and strings without a build date pass, since
+    /// there is nothing to compare.
+    pub fn check(&self, firmware: &crate::util::FirmwareVersion) -> Result<(), String> {
+        let listed = |list: &[String]| list.iter().any(|entry| entry.trim().eq_ignore_ascii_case(&firmware.raw));
+        if listed(&self.known_bad) {
+            return Err(format!("Firmware '{}' is on the known-bad list", firmware.raw));
+        }
+        if listed(&self.known_good) {
+            return Ok(());
+        }
+        // An unreadable date can only be set in code; configs reject it on load
+        let minimum = match crate::util::parse_date_setting(&self.minimum_date) {
+            Some(minimum) => minimum,
+            None => return Ok(()), // No minimum
+        };
+        match firmware.date {
+            Some(date) if date < minimum => Err(format!(
+                "Firmware built {} is older than the minimum {}",
+                date, minimum
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+// "" or a date `util::parse_date_setting` reads; a config with anything else
+// is rejected instead of warning on every firmware check
+fn deserialize_minimum_date<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let date = String::deserialize(deserializer)?;
+    if !date.trim().is_empty() && crate::util::parse_date_setting(&date).is_none() {
+        return Err(serde::de::Error::custom(format!(
+            "firmware minimum_date '{}' is not a date (YYYY-MM-DD or YYYYMMDD)",
+            date
+        )));
+    }
+    Ok(date)
+}
+
+// Which change of a result bit triggers a key binding
+#[derive(Debug, Copy, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum BitEdge {
+    #[default]
+    Rising,  // Bit goes from 0 to 1
+    Falling, // Bit goes from 1 to 0
+}
+
+// How the edge is displayed in the UI
+impl std::fmt::Display for BitEdge {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BitEdge::Rising => write!(f, "goes high"),
+            BitEdge::Falling => write!(f, "goes low"),
+        }
+    }
+}
+
+// Keys sent through the uinput virtual keyboard when a result bit changes
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct KeyBinding {
+    #[serde(deserialize_with = "deserialize_shift_bit")]
+    pub bit: u8, // Result bit position (0-7)
+    #[serde(default)]
+    pub edge: BitEdge,
+    // Chords separated by spaces, keys in a chord joined with '+'.
+    // e.g. "Ctrl+F3" or the macro "Esc Ctrl+S"
+    #[serde(default)]
+    pub keys: String,
+}
+
+// One of the 8 result bits the worker computes; a config naming any other is
+// rejected, since a binding on it would never fire
+fn deserialize_shift_bit<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error> {
+    let bit = u8::deserialize(deserializer)?;
+    if bit >= 8 {
+        return Err(serde::de::Error::custom(format!("key binding bit {} is out of range (0-7)", bit)));
+    }
+    Ok(bit)
+}
+
+impl Default for KeyBinding {
+    fn default() -> Self {
+        Self {
+            bit: 0,
+            edge: BitEdge::Rising,
+            keys: String::from(""),
+        }
+    }
+}
+
+// Events that can trigger a user hook command
+#[derive(Debug, Copy, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum HookEvent {
+    #[default]
+    ResultChanged,   // The combined result state changed
+    SourceOffline,   // A source device stopped responding
+    ReceiverOffline, // A receiver device stopped responding
+    WorkerStarted,   // The worker thread opened its devices
+    WorkerStopped,   // The worker thread finished cleanup
+}
+
+impl HookEvent {
+    pub const ALL: [HookEvent; 5] = [
+        HookEvent::ResultChanged,
+        HookEvent::SourceOffline,
+        HookEvent::ReceiverOffline,
+        HookEvent::WorkerStarted,
+        HookEvent::WorkerStopped,
+    ];
+}
+
+// How the event is displayed in the UI and passed to hook commands
+impl std::fmt::Display for HookEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            HookEvent::ResultChanged => write!(f, "result_changed"),
+            HookEvent::SourceOffline => write!(f, "source_offline"),
+            HookEvent::ReceiverOffline => write!(f, "receiver_offline"),
+            HookEvent::WorkerStarted => write!(f, "worker_started"),
+            HookEvent::WorkerStopped => write!(f, "worker_stopped"),
+        }
+    }
+}
+
+fn default_hook_timeout_ms() -> u64 {
+    5000
+}
+
+// A shell command run when an event happens
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HookConfig {
+    #[serde(default)]
+    pub event: HookEvent,
+    #[serde(default)]
+    pub command: String,
+    #[serde(default = "default_hook_timeout_ms")]
+    pub timeout_ms: u64, // Command is killed if it runs longer than this
+}
+
+impl Default for HookConfig {
+    fn default() -> Self {
+        Self {
+            event: HookEvent::ResultChanged,
+            command: String::from(""),
+            timeout_ms: default_hook_timeout_ms(),
+        }
+    }
+}
+
 // Wrapper for the array of modifiers to implement Default and Indexing
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct ModifiersArray {
     data: [ShiftModifiers; 8],
 }
@@ -0,0 +1,591 @@
+use crate::config::ConfigData;
+use crate::migration;
+use fast_config::{Config, ConfigFormat, InternalOptions};
+use log::{debug, error, info, warn};
+use serde_json::Value;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// File name of the config in every location it is looked for.
+pub const CONFIG_FILE_NAME: &str = "shift_tool.json";
+
+/// Environment variable that overrides the config path (below `--config`).
+pub const CONFIG_ENV_VAR: &str = "SHIFT_TOOL_CONFIG";
+
+/// System-wide base config, overlaid by each user's own file.
+#[cfg(unix)]
+pub const SYSTEM_CONFIG_PATH: &str = "/etc/shift_tool/shift_tool.json";
+
+/// How many timestamped backups of the config file are kept.
+pub const MAX_CONFIG_BACKUPS: usize = 5;
+
+// How often the config file is checked for outside edits
+const CONFIG_POLL_MS: u64 = 1000;
+
+// Timestamp used in rolling backup names, e.g. shift_tool.json.20250101-120000-123.bak
+const BACKUP_TIMESTAMP_FORMAT: &str = "%Y%m%d-%H%M%S-%3f";
+
+/// A config file that failed to parse.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigParseError {
+    pub message: String,
+    pub line: Option<usize>,   // 1-based, when the parser reported a position
+    pub column: Option<usize>, // 1-based
+}
+
+impl std::fmt::Display for ConfigParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => write!(f, "line {}, column {}: {}", line, column, self.message),
+            (Some(line), None) => write!(f, "line {}: {}", line, self.message),
+            _ => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// What happened when the config on disk could not be loaded.
+///
+/// Shown as an error banner in the UI until dismissed.
+#[derive(Debug, Clone)]
+pub struct ConfigRecovery {
+    pub error: ConfigParseError,
+    pub moved_to: Option<PathBuf>,      // Where the broken file was moved (None = left in place, nothing restored)
+    pub restored_from: Option<PathBuf>, // Backup loaded instead (None = defaults)
+}
+
+impl std::fmt::Display for ConfigRecovery {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "The configuration file could not be read ({}).", self.error)?;
+        if let Some(moved_to) = &self.moved_to {
+            write!(f, " It was moved to {}.", moved_to.display())?;
+        }
+        if self.left_in_place() {
+            write!(f, " It could not be moved aside, so it is left untouched and nothing is saved until it is fixed or removed.")?;
+        }
+        match &self.restored_from {
+            Some(backup) => write!(f, " Settings were restored from {}.", backup.display()),
+            None => write!(f, " Starting with default settings."),
+        }
+    }
+}
+
+impl ConfigRecovery {
+    /// True when the broken file is still at the config path. Saving would
+    /// overwrite the only copy of the user's settings, so saves are refused.
+    pub fn left_in_place(&self) -> bool {
+        self.moved_to.is_none()
+    }
+}
+
+// File name of the config, used as the prefix for backups and temp files
+fn config_file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "shift_tool.json".to_string())
+}
+
+/// Checks that `text` is a config this build can load.
+pub fn validate_config_text(text: &str) -> Result<(), ConfigParseError> {
+    match json5::from_str::<ConfigData>(text) {
+        Ok(_) => Ok(()),
+        Err(json5::Error::Message { msg, location }) => Err(ConfigParseError {
+            message: short_parse_message(&msg),
+            line: location.as_ref().map(|l| l.line),
+            column: location.as_ref().map(|l| l.column),
+        }),
+    }
+}
+
+// Syntax errors come with a multi-line source excerpt; keep only the
+// "expected ..." line for the banner.
+fn short_parse_message(msg: &str) -> String {
+    msg.lines()
+        .map(str::trim)
+        .find_map(|line| line.strip_prefix("= "))
+        .unwrap_or_else(|| msg.trim())
+        .to_string()
+}
+
+/// Backups of the config at `path` (`<file name>.*.bak`), newest first.
+pub fn backup_files(path: &Path) -> Vec<PathBuf> {
+    let (dir, file_name) = match (path.parent(), path.file_name()) {
+        (Some(dir), Some(name)) => (dir, name.to_string_lossy().into_owned()),
+        _ => return vec![],
+    };
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    let prefix = format!("{}.", file_name);
+
+    let mut backups: Vec<(std::time::SystemTime, PathBuf)> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .flatten()
+            .filter(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                name.starts_with(&prefix) && name.ends_with(".bak")
+            })
+            .map(|entry| {
+                let modified = entry
+                    .metadata()
+                    .and_then(|m| m.modified())
+                    .unwrap_or(std::time::UNIX_EPOCH);
+                (modified, entry.path())
+            })
+            .collect(),
+        Err(_) => return vec![],
+    };
+    backups.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| b.1.cmp(&a.1)));
+    backups.into_iter().map(|(_, path)| path).collect()
+}
+
+/// Makes sure the file at `path` can be loaded, recovering it if not.
+///
+/// A file that fails to parse is renamed to `<file name>.broken-<timestamp>`
+/// and replaced with the newest backup that still loads. If there is none,
+/// the file is simply left missing so the tool starts with defaults.
+/// Returns `None` when the file was fine (or didn't exist yet).
+pub fn recover_config_file(path: &Path) -> Option<ConfigRecovery> {
+    let text = match fs::read(path) {
+        Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+        Err(_) => return None, // Missing files are created with defaults
+    };
+    let parse_error = validate_config_text(&text).err()?;
+    error!("Config file {} is invalid: {}", path.display(), parse_error);
+
+    let timestamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    let file_name = config_file_name(path);
+    let broken_path = path.with_file_name(format!("{}.broken-{}", file_name, timestamp));
+    let moved_to = match fs::rename(path, &broken_path) {
+        Ok(()) => {
+            warn!("Moved broken config file to {}.", broken_path.display());
+            Some(broken_path)
+        }
+        Err(e) => {
+            // Copying a backup over it now would lose the only copy of the user's settings
+            error!("Failed to move broken config file aside: {}. Leaving it in place.", e);
+            return Some(ConfigRecovery { error: parse_error, moved_to: None, restored_from: None });
+        }
+    };
+
+    let restored_from = backup_files(path).into_iter().find(|backup| {
+        let valid = fs::read_to_string(backup)
+            .map(|text| validate_config_text(&text).is_ok())
+            .unwrap_or(false);
+        valid && fs::copy(backup, path).is_ok()
+    });
+    match &restored_from {
+        Some(backup) => info!("Restored config from backup {}.", backup.display()),
+        None => warn!("No usable config backup found. Starting with defaults."),
+    }
+
+    Some(ConfigRecovery {
+        error: parse_error,
+        moved_to,
+        restored_from,
+    })
+}
+
+/// A loaded config together with what it was built from.
+pub struct LoadedConfig {
+    pub config: Config<ConfigData>,
+    pub base: Option<Value>,                 // System-wide layer under the user file, if any
+    pub recovery: Option<ConfigRecovery>, // Set when the user file had to be replaced
+}
+
+/// Picks the config file to use.
+///
+/// In order: the `--config` argument, `env_path` (the value of
+/// `SHIFT_TOOL_CONFIG`), a `shift_tool.json` next to the executable (portable
+/// mode), and finally the user config directory.
+pub fn resolve_config_path(cli_path: Option<&Path>, env_path: Option<std::ffi::OsString>) -> PathBuf {
+    if let Some(path) = cli_path {
+        info!("Using config {} (--config).", path.display());
+        return path.to_path_buf();
+    }
+    if let Some(path) = env_path.filter(|p| !p.is_empty()) {
+        let path = PathBuf::from(path);
+        info!("Using config {} ({}).", path.display(), CONFIG_ENV_VAR);
+        return path;
+    }
+    let portable_path = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join(CONFIG_FILE_NAME)));
+    if let Some(path) = portable_path.filter(|p| p.is_file()) {
+        info!("Using config {} (portable mode).", path.display());
+        return path;
+    }
+    let config_dir = dirs::config_dir().unwrap_or_else(|| PathBuf::from(".")); // Fallback to current dir
+    config_dir.join(CONFIG_FILE_NAME)
+}
+
+/// Merges `overlay` into `base`. Objects are merged key by key, everything
+/// else (including lists) is replaced by the overlay's value.
+pub fn merge_layers(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base_map), Value::Object(overlay_map)) => {
+            for (key, value) in overlay_map {
+                match base_map.get_mut(&key) {
+                    Some(existing) => merge_layers(existing, value),
+                    None => {
+                        base_map.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// The inverse of `merge_layers`: drops everything in `value` that the base
+/// already provides, so only the user's own changes are saved.
+pub fn strip_base(value: Value, base: &Value) -> Value {
+    // Compare against the base with every field filled in, so fields added
+    // after the base was written don't make its lists look changed
+    match serde_json::from_value::<ConfigData>(base.clone()).and_then(serde_json::to_value) {
+        Ok(full_base) => strip_layer(value, &full_base),
+        Err(_) => strip_layer(value, base),
+    }
+}
+
+fn strip_layer(value: Value, base: &Value) -> Value {
+    match (value, base) {
+        (Value::Object(map), Value::Object(base_map)) => Value::Object(
+            map.into_iter()
+                .filter_map(|(key, value)| match base_map.get(&key) {
+                    Some(base_value) if *base_value == value && key != "schema_version" => None,
+                    Some(base_value @ Value::Object(_)) if value.is_object() => {
+                        Some((key, strip_layer(value, base_value)))
+                    }
+                    _ => Some((key, value)),
+                })
+                .collect(),
+        ),
+        (value, _) => value,
+    }
+}
+
+// Reads a config layer as JSON, migrated to the current schema
+fn read_layer(path: &Path) -> Result<Value, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    validate_config_text(&text).map_err(|e| e.to_string())?;
+
+    let mut value: Value = json5::from_str(&text).map_err(|e| e.to_string())?;
+    migration::migrate_value(&mut value)?;
+    Ok(value)
+}
+
+/// Reads the system-wide base config. A broken base is logged and ignored.
+pub fn load_system_base() -> Option<Value> {
+    #[cfg(unix)]
+    {
+        let path = Path::new(SYSTEM_CONFIG_PATH);
+        if !path.is_file() {
+            return None;
+        }
+        match read_layer(path) {
+            Ok(base) => {
+                info!("Using system-wide base config {}.", path.display());
+                Some(base)
+            }
+            Err(e) => {
+                error!("Ignoring system-wide config {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        None
+    }
+}
+
+/// Builds the config data for a user layer on top of `base`.
+pub fn apply_base(base: &Value, user: Value) -> Result<ConfigData, String> {
+    let mut merged = base.clone();
+    merge_layers(&mut merged, user);
+    serde_json::from_value(merged).map_err(|e| e.to_string())
+}
+
+/// Loads the config at `path`: recovers a broken file, migrates older
+/// schemas, opens it with `fast_config` and lays it over the system base.
+///
+/// Never fails because of the file's contents; `recovery` describes any
+/// recovery that took place so the UI can tell the user. A broken file that
+/// can't be moved aside is neither read nor written: the config starts from
+/// defaults (see `ConfigRecovery::left_in_place`).
+pub fn load_config(path: &Path) -> Result<LoadedConfig, String> {
+    let recovery = recover_config_file(path);
+    let left_in_place = recovery.as_ref().is_some_and(ConfigRecovery::left_in_place);
+
+    let mut config = if left_in_place {
+        // fast_config would try to parse the broken file, so build the config directly
+        Config {
+            data: ConfigData::default(),
+            path: path.to_path_buf(),
+            options: InternalOptions { pretty: true, format: ConfigFormat::JSON5, save_on_drop: false },
+        }
+    } else {
+        // Bring older config files up to the current schema before loading
+        if let Err(e) = migration::migrate_config_file(path) {
+            error!("Error migrating config file at {}: {}", path.display(), e);
+        }
+        Config::new(path, ConfigData::default())
+            .map_err(|e| format!("Error creating config file at {}: {}", path.display(), e))?
+    };
+
+    let base = load_system_base();
+    if let Some(base) = &base {
+        let user = if path.is_file() && !left_in_place {
+            read_layer(path)?
+        } else {
+            Value::Object(Default::default())
+        };
+        match apply_base(base, user) {
+            Ok(data) => config.data = data,
+            Err(e) => error!("Failed to apply system-wide base config: {}", e),
+        }
+    }
+
+    Ok(LoadedConfig {
+        config,
+        base,
+        recovery,
+    })
+}
+
+// Rolling backups carry a timestamp; migration backups (".v0.bak") are kept forever
+fn is_rolling_backup(path: &Path, config_file_name: &str) -> bool {
+    let name = match path.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => return false,
+    };
+    name.strip_prefix(config_file_name)
+        .and_then(|rest| rest.strip_prefix('.'))
+        .and_then(|rest| rest.strip_suffix(".bak"))
+        .is_some_and(|stamp| !stamp.is_empty() && stamp.bytes().all(|b| b.is_ascii_digit() || b == b'-'))
+}
+
+/// Writes `contents` to `path` without ever leaving a truncated file behind.
+///
+/// The data goes to a temporary file in the same directory, is fsynced, and
+/// then renamed over the target, so readers see either the old or the new file.
+pub fn write_atomic(path: &Path, contents: &str) -> std::io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(dir)?;
+
+    let file_name = config_file_name(path);
+    let temp_path = dir.join(format!(".{}.tmp", file_name));
+
+    let result = (|| {
+        let mut file = fs::File::create(&temp_path)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temp_path, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path); // Don't leave partial temp files around
+        return result;
+    }
+
+    // Persist the rename itself. Not supported on every platform, so best effort.
+    #[cfg(unix)]
+    if let Ok(dir_handle) = fs::File::open(dir) {
+        let _ = dir_handle.sync_all();
+    }
+    Ok(())
+}
+
+/// Copies the current file at `path` to a timestamped backup and prunes
+/// rolling backups beyond `MAX_CONFIG_BACKUPS`.
+fn rotate_backups(path: &Path) -> std::io::Result<()> {
+    let file_name = config_file_name(path);
+    let timestamp = chrono::Local::now().format(BACKUP_TIMESTAMP_FORMAT);
+    let backup_path = path.with_file_name(format!("{}.{}.bak", file_name, timestamp));
+    fs::copy(path, &backup_path)?;
+    debug!("Backed up config to {}.", backup_path.display());
+
+    let rolling: Vec<PathBuf> = backup_files(path)
+        .into_iter()
+        .filter(|backup| is_rolling_backup(backup, &file_name))
+        .collect();
+    for old_backup in rolling.iter().skip(MAX_CONFIG_BACKUPS) {
+        if let Err(e) = fs::remove_file(old_backup) {
+            warn!("Failed to remove old config backup {}: {}", old_backup.display(), e);
+        }
+    }
+    Ok(())
+}
+
+/// Saves `data` to `path` atomically, keeping the previous file as a backup.
+///
+/// With a `base` layer only the settings that differ from it are written.
+/// No backup is made when the contents haven't changed, so repeated Saves
+/// with unchanged data don't push older versions out of the rotation.
+pub fn save_config_data(path: &Path, data: &ConfigData, base: Option<&Value>) -> Result<(), String> {
+    let mut value = serde_json::to_value(data).map_err(|e| format!("Failed to serialize config: {}", e))?;
+    if let Some(base) = base {
+        value = strip_base(value, base);
+    }
+    let contents = serde_json::to_string_pretty(&value)
+        .map_err(|e| format!("Failed to serialize config: {}", e))?;
+
+    match fs::read_to_string(path) {
+        Ok(current) if current == contents => return Ok(()),
+        Ok(_) => {
+            if let Err(e) = rotate_backups(path) {
+                warn!("Failed to back up config before saving: {}", e);
+            }
+        }
+        Err(_) => {} // Nothing to back up yet
+    }
+
+    write_atomic(path, &contents).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Saves the config to its own path. Replaces `Config::save`, which truncates
+/// the file in place.
+pub fn save_config(config: &Config<ConfigData>, base: Option<&Value>) -> Result<(), String> {
+    save_config_data(&config.path, &config.data, base)
+}
+
+/// Reads a config file or backup, migrating it to the current schema and
+/// laying it over `base`.
+pub fn read_config_file(path: &Path, base: Option<&Value>) -> Result<ConfigData, String> {
+    let value = read_layer(path)?;
+    match base {
+        Some(base) => apply_base(base, value),
+        None => serde_json::from_value(value).map_err(|e| e.to_string()),
+    }
+}
+
+/// Replaces the loaded config with a backup and saves it.
+///
+/// The config being replaced is itself backed up first, so a restore can be undone.
+pub fn restore_backup(config: &mut Config<ConfigData>, base: Option<&Value>, backup: &Path) -> Result<(), String> {
+    let data = read_config_file(backup, base)?;
+    save_config_data(&config.path, &data, base)?;
+    config.data = data;
+    info!("Restored config from {}.", backup.display());
+    Ok(())
+}
+
+// Modification time and size, used to notice edits without reading the file
+fn file_stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Background thread that notices when the config file changes on disk.
+///
+/// It only flags the change; reading, validating and applying the new file
+/// happens on the UI thread in `ShiftTool::poll_config_reload`.
+#[derive(Default)]
+pub struct ConfigWatcher {
+    changed: Arc<Mutex<bool>>,
+    started: bool,
+}
+
+impl ConfigWatcher {
+    /// Starts watching `path`. Calling it again is a no-op.
+    pub fn start(&mut self, path: &Path) {
+        if self.started {
+            return;
+        }
+        self.started = true;
+
+        let path = path.to_path_buf();
+        let changed = self.changed.clone();
+        let mut last_stamp = file_stamp(&path);
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_millis(CONFIG_POLL_MS));
+            let stamp = file_stamp(&path);
+            if stamp != last_stamp {
+                debug!("Config file {} changed on disk.", path.display());
+                last_stamp = stamp;
+                if let Ok(mut guard) = changed.lock() {
+                    *guard = true;
+                }
+            }
+        });
+        info!("Watching config file for changes.");
+    }
+
+    /// Returns true once for every detected change.
+    pub fn take_change(&self) -> bool {
+        match self.changed.lock() {
+            Ok(mut guard) => std::mem::take(&mut *guard),
+            Err(_) => false,
+        }
+    }
+}
+
+// Hot reload of the config file
+impl crate::ShiftTool {
+    /// Applies outside edits of the config file. Called once per UI frame.
+    ///
+    /// Invalid edits are rejected and shown in the error banner; the running
+    /// config stays untouched. A running worker is only restarted when the
+    /// settings it copies at start changed.
+    pub(crate) fn poll_config_reload(&mut self) {
+        if !self.config_watcher.take_change() {
+            return;
+        }
+
+        if self.config_left_broken() && !self.config.path.exists() {
+            // The user removed the broken file, so saving no longer overwrites it
+            info!("{} was removed; saving is enabled.", self.config.path.display());
+            self.config_recovery = None;
+            return;
+        }
+        let data = match read_config_file(&self.config.path, self.config_base.as_ref()) {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("Rejected edit of {}: {}", self.config.path.display(), e);
+                self.config_reload_error = Some(e);
+                return;
+            }
+        };
+        self.config_reload_error = None;
+        if self.config_left_broken() {
+            info!("{} can be read again; saving is enabled.", self.config.path.display());
+            self.config_recovery = None;
+        }
+        if data == self.saved_data {
+            return; // Our own save, or an edit that changed nothing
+        }
+        if self.has_unsaved_changes() {
+            // Don't throw away edits made in the UI; Revert picks up the file instead
+            warn!("{} changed on disk while there are unsaved changes.", self.config.path.display());
+            self.config_reload_error =
+                Some("there are unsaved changes here; Save overwrites the file, Revert loads it".to_string());
+            self.saved_data = data;
+            return;
+        }
+
+        let restart_needed = data.worker_settings_changed(&self.config.data);
+        if data.control_port != self.config.data.control_port {
+            info!("Control port changes take effect after restarting the tool.");
+        }
+        let hotkeys_changed = data.virtual_hotkeys != self.config.data.virtual_hotkeys;
+        self.config.data = data.clone();
+        self.saved_data = data;
+        info!("Reloaded config from {}.", self.config.path.display());
+
+        // Keep per-slot state tracking and game rules in step with the new config
+        self.sync_slot_states();
+        self.process_watcher.set_rules(self.config.data.game_rules.clone());
+        if hotkeys_changed {
+            self.hotkeys.start(&self.config.data.virtual_hotkeys);
+        }
+
+        if restart_needed {
+            self.restart_worker(); // No-op while stopped
+        }
+    }
+}
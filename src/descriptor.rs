@@ -0,0 +1,193 @@
+use hidapi::HidDevice;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// Largest report descriptor hidapi hands out (HID_API_MAX_REPORT_DESCRIPTOR_SIZE).
+pub const MAX_DESCRIPTOR_SIZE: usize = 4096;
+
+/// Descriptors read so far, keyed by `device::device_key`. The worker fills
+/// it when it opens a device; the details window can also read one on demand.
+pub type SharedDescriptors = Arc<Mutex<BTreeMap<String, Result<ReportDescriptor, String>>>>;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ReportKind {
+    Input,
+    Output,
+    Feature,
+}
+
+impl std::fmt::Display for ReportKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ReportKind::Input => write!(f, "input"),
+            ReportKind::Output => write!(f, "output"),
+            ReportKind::Feature => write!(f, "feature"),
+        }
+    }
+}
+
+/// One report declared by a descriptor, with all its fields added up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportInfo {
+    pub kind: ReportKind,
+    pub report_id: u8, // 0 if the descriptor uses no report IDs
+    pub bits: u32,     // Payload size, without the report ID byte
+}
+
+impl ReportInfo {
+    /// Size in bytes as sent over hidapi, including the report ID byte.
+    pub fn size(&self) -> usize {
+        self.bits.div_ceil(8) as usize + usize::from(self.report_id != 0)
+    }
+}
+
+// e.g. "feature report 4: 19 bytes"
+impl std::fmt::Display for ReportInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} report {}: {} bytes", self.kind, self.report_id, self.size())
+    }
+}
+
+/// The parts of a HID report descriptor the tool cares about: which reports
+/// exist and how long they are, and the top-level collections.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReportDescriptor {
+    pub raw: Vec<u8>,
+    pub reports: Vec<ReportInfo>,
+    pub collections: Vec<(u16, u16)>, // Usage page and usage of each application collection
+}
+
+// Global items that Push/Pop save and restore
+#[derive(Debug, Copy, Clone, Default)]
+struct GlobalState {
+    usage_page: u16,
+    report_size: u32,
+    report_count: u32,
+    report_id: u8,
+}
+
+impl ReportDescriptor {
+    /// Parses the short items of a descriptor (HID 1.11, section 6.2.2).
+    /// Long items are skipped; a truncated item is an error.
+    pub fn parse(raw: &[u8]) -> Result<Self, String> {
+        let mut descriptor = ReportDescriptor { raw: raw.to_vec(), ..Default::default() };
+        let mut global = GlobalState::default();
+        let mut stack: Vec<GlobalState> = Vec::new();
+        let mut usage: Option<(u32, bool)> = None; // Last local usage and whether it was extended (page included), for collections
+        let mut depth = 0usize;
+        let mut pos = 0usize;
+
+        while pos < raw.len() {
+            let prefix = raw[pos];
+            if prefix == 0xFE {
+                // Long item: size byte, tag byte, data
+                let size = *raw.get(pos + 1).ok_or("Truncated long item")? as usize;
+                pos += 3 + size;
+                continue;
+            }
+            let size = match prefix & 0x03 {
+                3 => 4,
+                n => n as usize,
+            };
+            let data = raw
+                .get(pos + 1..pos + 1 + size)
+                .ok_or_else(|| format!("Truncated item at byte {}", pos))?;
+            let value = data.iter().rev().fold(0u32, |acc, &b| (acc << 8) | b as u32);
+            let item_type = (prefix >> 2) & 0x03;
+            let tag = prefix >> 4;
+            pos += 1 + size;
+
+            match (item_type, tag) {
+                // Main items
+                (0, 0x8) | (0, 0x9) | (0, 0xB) => {
+                    let kind = match tag {
+                        0x8 => ReportKind::Input,
+                        0x9 => ReportKind::Output,
+                        _ => ReportKind::Feature,
+                    };
+                    descriptor.add_bits(kind, global.report_id, global.report_size * global.report_count);
+                    usage = None;
+                }
+                (0, 0xA) => {
+                    // Collection; 1 = application
+                    if depth == 0 && value == 1 {
+                        let (full, extended) = usage.unwrap_or((0, false));
+                        let page = if extended { (full >> 16) as u16 } else { global.usage_page };
+                        descriptor.collections.push((page, full as u16));
+                    }
+                    depth += 1;
+                    usage = None;
+                }
+                (0, 0xC) => depth = depth.saturating_sub(1), // End Collection
+                // Global items
+                (1, 0x0) => global.usage_page = value as u16,
+                (1, 0x7) => global.report_size = value,
+                (1, 0x8) => global.report_id = value as u8,
+                (1, 0x9) => global.report_count = value,
+                (1, 0xA) => stack.push(global),
+                (1, 0xB) => global = stack.pop().ok_or("Pop without Push")?,
+                // Local items
+                (2, 0x0) => usage = Some(if size == 4 { (value, true) } else { (value & 0xFFFF, false) }),
+                _ => {}
+            }
+        }
+        Ok(descriptor)
+    }
+
+    fn add_bits(&mut self, kind: ReportKind, report_id: u8, bits: u32) {
+        match self.reports.iter_mut().find(|r| r.kind == kind && r.report_id == report_id) {
+            Some(report) => report.bits += bits,
+            None => self.reports.push(ReportInfo { kind, report_id, bits }),
+        }
+    }
+
+    pub fn report(&self, kind: ReportKind, report_id: u8) -> Option<&ReportInfo> {
+        self.reports.iter().find(|r| r.kind == kind && r.report_id == report_id)
+    }
+
+    /// The feature report the shift state is read from and written to.
+    pub fn shift_report(&self) -> Option<&ReportInfo> {
+        self.report(ReportKind::Feature, crate::util::FEATURE_REPORT_ID_SHIFT)
+    }
+
+    /// The raw descriptor as hex, 16 bytes per line.
+    pub fn hex_dump(&self) -> String {
+        self.raw
+            .chunks(16)
+            .map(|line| line.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" "))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Reads and parses the report descriptor of an open device.
+pub fn read_descriptor(device: &HidDevice) -> Result<ReportDescriptor, String> {
+    let mut buffer = [0u8; MAX_DESCRIPTOR_SIZE];
+    let len = device
+        .get_report_descriptor(&mut buffer)
+        .map_err(|e| format!("Failed to read the report descriptor: {}", e))?;
+    ReportDescriptor::parse(&buffer[..len])
+}
+
+// Descriptors for the device details window
+impl crate::ShiftTool {
+    /// The descriptor read for a device, if any.
+    pub(crate) fn cached_descriptor(&self, device_key: &str) -> Option<Result<ReportDescriptor, String>> {
+        self.descriptors.lock().ok()?.get(device_key).cloned()
+    }
+
+    /// Opens a listed device just long enough to read its descriptor.
+    pub(crate) fn read_descriptor_now(&mut self, device_idx: usize) {
+        let device = match self.device_list.get(device_idx).filter(|_| device_idx != 0) {
+            Some(device) => device,
+            None => return,
+        };
+        let result = device.open().and_then(|opened| read_descriptor(&opened));
+        if let Err(e) = &result {
+            log::warn!("Couldn't read the descriptor of {}: {}", device, e);
+        }
+        if let Ok(mut descriptors) = self.descriptors.lock() {
+            descriptors.insert(device.full_name.clone(), result);
+        }
+    }
+}
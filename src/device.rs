@@ -1,6 +1,10 @@
-use hidapi::{DeviceInfo, HidApi};
-use log::{error, warn, debug, trace}; // Use log crate
+use hidapi::{DeviceInfo, HidApi, HidDevice};
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
+use std::sync::{Arc, Mutex};
+use log::{error, warn, debug, info, trace}; // Use log crate
 use serde::{Deserialize, Serialize};
+use crate::util::FirmwareVersion;
 use std::rc::Rc;
 
 // Represents a discovered VPC device
@@ -8,12 +12,16 @@ use std::rc::Rc;
 pub struct VpcDevice {
     pub full_name: String, // Combined identifier
     pub name: Rc<String>,  // Product String
-    pub firmware: Rc<String>, // Manufacturer String (often firmware version)
+    pub firmware: FirmwareVersion, // Parsed manufacturer string (firmware vendor and build date)
     pub vendor_id: u16,
     pub product_id: u16,
     pub serial_number: String,
-    pub usage: u16, // HID usage page/id (less commonly needed for opening)
+    pub usage: u16, // HID usage of the chosen interface
+    pub interfaces: Vec<HidInterface>, // Every interface (top-level collection) hidapi lists for the device
+    pub interface: usize, // Index into `interfaces` of the one the worker opens
     pub active: bool, // Is the worker thread currently connected?
+    pub nickname: String, // User-assigned name from the config ("" = none)
+    pub unsupported: Option<String>, // Why the firmware policy rejects the device (None = supported)
 }
 
 impl Default for VpcDevice {
@@ -21,12 +29,16 @@ impl Default for VpcDevice {
         Self {
             full_name: String::from(""),
             name: String::from("-NO CONNECTION (Select device from list)-").into(),
-            firmware: String::from("").into(),
+            firmware: FirmwareVersion::default(),
             vendor_id: 0,
             product_id: 0,
             serial_number: String::from(""),
             usage: 0,
+            interfaces: vec![],
+            interface: 0,
             active: false,
+            nickname: String::from(""),
+            unsupported: None,
         }
     }
 }
@@ -37,6 +49,15 @@ impl std::fmt::Display for VpcDevice {
         if self.vendor_id == 0 && self.product_id == 0 {
             // Default/placeholder entry
             write!(f, "{}", self.name)
+        } else if !self.nickname.is_empty() {
+            // The nickname tells devices of the same model apart; keep the rest short
+            write!(
+                f,
+                "{} ({}, SN:{})",
+                self.nickname,
+                self.name,
+                if self.serial_number.is_empty() { "N/A" } else { &self.serial_number }
+            )
         } else {
             write!(
                 f,
@@ -45,19 +66,265 @@ impl std::fmt::Display for VpcDevice {
                 self.product_id,
                 self.name,
                 if self.serial_number.is_empty() { "N/A" } else { &self.serial_number },
-                if self.firmware.is_empty() { "N/A" } else { &self.firmware }
+                if self.firmware.raw.is_empty() { "N/A".to_string() } else { self.firmware.to_string() }
             )
         }
     }
 }
 
+impl VpcDevice {
+    /// The interface the worker opens, if hidapi listed any.
+    pub fn chosen_interface(&self) -> Option<&HidInterface> {
+        self.interfaces.get(self.interface)
+    }
+
+    /// Opens the device outside the worker, through the chosen interface if
+    /// there is one (for the details window).
+    pub(crate) fn open(&self) -> Result<HidDevice, String> {
+        let hidapi = HidApi::new().map_err(|e| format!("Failed to initialize HidApi: {}", e))?;
+        let opened = match self.chosen_interface() {
+            Some(interface) => hidapi.open_path(&interface.path),
+            None => hidapi.open_serial(self.vendor_id, self.product_id, &self.serial_number),
+        };
+        opened.map_err(|e| format!("Failed to open the device: {}", e))
+    }
+}
+
+/// One HID interface of a device. Composite devices (e.g. with keyboard
+/// emulation) list several, and only one carries the shift feature report.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HidInterface {
+    pub path: CString, // Platform path for HidApi::open_path
+    pub usage_page: u16,
+    pub usage: u16,
+    pub interface_number: i32, // -1 if the platform doesn't report it
+    pub shift_report: Option<bool>, // Whether its descriptor declares the shift report (None = not read)
+}
+
+impl HidInterface {
+    fn from_info(device_info: &DeviceInfo) -> Self {
+        Self {
+            path: device_info.path().to_owned(),
+            usage_page: device_info.usage_page(),
+            usage: device_info.usage(),
+            interface_number: device_info.interface_number(),
+            shift_report: None, // Filled in by refresh_devices
+        }
+    }
+
+    /// What the interface is, from its usage page and usage.
+    pub fn kind(&self) -> &'static str {
+        match (self.usage_page, self.usage) {
+            (0x01, 0x04) => "joystick",
+            (0x01, 0x05) => "gamepad",
+            (0x01, 0x08) => "multi-axis controller",
+            (0x01, 0x06) => "keyboard",
+            (0x01, 0x02) => "mouse",
+            (0x0C, _) => "consumer control",
+            (page, _) if page >= 0xFF00 => "vendor-defined",
+            _ => "other",
+        }
+    }
+
+    // Lower is more likely to carry the shift report. What the descriptor
+    // says comes first; the usage page only breaks ties (the game controller
+    // collection, then vendor pages, keyboard/mouse emulation last), and
+    // the lowest interface number after that.
+    fn rank(&self) -> (u8, u8, i32) {
+        let declared = match self.shift_report {
+            Some(true) => 0,
+            None => 1,
+            Some(false) => 2,
+        };
+        let usage = match self.kind() {
+            "joystick" => 0,
+            "gamepad" | "multi-axis controller" => 1,
+            "vendor-defined" => 2,
+            "other" => 3,
+            _ => 4,
+        };
+        (declared, usage, self.interface_number)
+    }
+}
+
+// e.g. "#0 joystick (usage 0001:0004), shift report"
+impl std::fmt::Display for HidInterface {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "#{} {} (usage {:04X}:{:04X})",
+            self.interface_number,
+            self.kind(),
+            self.usage_page,
+            self.usage
+        )?;
+        if self.shift_report == Some(true) {
+            write!(f, ", shift report")?;
+        }
+        Ok(())
+    }
+}
+
+/// An interface picked by hand in a slot's ✏ menu, stored in
+/// `interface_overrides`. Windows lists several top-level collections of one
+/// interface under the same number, so the usage tells them apart.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct InterfaceOverride {
+    pub interface_number: i32,
+    #[serde(default)]
+    pub usage_page: Option<u16>, // None (migrated from schema 2) = any collection of the interface
+    #[serde(default)]
+    pub usage: Option<u16>,
+}
+
+impl InterfaceOverride {
+    pub fn of(interface: &HidInterface) -> Self {
+        Self {
+            interface_number: interface.interface_number,
+            usage_page: Some(interface.usage_page),
+            usage: Some(interface.usage),
+        }
+    }
+
+    pub fn matches(&self, interface: &HidInterface) -> bool {
+        self.interface_number == interface.interface_number
+            && self.usage_page.is_none_or(|page| page == interface.usage_page)
+            && self.usage.is_none_or(|usage| usage == interface.usage)
+    }
+}
+
+// e.g. "#0 (usage 0001:0004)"
+impl std::fmt::Display for InterfaceOverride {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "#{}", self.interface_number)?;
+        if let (Some(page), Some(usage)) = (self.usage_page, self.usage) {
+            write!(f, " (usage {:04X}:{:04X})", page, usage)?;
+        }
+        Ok(())
+    }
+}
+
+/// Picks the interface to open: the best ranked one the override matches
+/// if there is one, otherwise the best ranked of all.
+pub fn pick_interface(interfaces: &[HidInterface], chosen: Option<&InterfaceOverride>) -> usize {
+    let best = |include: &dyn Fn(&HidInterface) -> bool| {
+        interfaces
+            .iter()
+            .enumerate()
+            .filter(|(_, i)| include(i))
+            .min_by_key(|(_, i)| i.rank())
+            .map(|(idx, _)| idx)
+    };
+    chosen
+        .and_then(|chosen| best(&|i| chosen.matches(i)))
+        .or_else(|| best(&|_| true))
+        .unwrap_or(0)
+}
+
+// Whether an interface's report descriptor declares the shift report, or None
+// if it can't be opened or read (e.g. keyboard collections on Windows).
+fn probe_shift_report(hidapi: &HidApi, interface: &HidInterface) -> Option<bool> {
+    let device = hidapi.open_path(&interface.path).ok()?;
+    let descriptor = crate::descriptor::read_descriptor(&device).ok()?;
+    trace!("Interface {} declares the shift report: {}", interface, descriptor.shift_report().is_some());
+    Some(descriptor.shift_report().is_some())
+}
+
+/// Reads the report descriptors of composite device interfaces on a
+/// background thread, so opening hidraw nodes never stalls a UI frame.
+#[derive(Debug, Default)]
+pub struct InterfaceProber {
+    results: Arc<Mutex<HashMap<CString, Option<bool>>>>, // Finished probes, by interface path
+    requested: HashSet<CString>,                          // Paths already handed to a probe thread
+}
+
+impl InterfaceProber {
+    /// The probe results for `interfaces`, queueing the ones not probed yet.
+    ///
+    /// An interface reads as `None` until its probe finishes. Results for
+    /// interfaces that are gone are dropped, since the OS reuses their paths.
+    pub fn results(&mut self, interfaces: &[HidInterface]) -> HashMap<CString, Option<bool>> {
+        let present: HashSet<&CString> = interfaces.iter().map(|i| &i.path).collect();
+        self.requested.retain(|path| present.contains(path));
+        let new: Vec<HidInterface> =
+            interfaces.iter().filter(|i| self.requested.insert(i.path.clone())).cloned().collect();
+        if !new.is_empty() {
+            let results = self.results.clone();
+            std::thread::spawn(move || {
+                let hidapi = match HidApi::new() {
+                    Ok(hidapi) => hidapi,
+                    Err(e) => {
+                        warn!("Can't read interface descriptors: {}", e);
+                        return;
+                    }
+                };
+                for interface in new {
+                    let shift_report = probe_shift_report(&hidapi, &interface);
+                    if let Ok(mut results) = results.lock() {
+                        results.insert(interface.path, shift_report);
+                    }
+                }
+            });
+        }
+
+        match self.results.lock() {
+            Ok(mut results) => {
+                results.retain(|path, _| present.contains(path));
+                results.clone()
+            }
+            Err(_) => HashMap::new(),
+        }
+    }
+}
+
+// Where a source slot gets its shift state from
+#[derive(Debug, Copy, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SourceKind {
+    #[default]
+    Hid,     // Read from the selected HID device
+    Virtual, // Set by software (UI toggles or the control port)
+}
+
+/// How a slot picks the device(s) it uses.
+#[derive(Debug, Copy, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SlotMatcher {
+    #[default]
+    Exact,       // The device with this VID/PID/serial
+    SameModel,   // Any device with this VID/PID; the saved serial is preferred
+    NamePattern, // Every device whose product name contains `name_pattern`
+}
+
+impl std::fmt::Display for SlotMatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SlotMatcher::Exact => write!(f, "This device"),
+            SlotMatcher::SameModel => write!(f, "Any device with this PID"),
+            SlotMatcher::NamePattern => write!(f, "All devices matching a name"),
+        }
+    }
+}
+
 // Data structure for saving selected devices in config
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct SavedDevice {
     pub vendor_id: u16,
     pub product_id: u16,
     pub serial_number: String,
     pub state_enabled: [bool; 8], // Which shift bits are active for this device
+    #[serde(default)]
+    pub kind: SourceKind, // Only meaningful for sources; receivers are always HID
+    #[serde(default = "default_slot_enabled")]
+    pub enabled: bool, // Disabled slots keep their settings but the worker skips them
+    #[serde(default)]
+    pub note: String, // Free-text note about what the slot is for
+    #[serde(default)]
+    pub matcher: SlotMatcher,
+    #[serde(default)]
+    pub name_pattern: String, // Case-insensitive part of the product name, for SlotMatcher::NamePattern
+}
+
+fn default_slot_enabled() -> bool {
+    true
 }
 
 impl Default for SavedDevice {
@@ -67,43 +334,152 @@ impl Default for SavedDevice {
             product_id: 0,
             serial_number: String::from(""),
             state_enabled: [true; 8], // Default to all enabled
+            kind: SourceKind::Hid,
+            enabled: true,
+            note: String::from(""),
+            matcher: SlotMatcher::Exact,
+            name_pattern: String::from(""),
+        }
+    }
+}
+
+impl SavedDevice {
+    pub fn is_virtual(&self) -> bool {
+        self.kind == SourceKind::Virtual
+    }
+
+    /// Key of the device this slot points at in `ConfigData::device_nicknames`.
+    pub fn device_key(&self) -> String {
+        device_key(self.vendor_id, self.product_id, &self.serial_number)
+    }
+
+    /// This slot with its masks and note blanked out: the part a running
+    /// worker can only pick up by reopening its devices.
+    pub fn device_settings(&self) -> SavedDevice {
+        SavedDevice {
+            state_enabled: [true; 8],
+            note: String::new(),
+            ..self.clone()
         }
     }
 }
 
+/// Identifies a physical device as "VID:PID:serial" (hex IDs, "no_sn" without a serial).
+/// Used as `VpcDevice::full_name` and as the nickname key.
+pub fn device_key(vendor_id: u16, product_id: u16, serial_number: &str) -> String {
+    format!(
+        "{:04X}:{:04X}:{}",
+        vendor_id,
+        product_id,
+        if serial_number.is_empty() { "no_sn" } else { serial_number }
+    )
+}
+
 /// Finds the index in the `device_list` corresponding to the saved device data.
 /// Returns 0 (default "No Connection") if not found or if saved_device is invalid.
+/// For a slot matching several devices this is the first of them.
 pub(crate) fn find_device_index_for_saved(
     device_list: &[VpcDevice], // Pass device list explicitly
     saved_device: &SavedDevice,
 ) -> usize {
-    if saved_device.vendor_id == 0 && saved_device.product_id == 0 {
-        return 0; // Point to the default "No Connection" entry
-    }
-    device_list
-        .iter()
-        .position(|d| {
-            d.vendor_id == saved_device.vendor_id
-                && d.product_id == saved_device.product_id
-                && d.serial_number == saved_device.serial_number
-        })
+    resolve_slot_devices(device_list, saved_device)
+        .first()
+        .copied()
         .unwrap_or(0) // Default to index 0 ("No Connection") if not found
 }
 
+/// Indices into `device_list` of the connected devices a slot uses, following
+/// its matcher. Index 0 ("No connection") is never returned.
+///
+/// Besides the exact device, only devices the firmware policy accepts match,
+/// so a rejected device doesn't take the place of a usable one.
+pub fn resolve_slot_devices(device_list: &[VpcDevice], slot: &SavedDevice) -> Vec<usize> {
+    resolve_slot_devices_except(device_list, slot, &HashSet::new())
+}
+
+/// The devices every source and receiver slot uses, in slot order.
+///
+/// Enabled exact slots claim their device first; the matcher slots then skip
+/// devices an exact slot or an earlier matcher slot already has, so a pattern
+/// can't pull a device into a second slot and have it read and written in
+/// the same cycle.
+pub fn resolve_all_slots(
+    device_list: &[VpcDevice],
+    sources: &[SavedDevice],
+    receivers: &[SavedDevice],
+) -> (Vec<Vec<usize>>, Vec<Vec<usize>>) {
+    let slots = || sources.iter().chain(receivers);
+    let mut taken: HashSet<usize> = slots()
+        .filter(|slot| slot.enabled && slot.matcher == SlotMatcher::Exact)
+        .flat_map(|slot| resolve_slot_devices(device_list, slot))
+        .collect();
+    let mut resolved: Vec<Vec<usize>> = slots()
+        .map(|slot| {
+            if slot.matcher == SlotMatcher::Exact {
+                return resolve_slot_devices(device_list, slot);
+            }
+            let devices = resolve_slot_devices_except(device_list, slot, &taken);
+            if slot.enabled {
+                taken.extend(&devices);
+            }
+            devices
+        })
+        .collect();
+    let receivers = resolved.split_off(sources.len());
+    (resolved, receivers)
+}
+
+fn resolve_slot_devices_except(
+    device_list: &[VpcDevice],
+    slot: &SavedDevice,
+    taken: &HashSet<usize>,
+) -> Vec<usize> {
+    if slot.is_virtual() {
+        return Vec::new();
+    }
+    let listed = || device_list.iter().enumerate().skip(1).filter(|(idx, _)| !taken.contains(idx));
+    let same_model = |d: &VpcDevice| d.vendor_id == slot.vendor_id && d.product_id == slot.product_id;
+    let exact = listed()
+        .find(|(_, d)| same_model(d) && d.serial_number == slot.serial_number)
+        .map(|(idx, _)| idx);
+    match slot.matcher {
+        SlotMatcher::Exact => exact.into_iter().collect(),
+        SlotMatcher::SameModel => exact
+            .or_else(|| {
+                listed()
+                    .find(|(_, d)| same_model(d) && d.unsupported.is_none())
+                    .map(|(idx, _)| idx)
+            })
+            .into_iter()
+            .collect(),
+        SlotMatcher::NamePattern => {
+            let pattern = slot.name_pattern.trim().to_ascii_lowercase();
+            if pattern.is_empty() {
+                return Vec::new();
+            }
+            listed()
+                .filter(|(_, d)| d.unsupported.is_none() && d.name.to_ascii_lowercase().contains(&pattern))
+                .map(|(idx, _)| idx)
+                .collect()
+        }
+    }
+}
+
 
 // --- Device Management Functions ---
 
 impl crate::ShiftTool {
     /// Refreshes the internal list of available HID devices.
-    pub(crate) fn refresh_devices(&mut self) {
+    pub fn refresh_devices(&mut self) {
         trace!("Refreshing device list...");
         match HidApi::new() {
             Ok(hidapi) => {
                 let mut current_devices: Vec<VpcDevice> = Vec::new();
-                // Keep track of seen devices to avoid duplicates
-                // Use a HashSet for efficient checking
-                use std::collections::HashSet;
-                let mut seen_devices = HashSet::new();
+                // hidapi lists every interface of a device separately; keep one
+                // entry per device (by index into current_devices) and collect
+                // its interfaces
+                let mut seen_devices: HashMap<(u16, u16, String), usize> = HashMap::new();
+                let known_devices: HashSet<String> = self.device_list.iter().map(|d| d.full_name.clone()).collect();
 
                 for device_info in hidapi.device_list() {
                     // Filter for specific vendor if desired
@@ -119,29 +495,87 @@ impl crate::ShiftTool {
                             );
 
                             // Check if we've already added this unique device
-                            if seen_devices.insert(device_key) {
-                                // If insert returns true, it's a new device
-                                if crate::util::is_supported(
-                                    vpc_device.firmware.to_string(),
-                                ) {
-                                    debug!("Found supported device: {}", vpc_device);
-                                    current_devices.push(vpc_device);
-                                } else {
-                                    warn!(
-                                        "Found unsupported device (firmware?): {}",
-                                        vpc_device
-                                    );
-                                    // Optionally add unsupported devices too, just filter later?
-                                    // current_devices.push(vpc_device);
+                            if let Some(&idx) = seen_devices.get(&device_key) {
+                                // Another interface of a device already in the list
+                                let known = &mut current_devices[idx];
+                                for interface in vpc_device.interfaces {
+                                    if !known.interfaces.contains(&interface) {
+                                        known.interfaces.push(interface);
+                                    }
                                 }
                             } else {
-                                // Device already seen (duplicate entry from hidapi)
-                                log::trace!("Skipping duplicate device entry: {}", vpc_device);
+                                seen_devices.insert(device_key, current_devices.len());
+                                let mut vpc_device = vpc_device;
+                                // The list is refreshed every frame; only warn when a device shows up
+                                let just_connected =
+                                    !self.device_list.iter().any(|d| d.full_name == vpc_device.full_name);
+                                if let Some(problem) = vpc_device.firmware.diagnostic() {
+                                    if just_connected {
+                                        warn!("Unrecognised firmware on {}: {}", vpc_device, problem);
+                                    }
+                                }
+                                match crate::util::is_supported(
+                                    &vpc_device.firmware,
+                                    &self.config.data.firmware_policy,
+                                    self.skip_firmware,
+                                ) {
+                                    Ok(()) => debug!(
+                                        "Found supported device: {} [{}]",
+                                        vpc_device,
+                                        crate::catalog::lookup(
+                                            &self.config.data.device_catalog,
+                                            vpc_device.product_id,
+                                            &vpc_device.name
+                                        )
+                                        .map_or_else(|| "unknown model".to_string(), |e| e.to_string())
+                                    ),
+                                    Err(reason) => {
+                                        // Still listed (greyed out) so the user sees why
+                                        if just_connected {
+                                            warn!("Found unsupported device {}: {}", vpc_device, reason);
+                                        }
+                                        vpc_device.unsupported = Some(reason);
+                                    }
+                                }
+                                current_devices.push(vpc_device);
                             }
                         }
                     }
                 }
 
+                // Only composite devices have a choice to make. Their descriptors are
+                // read in the background when an interface shows up, not every refresh.
+                let composite: Vec<HidInterface> = current_devices
+                    .iter()
+                    .filter(|d| d.interfaces.len() > 1)
+                    .flat_map(|d| d.interfaces.iter().cloned())
+                    .collect();
+                let probed = self.interface_prober.results(&composite);
+
+                // Apply nicknames before sorting so they show up everywhere the device does
+                for device in current_devices.iter_mut() {
+                    if let Some(nickname) = self.config.data.device_nicknames.get(&device.full_name) {
+                        device.nickname = nickname.clone();
+                    }
+                    for interface in device.interfaces.iter_mut() {
+                        interface.shift_report = probed.get(&interface.path).copied().flatten();
+                    }
+                    let chosen = self.config.data.interface_overrides.get(&device.full_name);
+                    if let Some(chosen) = chosen {
+                        // Once per connection; the list is refreshed every frame
+                        if !known_devices.contains(&device.full_name)
+                            && !device.interfaces.iter().any(|i| chosen.matches(i))
+                        {
+                            warn!("Interface override {} doesn't exist on {}; choosing automatically.", chosen, device);
+                        }
+                    }
+                    device.interface = pick_interface(&device.interfaces, chosen);
+                    if let Some(interface) = device.interfaces.get(device.interface) {
+                        device.usage = interface.usage;
+                        trace!("Using interface {} of {}.", interface, device.full_name);
+                    }
+                }
+
                 // Sort devices (e.g., by name)
                 current_devices.sort_by(|a, b| a.name.cmp(&b.name));
 
@@ -159,6 +593,12 @@ impl crate::ShiftTool {
                 // Validate selected devices against the new, deduplicated list
                 self.validate_selected_devices();
 
+                // Matcher slots follow devices being plugged in and out
+                if self.get_thread_status() && self.matched_devices() != self.worker_devices {
+                    info!("Devices matched by slot matchers changed; restarting the worker.");
+                    self.restart_worker();
+                }
+
             }
             Err(e) => {
                 error!("Failed to create HidApi for device refresh: {}", e);
@@ -166,6 +606,47 @@ impl crate::ShiftTool {
         }
     }
 
+    /// The devices each source and receiver slot uses (`resolve_all_slots`).
+    pub(crate) fn slot_devices(&self) -> (Vec<Vec<usize>>, Vec<Vec<usize>>) {
+        resolve_all_slots(&self.device_list, &self.config.data.sources, &self.config.data.receivers)
+    }
+
+    /// Why the device a slot points at can't be used as configured, if it can't.
+    pub(crate) fn slot_warning(&self, slot: &SavedDevice, is_receiver: bool, i: usize) -> Option<String> {
+        if slot.matcher != SlotMatcher::Exact {
+            let matched = resolve_slot_devices(&self.device_list, slot);
+            if slot.matcher == SlotMatcher::NamePattern && matched.is_empty() {
+                return Some(format!("No connected device matches \"{}\".", slot.name_pattern.trim()));
+            }
+            let (sources, receivers) = self.slot_devices();
+            let used = if is_receiver { &receivers[i] } else { &sources[i] };
+            let skipped = matched.iter().filter(|idx| !used.contains(idx)).count();
+            if used.is_empty() && !matched.is_empty() {
+                return Some("Every matching device is used by another slot; the worker skips this slot.".to_string());
+            }
+            if skipped > 0 && slot.matcher == SlotMatcher::NamePattern {
+                return Some(format!("{} matching device(s) are used by another slot and left out.", skipped));
+            }
+        }
+        let idx = find_device_index_for_saved(&self.device_list, slot);
+        if let Some(reason) = self.device_list.get(idx).filter(|_| idx != 0).and_then(|d| d.unsupported.clone()) {
+            return Some(format!("{}; the worker skips this slot.", reason));
+        }
+        // Only known once the worker (or the details window) has read the descriptor
+        if let Some(Ok(descriptor)) = self.cached_descriptor(&slot.device_key()) {
+            if descriptor.shift_report().is_none() {
+                return Some(if is_receiver {
+                    "The HID descriptor has no shift feature report; the worker won't use it as a receiver.".to_string()
+                } else {
+                    "The HID descriptor has no shift feature report; reading this source will likely fail.".to_string()
+                });
+            }
+        }
+        self.slot_catalog_entry(slot)
+            .filter(|entry| is_receiver && !entry.can_receive())
+            .map(|entry| format!("{} has no shift report; it can't act as a receiver.", entry.model))
+    }
+
     /// Generic helper to find a device index based on SavedDevice data.
     fn find_device_index_for_saved(&self, saved_device: &SavedDevice) -> usize {
         if saved_device.vendor_id == 0 && saved_device.product_id == 0 {
@@ -182,16 +663,20 @@ impl crate::ShiftTool {
     }
 
     /// Checks if saved source/receiver devices still exist in the refreshed list.
-    /// Resets the config entry to default if the device is gone.
+    /// Missing devices keep their config; `update_rebind_offers` looks for a
+    /// replacement.
     fn validate_selected_devices(&mut self) {
+        self.update_rebind_offers();
+
         for i in 0..self.config.data.sources.len() {
             let idx = self.find_device_index_for_saved(&self.config.data.sources[i]);
             // Check if device *was* configured but is *not* found (idx 0 is default/not found)
             if idx == 0 && (self.config.data.sources[i].vendor_id != 0 || self.config.data.sources[i].product_id != 0) {
                 // Log that the configured device is currently missing, but DO NOT reset config
                 warn!(
-                    "validate_selected_devices: Configured source device {} (VID={:04X}, PID={:04X}) not found in refreshed list. Keeping configuration.",
-                    i + 1, self.config.data.sources[i].vendor_id, self.config.data.sources[i].product_id
+                    "validate_selected_devices: Configured source device {} (VID={:04X}, PID={:04X}{}) not found in refreshed list. Keeping configuration.",
+                    i + 1, self.config.data.sources[i].vendor_id, self.config.data.sources[i].product_id,
+                    self.config.data.nickname_suffix(&self.config.data.sources[i])
                 );
             }
         }
@@ -199,8 +684,9 @@ impl crate::ShiftTool {
             let idx = self.find_device_index_for_saved(&self.config.data.receivers[i]);
             if idx == 0 && (self.config.data.receivers[i].vendor_id != 0 || self.config.data.receivers[i].product_id != 0) {
                 warn!(
-                    "validate_selected_devices: Configured receiver device {} (VID={:04X}, PID={:04X}) not found in refreshed list. Keeping configuration.",
-                    i + 1, self.config.data.receivers[i].vendor_id, self.config.data.receivers[i].product_id
+                    "validate_selected_devices: Configured receiver device {} (VID={:04X}, PID={:04X}{}) not found in refreshed list. Keeping configuration.",
+                    i + 1, self.config.data.receivers[i].vendor_id, self.config.data.receivers[i].product_id,
+                    self.config.data.nickname_suffix(&self.config.data.receivers[i])
                 );
             }
         }
@@ -217,32 +703,27 @@ fn create_vpc_device_from_info(device_info: &DeviceInfo) -> Option<VpcDevice> {
         .product_string()
         .unwrap_or("Unknown Product")
         .to_string();
-    let firmware = device_info
-        .manufacturer_string()
-        .unwrap_or("Unknown Firmware")
-        .to_string();
+    let firmware = FirmwareVersion::parse(device_info.manufacturer_string().unwrap_or("Unknown Firmware"));
     let serial_number = device_info.serial_number().unwrap_or("").to_string();
-    let usage = device_info.usage();
 
     if vendor_id == 0 || product_id == 0 || name == "Unknown Product" {
         return None;
     }
 
-    let full_name = format!(
-        "{:04X}:{:04X}:{}",
-        vendor_id,
-        product_id,
-        if serial_number.is_empty() { "no_sn" } else { &serial_number }
-    );
+    let full_name = device_key(vendor_id, product_id, &serial_number);
 
     Some(VpcDevice {
         full_name,
         name: name.into(),
-        firmware: firmware.into(),
+        firmware,
         vendor_id,
         product_id,
         serial_number,
-        usage,
+        usage: device_info.usage(),
+        interfaces: vec![HidInterface::from_info(device_info)], // More are added by refresh_devices
+        interface: 0,
         active: false,
+        nickname: String::new(), // Filled in from the config by refresh_devices
+        unsupported: None,       // Set by refresh_devices from the firmware policy
     })
 }
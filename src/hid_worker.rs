@@ -1,10 +1,24 @@
-use crate::config::{ModifiersArray};
-use crate::device::SavedDevice;
-use crate::{SharedDeviceState, SharedStateFlag}; // Import shared types
+use crate::config::{
+    HookConfig, HookEvent, JoystickSource, KeyBinding, ModifiersArray, VirtualJoystickConfig,
+};
+use crate::descriptor::SharedDescriptors;
+use crate::device::{SavedDevice, SlotMatcher, SourceKind};
+use crate::hooks::{HookContext, HookRunner};
+use crate::trace::{
+    Trace, TraceEvent, TraceKind, TraceLine, TraceSettings, TraceSlot, TraceWriter,
+};
+use crate::uinput::{VirtualJoystick, VirtualKeyboard};
 use crate::util::{self, ReportFormat, MAX_REPORT_SIZE};
-use log::{error, info, trace, warn};
+use crate::{SharedDeviceState, SharedStateFlag}; // Import shared types
 use hidapi::{HidApi, HidDevice};
+use log::{error, info, trace, warn};
 use std::{
+    cell::RefCell,
+    collections::{BTreeMap, VecDeque},
+    ffi::CString,
+    path::PathBuf,
+    sync::mpsc::{self, Receiver},
+    sync::{Arc, Condvar, Mutex},
     thread,
     time::Duration,
 };
@@ -13,11 +27,37 @@ use std::{
 pub const VENDOR_ID_FILTER: u16 = 0x3344; // Assuming Virpil VID
 const WORKER_SLEEP_MS: u64 = 100; // Reduced sleep time for better responsiveness
 
-
 #[derive(Clone)]
 struct DeviceWorkerInfo {
     config: SavedDevice,
     format: ReportFormat,
+    label: String,         // Nickname or product name, for logs and hooks
+    product: String,       // Product name, the label when the device has no nickname
+    path: Option<CString>, // Interface chosen by refresh_devices; None if the device wasn't listed
+    slot: usize, // Slot this device was resolved from; a matcher slot can fan out to several
+}
+
+/// Settings a running worker picks up without reopening any device.
+///
+/// The UI sends a new snapshot whenever rules, masks or device nicknames
+/// change; the worker applies the latest one at the start of its next cycle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LiveSettings {
+    pub shift_modifiers: ModifiersArray,
+    pub source_masks: Vec<[bool; 8]>, // state_enabled per source slot
+    pub receiver_masks: Vec<[bool; 8]>, // state_enabled per receiver slot
+    pub nicknames: BTreeMap<String, String>, // Device nicknames, for the labels in logs and hooks
+}
+
+impl LiveSettings {
+    pub fn from_config(data: &crate::config::ConfigData) -> Self {
+        Self {
+            shift_modifiers: data.shift_modifiers,
+            source_masks: data.sources.iter().map(|s| s.state_enabled).collect(),
+            receiver_masks: data.receivers.iter().map(|r| r.state_enabled).collect(),
+            nicknames: data.device_nicknames.clone(),
+        }
+    }
 }
 
 // Structure to hold data passed to the worker thread
@@ -27,100 +67,273 @@ struct WorkerData {
     sources_info: Vec<DeviceWorkerInfo>,
     receivers_info: Vec<DeviceWorkerInfo>,
     shift_modifiers: ModifiersArray,
+    virtual_joystick: VirtualJoystickConfig,
+    key_bindings: Vec<KeyBinding>,
+    hooks: Vec<HookConfig>,
     source_states_shared: Vec<SharedDeviceState>,
     receiver_states_shared: Vec<SharedDeviceState>,
     final_shift_state_shared: SharedDeviceState,
+    descriptors: SharedDescriptors, // Filled in as devices are opened
+    live_updates: Receiver<LiveSettings>, // Rule/mask edits made while running
+    trace_path: Option<PathBuf>,    // --record: trace file every read and write is appended to
+}
+
+/// The feature report calls a worker cycle makes. Implemented by hidapi
+/// devices and by the trace replay backend.
+trait FeatureReports {
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize, String>;
+    fn send_feature_report(&self, data: &[u8]) -> Result<(), String>;
+}
+
+impl FeatureReports for HidDevice {
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize, String> {
+        HidDevice::get_feature_report(self, buf).map_err(|e| e.to_string())
+    }
+
+    fn send_feature_report(&self, data: &[u8]) -> Result<(), String> {
+        HidDevice::send_feature_report(self, data).map_err(|e| e.to_string())
+    }
 }
 
 // Main function to spawn the worker thread
 // Now part of ShiftTool impl block
 impl crate::ShiftTool {
-    pub(crate) fn spawn_worker(&mut self) -> bool {
+    // The slot config handed to the worker. Slots whose device the firmware
+    // policy rejects are passed as switched off, so the worker never opens them.
+    fn supported_slot_config(
+        &self,
+        slot: &SavedDevice,
+        device_idx: usize,
+        kind: &str,
+        i: usize,
+    ) -> SavedDevice {
+        let mut config = slot.clone();
+        if let Some(reason) = self
+            .device_list
+            .get(device_idx)
+            .filter(|_| device_idx != 0)
+            .and_then(|d| d.unsupported.as_ref())
+        {
+            if config.enabled {
+                warn!("{} {} is not opened: {}", kind, i, reason);
+                config.enabled = false;
+            }
+        }
+        config
+    }
+
+    // The devices a slot is opened as: one per device it resolved to (see
+    // `slot_devices`), bound to that device. An exact or same-model slot
+    // whose device isn't listed is still passed on (as 0), so the worker
+    // keeps trying it by VID/PID/serial; one whose devices all belong to
+    // other slots is left out.
+    fn slot_targets(&self, slot: &SavedDevice, resolved: &[usize], kind: &str, i: usize) -> Vec<(usize, SavedDevice)> {
+        let matched = crate::device::resolve_slot_devices(&self.device_list, slot);
+        for idx in matched.iter().filter(|idx| !resolved.contains(idx)) {
+            warn!(
+                "{} {} leaves out {}: another slot already uses it.",
+                kind, i, self.device_list[*idx].full_name
+            );
+        }
+        if resolved.is_empty() {
+            if !matched.is_empty() {
+                return Vec::new();
+            }
+            return match slot.matcher {
+                SlotMatcher::NamePattern => Vec::new(),
+                _ => vec![(0, slot.clone())],
+            };
+        }
+        resolved
+            .iter()
+            .map(|&idx| {
+                let device = &self.device_list[idx];
+                let mut config = slot.clone();
+                config.vendor_id = device.vendor_id;
+                config.product_id = device.product_id;
+                config.serial_number = device.serial_number.clone();
+                (idx, config)
+            })
+            .collect()
+    }
+
+    /// Device keys the matcher slots currently resolve to. The worker is
+    /// restarted when this changes, so it follows devices being plugged in
+    /// and out.
+    pub(crate) fn matched_devices(&self) -> Vec<String> {
+        let (sources, receivers) = self.slot_devices();
+        self.config
+            .data
+            .sources
+            .iter()
+            .chain(&self.config.data.receivers)
+            .zip(sources.iter().chain(&receivers))
+            .filter(|(slot, _)| slot.enabled && slot.matcher != SlotMatcher::Exact)
+            .flat_map(|(_, devices)| devices)
+            .map(|&idx| self.device_list[idx].full_name.clone())
+            .collect()
+    }
+
+    // Path of the interface refresh_devices chose for a listed device
+    fn interface_path(&self, device_idx: usize) -> Option<CString> {
+        self.device_list
+            .get(device_idx)
+            .filter(|_| device_idx != 0)
+            .and_then(|device| device.chosen_interface())
+            .map(|interface| interface.path.clone())
+    }
+
+    pub fn spawn_worker(&mut self) -> bool {
         info!("Attempting to spawn HID worker thread...");
 
+        let (source_devices, receiver_devices) = self.slot_devices();
         let mut sources_info: Vec<DeviceWorkerInfo> = Vec::new();
         for (i, source_config) in self.config.data.sources.iter().enumerate() {
+            // Virtual sources are never opened, so they don't need a report format
+            if source_config.is_virtual() {
+                info!("Source {} is virtual; its state is set by software.", i);
+                sources_info.push(DeviceWorkerInfo {
+                    config: source_config.clone(),
+                    format: util::DEFAULT_REPORT_FORMAT,
+                    label: "virtual".to_string(),
+                    product: "virtual".to_string(),
+                    path: None,
+                    slot: i,
+                });
+                continue;
+            }
 
-            // 1. Find the corresponding VpcDevice in the current device_list
+            // 1. Find the corresponding VpcDevice(s) in the current device_list
             //    This is needed to get the firmware string.
-            let device_idx = crate::device::find_device_index_for_saved(
-                &self.device_list, // The list of currently detected devices
-                source_config,     // The config for the i-th source slot
-            );
-
-            // 2. Get the firmware string from the found VpcDevice
-            let firmware_str = if device_idx != 0 && device_idx < self.device_list.len() {
-                // Successfully found the device in the current list
-                self.device_list[device_idx].firmware.to_string() // Access the firmware field
-            } else {
-                // Device not found (index 0 is default/placeholder) or list issue
-                warn!("Source device {} not found in current list for format determination.", i);
-                "".to_string() // Use empty string if not found
-            };
+            let targets = self.slot_targets(source_config, &source_devices[i], "Source", i);
+            if targets.is_empty() {
+                warn!("No connected device matches source {}; it is left out.", i);
+            }
+            for &(device_idx, ref source_config) in &targets {
+                // 2. Get the parsed firmware from the found VpcDevice
+                let firmware = if device_idx != 0 && device_idx < self.device_list.len() {
+                    // Successfully found the device in the current list
+                    self.device_list[device_idx].firmware.clone() // Access the firmware field
+                } else {
+                    // Device not found (index 0 is default/placeholder) or list issue
+                    warn!(
+                        "Source device {} not found in current list for format determination.",
+                        i
+                    );
+                    util::FirmwareVersion::default() // No firmware if not found
+                };
 
-            let name_str = if device_idx != 0 && device_idx < self.device_list.len() {
-                // Successfully found the device in the current list
-                self.device_list[device_idx].name.to_string() // Access the firmware field
-            } else {
-                // Device not found (index 0 is default/placeholder) or list issue
-                warn!("Source device {} not found in current list for format determination.", i);
-                "".to_string() // Use empty string if not found
-            };
+                let name_str = if device_idx != 0 && device_idx < self.device_list.len() {
+                    // Successfully found the device in the current list
+                    self.device_list[device_idx].name.to_string() // Access the firmware field
+                } else {
+                    // Device not found (index 0 is default/placeholder) or list issue
+                    warn!(
+                        "Source device {} not found in current list for format determination.",
+                        i
+                    );
+                    "".to_string() // Use empty string if not found
+                };
 
-            // 3. Call determine_report_format with the firmware string
-            //    This function (from src/util.rs) contains the logic
-            //    to check dates or patterns and return the correct format.
-            let determined_format: ReportFormat = util::determine_report_format(&name_str, &firmware_str);
+                // 3. Pick the report format: a catalog entry can pin one, otherwise
+                //    util::determine_report_format decides from the firmware date.
+                let entry = self.slot_catalog_entry(source_config);
+                let determined_format: ReportFormat =
+                    util::report_format_for(&name_str, &firmware, entry.map(|e| e.format));
 
-            // 4. Log the result for debugging
-            info!(
-                "Determined report format {:?} for source {} (Firmware: '{}')",
-                determined_format, // Log the whole struct (uses Debug derive)
-                i,
-                firmware_str
-            );
+                // 4. Log the result for debugging
+                info!(
+                    "Determined report format {:?} for source {} [{}] (Firmware: '{}')",
+                    determined_format, // Log the whole struct (uses Debug derive)
+                    i,
+                    entry.map_or_else(|| "unknown model".to_string(), |e| e.to_string()),
+                    firmware.raw
+                );
 
-            // 5. Store the result along with the config in DeviceWorkerInfo
-            sources_info.push(DeviceWorkerInfo {
-                config: source_config.clone(), // Clone the config part
-                format: determined_format,     // Store the determined format
-            });
+                // 5. Store the result along with the config in DeviceWorkerInfo
+                sources_info.push(DeviceWorkerInfo {
+                    config: self.supported_slot_config(source_config, device_idx, "Source", i),
+                    format: determined_format, // Store the determined format
+                    label: self
+                        .config
+                        .data
+                        .nickname_for(source_config)
+                        .map_or_else(|| name_str.clone(), str::to_string),
+                    product: name_str,
+                    path: self.interface_path(device_idx),
+                    slot: i,
+                });
+            }
         }
 
         let mut receivers_info: Vec<DeviceWorkerInfo> = Vec::new();
         for (i, receiver_config) in self.config.data.receivers.iter().enumerate() {
-            let device_idx = crate::device::find_device_index_for_saved(
-                &self.device_list,
-                receiver_config,
-            );
-            let firmware_str = if device_idx != 0 && device_idx < self.device_list.len() {
-                self.device_list[device_idx].firmware.to_string()
-            } else {
-                warn!("Receiver device {} not found in current list for format determination.", i);
-                "".to_string()
-            };
-            let name_str = if device_idx != 0 && device_idx < self.device_list.len() {
-                self.device_list[device_idx].name.to_string()
-            } else {
-                warn!("Receiver device {} not found in current list for format determination.", i);
-                "".to_string()
-            };
+            let targets = self.slot_targets(receiver_config, &receiver_devices[i], "Receiver", i);
+            if targets.is_empty() {
+                warn!(
+                    "No connected device matches receiver {}; it is left out.",
+                    i
+                );
+            }
+            for &(device_idx, ref receiver_config) in &targets {
+                let firmware = if device_idx != 0 && device_idx < self.device_list.len() {
+                    self.device_list[device_idx].firmware.clone()
+                } else {
+                    warn!(
+                        "Receiver device {} not found in current list for format determination.",
+                        i
+                    );
+                    util::FirmwareVersion::default()
+                };
+                let name_str = if device_idx != 0 && device_idx < self.device_list.len() {
+                    self.device_list[device_idx].name.to_string()
+                } else {
+                    warn!(
+                        "Receiver device {} not found in current list for format determination.",
+                        i
+                    );
+                    "".to_string()
+                };
 
-            let determined_format: ReportFormat = util::determine_report_format(&name_str, &firmware_str);
+                let entry = self.slot_catalog_entry(receiver_config);
+                let determined_format: ReportFormat =
+                    util::report_format_for(&name_str, &firmware, entry.map(|e| e.format));
 
-            info!(
-                "Determined report format {:?} for receiver {} (Firmware: '{}')",
-                determined_format,
-                i,
-                firmware_str
-            );
+                info!(
+                    "Determined report format {:?} for receiver {} [{}] (Firmware: '{}')",
+                    determined_format,
+                    i,
+                    entry.map_or_else(|| "unknown model".to_string(), |e| e.to_string()),
+                    firmware.raw
+                );
+                if entry.is_some_and(|e| !e.can_receive()) {
+                    warn!(
+                        "Receiver {} ({}) has no shift report; writes to it will likely fail.",
+                        i, name_str
+                    );
+                }
 
-            receivers_info.push(DeviceWorkerInfo {
-                config: receiver_config.clone(),
-                format: determined_format,
-            });
+                receivers_info.push(DeviceWorkerInfo {
+                    config: self.supported_slot_config(receiver_config, device_idx, "Receiver", i),
+                    format: determined_format,
+                    label: self
+                        .config
+                        .data
+                        .nickname_for(receiver_config)
+                        .map_or_else(|| name_str.clone(), str::to_string),
+                    product: name_str,
+                    path: self.interface_path(device_idx),
+                    slot: i,
+                });
+            }
         }
 
+        self.worker_devices = self.matched_devices();
+
+        // Rule/mask edits reach the running worker through this channel
+        let (live_sender, live_updates) = mpsc::channel();
+        self.worker_updates = Some(live_sender);
+        self.live_settings = Some(LiveSettings::from_config(&self.config.data));
 
         // Clone data needed by the thread
         let worker_data = WorkerData {
@@ -128,15 +341,22 @@ impl crate::ShiftTool {
             sources_info,
             receivers_info,
             shift_modifiers: self.config.data.shift_modifiers, // Copy (it's Copy)
+            virtual_joystick: self.config.data.virtual_joystick,
+            key_bindings: self.config.data.key_bindings.clone(),
+            hooks: self.config.data.hooks.clone(),
             source_states_shared: self.source_states.clone(),
             receiver_states_shared: self.receiver_states.clone(),
             final_shift_state_shared: self.shift_state.clone(),
+            descriptors: self.descriptors.clone(),
+            live_updates,
+            trace_path: self.trace_path.clone(),
         };
 
-        // Spawn the thread
-        thread::spawn(move || {
+        // Spawn the thread, keeping the handle so restarts can wait for it
+        self.worker_handle = Some(thread::spawn(move || {
             // Create HidApi instance *within* the thread
-            match HidApi::new() { // Use new() which enumerates internally
+            match HidApi::new() {
+                // Use new() which enumerates internally
                 Ok(hidapi) => {
                     info!("HidApi created successfully in worker thread.");
                     // Filter devices *within* the thread if needed, though opening by VID/PID/SN is primary
@@ -150,14 +370,67 @@ impl crate::ShiftTool {
                     // For now, thread just exits.
                 }
             }
-        });
+        }));
 
         info!("HID worker thread spawn initiated.");
         true // Indicate spawn attempt was made
     }
 
+    /// Stops the running worker, waits for it to exit and spawns a fresh one
+    /// from the current config. Does nothing if the worker isn't running.
+    pub(crate) fn restart_worker(&mut self) {
+        if !self.get_thread_status() {
+            return;
+        }
+        info!("Restarting HID worker thread...");
+
+        self.signal_worker(false);
+        if let Some(handle) = self.worker_handle.take() {
+            if handle.join().is_err() {
+                error!("Worker thread panicked before restart.");
+            }
+        }
+        self.stop_worker_cleanup();
+
+        self.signal_worker(true);
+        if !self.spawn_worker() {
+            error!("Worker thread failed to respawn after restart.");
+            self.signal_worker(false);
+        }
+    }
+
+    /// Sends the current rules and masks to the running worker if they changed
+    /// since the last push. Called once per UI frame.
+    pub(crate) fn push_live_settings(&mut self) {
+        let sender = match &self.worker_updates {
+            Some(sender) => sender,
+            None => return,
+        };
+        let settings = LiveSettings::from_config(&self.config.data);
+        if self.live_settings.as_ref() == Some(&settings) {
+            return;
+        }
+        if sender.send(settings.clone()).is_err() {
+            // Worker has exited; the next spawn creates a new channel
+            self.worker_updates = None;
+            return;
+        }
+        trace!("Pushed live rule/mask update to the worker.");
+        self.live_settings = Some(settings);
+    }
+
+    // Sets the shared run flag and wakes the worker
+    fn signal_worker(&self, run: bool) {
+        let (lock, cvar) = &*self.thread_state;
+        match lock.lock() {
+            Ok(mut started) => *started = run,
+            Err(_) => error!("Thread state mutex poisoned while signaling worker!"),
+        }
+        cvar.notify_all();
+    }
+
     // Cleanup actions when the worker is stopped from the UI
-    pub(crate) fn stop_worker_cleanup(&mut self) {
+    pub fn stop_worker_cleanup(&mut self) {
         info!("Performing worker stop cleanup...");
         // Reset shared states displayed in the UI
         let reset_state = |state_arc: &SharedDeviceState| {
@@ -167,7 +440,16 @@ impl crate::ShiftTool {
             // No need to notify condvar if only UI reads it
         };
 
-        self.source_states.iter().for_each(reset_state);
+        // Virtual sources keep their software-set state across stop/start
+        for (state_arc, source_config) in self
+            .source_states
+            .iter()
+            .zip(self.config.data.sources.iter())
+        {
+            if !source_config.is_virtual() {
+                reset_state(state_arc);
+            }
+        }
         self.receiver_states.iter().for_each(reset_state);
         reset_state(&self.shift_state);
 
@@ -179,43 +461,83 @@ impl crate::ShiftTool {
     }
 }
 
+// Swaps in new rules and masks between cycles, so a cycle never mixes old and new settings
+fn apply_live_settings(data: &mut WorkerData, settings: LiveSettings) {
+    if settings.source_masks.len() != data.source_states_shared.len()
+        || settings.receiver_masks.len() != data.receiver_states_shared.len()
+    {
+        warn!("Worker: Ignoring live update for a different slot layout; restart the worker to apply it.");
+        return;
+    }
+    data.shift_modifiers = settings.shift_modifiers;
+    let relabel = |info: &mut DeviceWorkerInfo| {
+        if !info.config.is_virtual() {
+            info.label = settings
+                .nicknames
+                .get(&info.config.device_key())
+                .filter(|nickname| !nickname.is_empty())
+                .map_or_else(|| info.product.clone(), String::clone);
+        }
+    };
+    for info in data.sources_info.iter_mut() {
+        info.config.state_enabled = settings.source_masks[info.slot];
+        relabel(info);
+    }
+    for info in data.receivers_info.iter_mut() {
+        info.config.state_enabled = settings.receiver_masks[info.slot];
+        relabel(info);
+    }
+    info!("Worker: Applied live rule/mask/nickname update.");
+}
 
 /// Opens HID devices based on the provided configuration and format info.
 ///
-/// Iterates through the `device_infos`, attempts to open each device using
-/// VID, PID, and Serial Number from the `config` field. Sets non-blocking mode.
+/// Iterates through the `device_infos` and opens each device with
+/// `open_slot_device`, then checks its report descriptor with
+/// `verify_shift_report` (which may correct `format`). Sets non-blocking mode.
 ///
 /// Returns a Vec where each element corresponds to an input `DeviceWorkerInfo`.
 /// Contains `Some(HidDevice)` on success, or `None` if the device couldn't be
-/// opened, wasn't configured (VID/PID=0), or failed to set non-blocking mode.
+/// opened, wasn't configured (VID/PID=0), is a receiver without a shift
+/// report, or failed to set non-blocking mode.
 fn open_hid_devices(
     hidapi: &HidApi,
-    device_infos: &[DeviceWorkerInfo], // Accepts a slice of the new struct
+    device_infos: &mut [DeviceWorkerInfo], // Accepts a slice of the new struct
+    is_receiver: bool,
+    descriptors: &SharedDescriptors,
 ) -> Vec<Option<HidDevice>> {
     let mut devices = Vec::with_capacity(device_infos.len());
 
     // Iterate through the DeviceWorkerInfo structs
-    for (i, info) in device_infos.iter().enumerate() {
+    for (i, info) in device_infos.iter_mut().enumerate() {
         // Use info.config to get the device identifiers
         let config = &info.config;
 
-        // Skip if device is not configured (VID/PID are zero)
-        if config.vendor_id == 0 || config.product_id == 0 {
+        // Skip if device is not configured (VID/PID are zero), virtual or disabled
+        if !config.enabled {
+            log::info!("Skipping disabled device slot {}.", i);
+            devices.push(None);
+            continue;
+        }
+        if config.is_virtual() || config.vendor_id == 0 || config.product_id == 0 {
             log::trace!("Skipping opening device slot {} (unconfigured).", i);
             devices.push(None); // Placeholder for unconfigured slot
             continue;
         }
 
         // Attempt to open the device
-        match hidapi.open(
-            config.vendor_id,
-            config.product_id,
-        ) {
+        match open_slot_device(hidapi, info) {
             Ok(device) => {
+                // The descriptor has the final say on the format
+                if !verify_shift_report(&device, info, i, is_receiver, descriptors) {
+                    devices.push(None);
+                    continue;
+                }
+
                 // Log success with format info for context
                 log::info!(
-                    "Successfully opened device slot {}: VID={:04X}, PID={:04X}, SN='{}', Format='{}'",
-                    i, config.vendor_id, config.product_id, config.serial_number, info.format.name // Log format name
+                    "Successfully opened device slot {} '{}': VID={:04X}, PID={:04X}, SN='{}', Format='{}'",
+                    i, info.label, info.config.vendor_id, info.config.product_id, info.config.serial_number, info.format.name // Log format name
                 );
 
                 // Attempt to set non-blocking mode
@@ -234,8 +556,13 @@ fn open_hid_devices(
             Err(e) => {
                 // Log failure to open
                 log::warn!(
-                    "Failed to open device slot {}: VID={:04X}, PID={:04X}, SN='{}': {:?}",
-                    i, config.vendor_id, config.product_id, config.serial_number, e
+                    "Failed to open device slot {} '{}': VID={:04X}, PID={:04X}, SN='{}': {:?}",
+                    i,
+                    info.label,
+                    config.vendor_id,
+                    config.product_id,
+                    config.serial_number,
+                    e
                 );
                 devices.push(None); // Push None on failure
             }
@@ -244,260 +571,698 @@ fn open_hid_devices(
     devices
 }
 
+/// Reads the descriptor of a freshly opened device and checks that it has
+/// the shift feature report. If it does, the report length picks the format,
+/// overriding the firmware date guess. Receivers without the report are
+/// refused (returns false); sources are only warned about. Devices whose
+/// descriptor can't be read keep the guessed format.
+fn verify_shift_report(
+    device: &HidDevice,
+    info: &mut DeviceWorkerInfo,
+    i: usize,
+    is_receiver: bool,
+    descriptors: &SharedDescriptors,
+) -> bool {
+    let descriptor = crate::descriptor::read_descriptor(device);
+    let usable = match &descriptor {
+        Ok(descriptor) => match descriptor.shift_report() {
+            Some(report) => {
+                match util::format_for_report_size(report.size()) {
+                    Some(format) if format != info.format => {
+                        info!(
+                            "Slot {} '{}': descriptor declares a {} byte shift report; using format '{}' instead of '{}'.",
+                            i, info.label, report.size(), format.name, info.format.name
+                        );
+                        info.format = format;
+                    }
+                    Some(_) => {}
+                    None => warn!(
+                        "Slot {} '{}': shift report is {} bytes, which no known format uses; keeping '{}'.",
+                        i, info.label, report.size(), info.format.name
+                    ),
+                }
+                true
+            }
+            None if is_receiver => {
+                error!(
+                    "Receiver {} '{}' has no feature report {} in its HID descriptor, so it can't receive the shift state. Not using it.",
+                    i, info.label, util::FEATURE_REPORT_ID_SHIFT
+                );
+                false
+            }
+            None => {
+                warn!(
+                    "Source {} '{}' has no feature report {} in its HID descriptor; reading its shift state will likely fail.",
+                    i, info.label, util::FEATURE_REPORT_ID_SHIFT
+                );
+                true
+            }
+        },
+        Err(e) => {
+            warn!(
+                "Slot {} '{}': {}; keeping format '{}'.",
+                i, info.label, e, info.format.name
+            );
+            true
+        }
+    };
+    if let Ok(mut descriptors) = descriptors.lock() {
+        descriptors.insert(info.config.device_key(), descriptor);
+    }
+    usable
+}
+
+// Opens a device again after a failed read or write, in non-blocking mode.
+// It may have come back with other firmware, so the descriptor is checked
+// (and may change the format) just like on the first open.
+fn reopen_slot_device(
+    hidapi: &HidApi,
+    info: &mut DeviceWorkerInfo,
+    i: usize,
+    is_receiver: bool,
+    descriptors: &SharedDescriptors,
+) -> Option<HidDevice> {
+    let device = open_slot_device(hidapi, info).ok()?;
+    if !verify_shift_report(&device, info, i, is_receiver, descriptors) {
+        return None;
+    }
+    device.set_blocking_mode(false).ok().map(|_| device)
+}
 
-// The core worker loop logic
-fn run_hid_worker_loop(hidapi: HidApi, data: WorkerData) {
-    log::info!("HID worker loop starting.");
+/// Opens a slot's device through the interface `refresh_devices` chose for
+/// it. Falls back to VID/PID/serial if there is no path or it went stale
+/// (e.g. the device was replugged under another hidraw node).
+fn open_slot_device(hidapi: &HidApi, info: &DeviceWorkerInfo) -> hidapi::HidResult<HidDevice> {
+    let config = &info.config;
+    if let Some(path) = &info.path {
+        match hidapi.open_path(path) {
+            Ok(device) => return Ok(device),
+            Err(e) => log::debug!(
+                "Failed to open {:?} for '{}' ({}); trying VID/PID.",
+                path,
+                info.label,
+                e
+            ),
+        }
+    }
+    if config.serial_number.is_empty() {
+        hidapi.open(config.vendor_id, config.product_id)
+    } else {
+        hidapi.open_serial(config.vendor_id, config.product_id, &config.serial_number)
+    }
+}
 
-    // --- Device Opening ---
-    // Open sources and receivers, keeping track of which ones succeeded
-    let mut source_devices = open_hid_devices(&hidapi, &data.sources_info);
-    let mut receiver_devices = open_hid_devices(&hidapi, &data.receivers_info);
+/// Creates the virtual joystick output if it is enabled in the config.
+///
+/// Returns `None` when disabled or when the uinput device can't be created
+/// (e.g. missing permissions on `/dev/uinput`); HID routing continues either way.
+fn open_virtual_joystick(data: &WorkerData) -> Option<VirtualJoystick> {
+    if !data.virtual_joystick.enabled {
+        return None;
+    }
+
+    if let JoystickSource::Receiver(idx) = data.virtual_joystick.source {
+        if idx >= data.receiver_states_shared.len() {
+            warn!("Virtual joystick mirrors receiver {} which is not configured. It will stay released.", idx + 1);
+        }
+    }
 
-    // Buffers for HID reports
+    match VirtualJoystick::new() {
+        Ok(js) => Some(js),
+        Err(e) => {
+            error!("Failed to create virtual joystick: {}", e);
+            None
+        }
+    }
+}
+
+/// Creates the virtual keyboard if any key bindings are configured.
+fn open_virtual_keyboard(data: &WorkerData) -> Option<VirtualKeyboard> {
+    if data.key_bindings.is_empty() {
+        return None;
+    }
+
+    match VirtualKeyboard::new(&data.key_bindings) {
+        Ok(kb) => Some(kb),
+        Err(e) => {
+            error!("Failed to create virtual keyboard: {}", e);
+            None
+        }
+    }
+}
+
+/// Fires `event` for every slot whose device was open last cycle but isn't anymore.
+fn fire_offline_hooks(
+    hooks: &HookRunner,
+    event: HookEvent,
+    device_infos: &[DeviceWorkerInfo],
+    devices: &[Option<HidDevice>],
+    online: &mut [bool],
+) {
+    for (i, device_opt) in devices.iter().enumerate() {
+        let is_online = device_opt.is_some();
+        if online[i] && !is_online {
+            info!(
+                "Worker: Slot {} '{}' went offline, firing {} hooks.",
+                i, device_infos[i].label, event
+            );
+            hooks.fire(
+                event,
+                &HookContext {
+                    slot: Some(device_infos[i].slot),
+                    serial_number: device_infos[i].config.serial_number.clone(),
+                    device_name: device_infos[i].label.clone(),
+                    ..Default::default()
+                },
+            );
+        }
+        online[i] = is_online;
+    }
+}
+
+/// Result of one worker cycle.
+struct CycleOutcome {
+    final_state: u16,
+    failed_sources: Vec<usize>,   // Read failed; the caller reopens these
+    failed_receivers: Vec<usize>, // Write failed; the caller reopens these
+}
+
+/// One worker cycle: reads the sources, combines their states by the rules
+/// and writes the result to the receivers. Runs on any `FeatureReports`
+/// backend, so trace replay goes through the same code as the real worker.
+/// Devices that fail are left for the caller to reopen.
+fn run_cycle<D: FeatureReports>(
+    data: &WorkerData,
+    source_devices: &[Option<D>],
+    receiver_devices: &[Option<D>],
+    mut recorder: Option<&mut TraceWriter>,
+) -> CycleOutcome {
     let mut read_buffer = [0u8; MAX_REPORT_SIZE];
     let mut write_buffer = [0u8; MAX_REPORT_SIZE]; // Buffer for calculated output
+    let mut outcome = CycleOutcome {
+        final_state: 0,
+        failed_sources: Vec::new(),
+        failed_receivers: Vec::new(),
+    };
 
-    let &(ref run_lock, ref _run_cvar) = &*data.run_state;
+    // --- Read from Source Devices ---
+    let mut current_source_states: Vec<Option<u16>> = vec![None; source_devices.len()];
 
-    loop {
-        // --- Check Run State ---
-        let should_run = { // Scope for mutex guard
-            match run_lock.lock() {
-                Ok(guard) => *guard,
-                Err(_poisoned) => {
-                    error!("Run state mutex poisoned in worker loop!");
-                    false
-                }
+    for (i, device_opt) in source_devices.iter().enumerate() {
+        let slot = data.sources_info[i].slot;
+        if !data.sources_info[i].config.enabled {
+            continue; // Disabled slot: left out of the result entirely
+        }
+        if data.sources_info[i].config.is_virtual() {
+            // Virtual source: the UI/control port writes the shared state, we only read it
+            current_source_states[i] = data
+                .source_states_shared
+                .get(slot)
+                .and_then(|shared_state| shared_state.lock().ok().map(|guard| *guard));
+            if let Some(trace) = recorder.as_deref_mut() {
+                trace.record(TraceKind::SourceRead, i, Ok(&[]), current_source_states[i]);
             }
-        };
-
-        if !should_run {
-            info!("Stop signal received, exiting worker loop.");
-            break; // Exit the loop
+            continue;
         }
 
-        // --- Read from Source Devices ---
-        let mut current_source_states: Vec<Option<u16>> = vec![None; source_devices.len()];
-
-        for (i, device_opt) in source_devices.iter_mut().enumerate() {
-            if let Some(device) = device_opt {
-                let source_info = &data.sources_info[i];
-                let source_format = source_info.format;
-                read_buffer[0] = source_format.report_id;
-
-                // Attempt to read feature report
-                match device.get_feature_report(&mut read_buffer) {
-                    Ok(bytes_read) => {
-                        if let Some(state_val) = source_format.unpack_state(&read_buffer[0..bytes_read]) {
-                            trace!("Worker: Unpacked state {} from source {}", state_val, i);
-                            current_source_states[i] = Some(state_val);
-                            // Update shared state for UI
-                            if let Some(shared_state) = data.source_states_shared.get(i) {
-                                if let Ok(mut guard) = shared_state.lock() { *guard = state_val; }
-                                else { log::error!("Worker: Mutex poisoned for source_states_shared[{}]!", i); }
-                            }
-                        } else {
-                            // unpack_state returned None (e.g., wrong ID, too short)
-                            log::warn!("Worker: Failed to unpack state from source {} (bytes read: {}) using format '{}'", i, bytes_read, source_format.name);
-                            current_source_states[i] = None;
-                            if let Some(shared_state) = data.source_states_shared.get(i) {
-                                if let Ok(mut guard) = shared_state.lock() { *guard = 0; } // Reset UI
+        if let Some(device) = device_opt {
+            let source_info = &data.sources_info[i];
+            let source_format = source_info.format;
+            read_buffer[0] = source_format.report_id;
+
+            // Attempt to read feature report
+            match device.get_feature_report(&mut read_buffer) {
+                Ok(bytes_read) => {
+                    let unpacked = source_format.unpack_state(&read_buffer[0..bytes_read]);
+                    if let Some(trace) = recorder.as_deref_mut() {
+                        trace.record(
+                            TraceKind::SourceRead,
+                            i,
+                            Ok(&read_buffer[0..bytes_read]),
+                            unpacked,
+                        );
+                    }
+                    if let Some(state_val) = unpacked {
+                        trace!("Worker: Unpacked state {} from source {}", state_val, i);
+                        current_source_states[i] = Some(state_val);
+                        // Update shared state for UI
+                        if let Some(shared_state) = data.source_states_shared.get(slot) {
+                            if let Ok(mut guard) = shared_state.lock() {
+                                *guard = state_val;
+                            } else {
+                                log::error!(
+                                    "Worker: Mutex poisoned for source_states_shared[{}]!",
+                                    i
+                                );
                             }
                         }
-                    }
-                    Err(e) => {
-                        log::warn!("Worker: Error reading from source {}: {:?}. Attempting reopen.", i, e);
+                    } else {
+                        // unpack_state returned None (e.g., wrong ID, too short)
+                        log::warn!("Worker: Failed to unpack state from source {} (bytes read: {}) using format '{}'", i, bytes_read, source_format.name);
                         current_source_states[i] = None;
-                        if let Some(shared_state) = data.source_states_shared.get(i) {
-                            if let Ok(mut guard) = shared_state.lock() { *guard = 0; }
+                        if let Some(shared_state) = data.source_states_shared.get(slot) {
+                            if let Ok(mut guard) = shared_state.lock() {
+                                *guard = 0;
+                            } // Reset UI
                         }
-                        // Reopen logic using source_info.config
-                        log::debug!("Worker: Attempting to reopen source[{}]...", i);
-                        *device_opt = hidapi.open_serial(
-                            source_info.config.vendor_id,
-                            source_info.config.product_id,
-                            &source_info.config.serial_number,
-                        ).ok().and_then(|d| d.set_blocking_mode(false).ok().map(|_| d)); // Simplified reopen
-                        if device_opt.is_some() { log::info!("Worker: Reopen successful for source[{}].", i); }
-                        else { log::warn!("Worker: Reopen failed for source[{}].", i); }
                     }
                 }
-            } else {
-                // Device was not opened initially or failed reopen
-                current_source_states[i] = None;
-                if let Some(shared_state) = data.source_states_shared.get(i) {
-                    if let Ok(mut guard) = shared_state.lock() { *guard = 0; } // Reset UI state
+                Err(e) => {
+                    log::warn!(
+                        "Worker: Error reading from source {}: {}. Attempting reopen.",
+                        i,
+                        e
+                    );
+                    if let Some(trace) = recorder.as_deref_mut() {
+                        trace.record(TraceKind::SourceRead, i, Err(&e), None);
+                    }
+                    current_source_states[i] = None;
+                    if let Some(shared_state) = data.source_states_shared.get(slot) {
+                        if let Ok(mut guard) = shared_state.lock() {
+                            *guard = 0;
+                        }
+                    }
+                    outcome.failed_sources.push(i); // Reopened by the caller
                 }
             }
+        } else {
+            // Device was not opened initially or failed reopen
+            current_source_states[i] = None;
+            if let Some(shared_state) = data.source_states_shared.get(slot) {
+                if let Ok(mut guard) = shared_state.lock() {
+                    *guard = 0;
+                } // Reset UI state
+            }
         }
+    }
 
-        // --- 3. Calculate Final State based on Rules ---
-        let mut final_state: u16 = 0;
-        for bit_pos in 0..8u8 {
-            let mut relevant_values: Vec<bool> = Vec::new();
-            for (source_idx, state_opt) in current_source_states.iter().enumerate() {
-                if data.sources_info[source_idx].config.state_enabled[bit_pos as usize] {
-                    relevant_values.push(state_opt.map_or(false, |s| util::read_bit(s, bit_pos)));
-                }
+    // A slot matching several devices counts once in the rules, as what any
+    // of them has set, so AND/XOR don't depend on how many devices matched
+    let mut slot_states: Vec<(&SavedDevice, Option<u16>)> = Vec::new();
+    for (slot, shared_state) in data.source_states_shared.iter().enumerate() {
+        let devices: Vec<(&DeviceWorkerInfo, Option<u16>)> = data
+            .sources_info
+            .iter()
+            .zip(current_source_states.iter().copied())
+            .filter(|(info, _)| info.slot == slot)
+            .collect();
+        let config = match devices.first() {
+            Some((info, _)) => &info.config, // Every device of a slot has the slot's settings
+            None => continue,
+        };
+        let state = devices.iter().filter_map(|(_, state)| *state).reduce(|acc, s| acc | s);
+        if devices.len() > 1 {
+            if let Ok(mut guard) = shared_state.lock() {
+                *guard = state.unwrap_or(0);
             }
-            if !relevant_values.is_empty() {
-                let modifier = data.shift_modifiers[bit_pos as usize];
-                let result_bit = match modifier {
-                    crate::config::ShiftModifiers::OR => relevant_values.iter().any(|&v| v),
-                    crate::config::ShiftModifiers::AND => relevant_values.iter().all(|&v| v),
-                    crate::config::ShiftModifiers::XOR => relevant_values.iter().fold(false, |acc, &v| acc ^ v),
-                };
-                if result_bit { final_state |= 1 << bit_pos; }
+        }
+        slot_states.push((config, state));
+    }
+
+    // --- 3. Calculate Final State based on Rules ---
+    let mut final_state: u16 = 0;
+    for bit_pos in 0..8u8 {
+        let mut relevant_values: Vec<bool> = Vec::new();
+        for &(source_config, state_opt) in &slot_states {
+            if source_config.enabled && source_config.state_enabled[bit_pos as usize] {
+                relevant_values.push(state_opt.map_or(false, |s| util::read_bit(s, bit_pos)));
             }
         }
-        // Update shared final state for UI
-        if let Ok(mut guard) = data.final_shift_state_shared.lock() {
-            *guard = final_state;
+        if !relevant_values.is_empty() {
+            let modifier = data.shift_modifiers[bit_pos as usize];
+            let result_bit = match modifier {
+                crate::config::ShiftModifiers::OR => relevant_values.iter().any(|&v| v),
+                crate::config::ShiftModifiers::AND => relevant_values.iter().all(|&v| v),
+                crate::config::ShiftModifiers::XOR => {
+                    relevant_values.iter().fold(false, |acc, &v| acc ^ v)
+                }
+            };
+            if result_bit {
+                final_state |= 1 << bit_pos;
+            }
         }
-        // --- End Calculate Final State ---
+    }
+    // Update shared final state for UI
+    if let Ok(mut guard) = data.final_shift_state_shared.lock() {
+        *guard = final_state;
+    }
+    // --- End Calculate Final State ---
 
-        // --- 4. Write to Receiver Devices ---
-        for (i, device_opt) in receiver_devices.iter_mut().enumerate() {
-            if let Some(device) = device_opt {
-                let receiver_info = &data.receivers_info[i];
-                let receiver_format = receiver_info.format;
+    // --- 4. Write to Receiver Devices ---
+    for (i, device_opt) in receiver_devices.iter().enumerate() {
+        if let Some(device) = device_opt {
+            let receiver_info = &data.receivers_info[i];
+            let receiver_format = receiver_info.format;
 
-                // --- 4a. Send Zero State Report First ---
-                let zero_buffer_slice = receiver_format.pack_state(&mut write_buffer, 0);
-                if zero_buffer_slice.is_empty() { /* handle error */ continue; }
+            // --- 4a. Send Zero State Report First ---
+            let zero_buffer_slice = receiver_format.pack_state(&mut write_buffer, 0);
+            if zero_buffer_slice.is_empty() {
+                /* handle error */
+                continue;
+            }
 
-                log::trace!("Worker: Sending zero state reset ({} bytes) to receiver[{}] using format '{}'", receiver_format.total_size, i, receiver_format.name);
-                match device.send_feature_report(zero_buffer_slice) {
-                    Ok(_) => {
-                        log::trace!("Worker: Zero state sent successfully to receiver[{}].", i);
+            log::trace!(
+                "Worker: Sending zero state reset ({} bytes) to receiver[{}] using format '{}'",
+                receiver_format.total_size,
+                i,
+                receiver_format.name
+            );
+            let zero_result = device.send_feature_report(zero_buffer_slice);
+            if let Some(trace) = recorder.as_deref_mut() {
+                trace.record(
+                    TraceKind::ReceiverWrite,
+                    i,
+                    zero_result
+                        .as_ref()
+                        .map(|_| zero_buffer_slice)
+                        .map_err(String::as_str),
+                    Some(0),
+                );
+            }
+            match zero_result {
+                Ok(_) => {
+                    log::trace!("Worker: Zero state sent successfully to receiver[{}].", i);
 
-                        // --- 4b. If Zero Send OK, Prepare and Send Actual State ---
-                        let mut state_to_send = final_state; // Start with the globally calculated state
+                    // --- 4b. If Zero Send OK, Prepare and Send Actual State ---
+                    let mut state_to_send = final_state; // Start with the globally calculated state
 
-                        // Apply receiver's enabled mask
-                        for bit_pos in 0..8u8 {
-                            if !receiver_info.config.state_enabled[bit_pos as usize] {
-                                state_to_send &= !(1 << bit_pos);
-                            }
+                    // Apply receiver's enabled mask
+                    for bit_pos in 0..8u8 {
+                        if !receiver_info.config.state_enabled[bit_pos as usize] {
+                            state_to_send &= !(1 << bit_pos);
                         }
+                    }
 
-                        // --- Start: Read receiver's current state and merge ---
-                        let mut receiver_current_state: u16 = 0; // Default to 0 if read fails
-                        read_buffer[0] = receiver_format.report_id; // Set ID for reading receiver
+                    // --- Start: Read receiver's current state and merge ---
+                    let mut receiver_current_state: u16 = 0; // Default to 0 if read fails
+                    read_buffer[0] = receiver_format.report_id; // Set ID for reading receiver
 
-                        log::trace!("Worker: Reading current state from receiver[{}] before merge.", i);
-                        match device.get_feature_report(&mut read_buffer) {
-                            Ok(bytes_read) => {
-                                if let Some(current_state) = receiver_format.unpack_state(&read_buffer[0..bytes_read]) {
-                                    log::trace!("Worker: Receiver[{}] current unpacked state: {}", i, current_state);
-                                    receiver_current_state = current_state;
-                                } else {
-                                    log::warn!("Worker: Failed to unpack current state from receiver {} (bytes read: {}) using format '{}'. Merge will use 0.", i, bytes_read, receiver_format.name);
-                                }
+                    log::trace!(
+                        "Worker: Reading current state from receiver[{}] before merge.",
+                        i
+                    );
+                    match device.get_feature_report(&mut read_buffer) {
+                        Ok(bytes_read) => {
+                            let unpacked =
+                                receiver_format.unpack_state(&read_buffer[0..bytes_read]);
+                            if let Some(trace) = recorder.as_deref_mut() {
+                                trace.record(
+                                    TraceKind::ReceiverRead,
+                                    i,
+                                    Ok(&read_buffer[0..bytes_read]),
+                                    unpacked,
+                                );
+                            }
+                            if let Some(current_state) = unpacked {
+                                log::trace!(
+                                    "Worker: Receiver[{}] current unpacked state: {}",
+                                    i,
+                                    current_state
+                                );
+                                receiver_current_state = current_state;
+                            } else {
+                                log::warn!("Worker: Failed to unpack current state from receiver {} (bytes read: {}) using format '{}'. Merge will use 0.", i, bytes_read, receiver_format.name);
                             }
-                            Err(e_read) => {
-                                // Log error reading current state, but proceed with merge using 0
-                                log::warn!("Worker: Error reading current state from receiver[{}]: {:?}. Merge will use 0.", i, e_read);
-                                // Note: Don't attempt reopen here, as we are about to send anyway.
-                                // If send fails later, reopen will be attempted then.
+                        }
+                        Err(e_read) => {
+                            // Log error reading current state, but proceed with merge using 0
+                            log::warn!("Worker: Error reading current state from receiver[{}]: {}. Merge will use 0.", i, e_read);
+                            if let Some(trace) = recorder.as_deref_mut() {
+                                trace.record(TraceKind::ReceiverRead, i, Err(&e_read), None);
                             }
+                            // Note: Don't attempt reopen here, as we are about to send anyway.
+                            // If send fails later, reopen will be attempted then.
                         }
-                        state_to_send |= receiver_current_state; // Merge
-                        // --- End Read current state ---
+                    }
+                    state_to_send |= receiver_current_state; // Merge
+                                                             // --- End Read current state ---
 
-                        // Use pack_state to prepare the buffer slice with the potentially merged state
-                        let actual_buffer_slice = receiver_format.pack_state(
-                            &mut write_buffer,
-                            state_to_send, // Use the final (potentially merged) state
-                        );
+                    // Use pack_state to prepare the buffer slice with the potentially merged state
+                    let actual_buffer_slice = receiver_format.pack_state(
+                        &mut write_buffer,
+                        state_to_send, // Use the final (potentially merged) state
+                    );
 
-                        if actual_buffer_slice.is_empty() { /* handle pack error */ continue; }
+                    if actual_buffer_slice.is_empty() {
+                        /* handle pack error */
+                        continue;
+                    }
 
-                        log::debug!(
-                            "Worker: Attempting send final state to receiver[{}], state: {}, buffer ({} bytes): {:02X?}",
-                            i, state_to_send, receiver_format.total_size, actual_buffer_slice
-                        );
+                    log::debug!(
+                        "Worker: Attempting send final state to receiver[{}], state: {}, buffer ({} bytes): {:02X?}",
+                        i, state_to_send, receiver_format.total_size, actual_buffer_slice
+                    );
 
-                        // Send the actual calculated/merged state
-                        match device.send_feature_report(actual_buffer_slice) {
-                            Ok(_) => {
-                                log::debug!("Worker: Final state send to receiver[{}] successful.", i);
-                                // Update shared state for UI with the state we just sent
-                                if let Some(shared_state) = data.receiver_states_shared.get(i) {
-                                    if let Ok(mut guard) = shared_state.lock() {
-                                        *guard = state_to_send; // Update with the sent state
-                                    } else {
-                                        if let Some(shared_state) = data.receiver_states_shared.get(i) {
-                                            match shared_state.lock() {
-                                                Ok(mut guard) => *guard = 0,
-                                                Err(poisoned) => {
-                                                    log::error!("Mutex for receiver_states_shared[{}] poisoned! Recovering and resetting.", i);
-                                                    *poisoned.into_inner() = 0;
-                                                }
+                    // Send the actual calculated/merged state
+                    let send_result = device.send_feature_report(actual_buffer_slice);
+                    if let Some(trace) = recorder.as_deref_mut() {
+                        trace.record(
+                            TraceKind::ReceiverWrite,
+                            i,
+                            send_result
+                                .as_ref()
+                                .map(|_| actual_buffer_slice)
+                                .map_err(String::as_str),
+                            Some(state_to_send),
+                        );
+                    }
+                    match send_result {
+                        Ok(_) => {
+                            log::debug!("Worker: Final state send to receiver[{}] successful.", i);
+                            // Update shared state for UI with the state we just sent
+                            if let Some(shared_state) =
+                                data.receiver_states_shared.get(data.receivers_info[i].slot)
+                            {
+                                if let Ok(mut guard) = shared_state.lock() {
+                                    *guard = state_to_send; // Update with the sent state
+                                } else {
+                                    if let Some(shared_state) =
+                                        data.receiver_states_shared.get(data.receivers_info[i].slot)
+                                    {
+                                        match shared_state.lock() {
+                                            Ok(mut guard) => *guard = 0,
+                                            Err(poisoned) => {
+                                                log::error!("Mutex for receiver_states_shared[{}] poisoned! Recovering and resetting.", i);
+                                                *poisoned.into_inner() = 0;
                                             }
                                         }
                                     }
                                 }
                             }
-                            Err(e_actual) => {
-                                // ... (error handling, reopen logic for send failure) ...
-                                log::warn!("Worker: Error sending final state to receiver[{}]: {:?}", i, e_actual);
-                                if let Some(shared_state) = data.receiver_states_shared.get(i) {
-                                    match shared_state.lock() {
-                                        Ok(mut guard) => *guard = 0,
-                                        Err(poisoned) => {
-                                            log::error!("Mutex for receiver_states_shared[{}] poisoned! Recovering and resetting.", i);
-                                            *poisoned.into_inner() = 0;
-                                        }
+                        }
+                        Err(e_actual) => {
+                            // ... (error handling, reopen logic for send failure) ...
+                            log::warn!(
+                                "Worker: Error sending final state to receiver[{}]: {}",
+                                i,
+                                e_actual
+                            );
+                            if let Some(shared_state) =
+                                data.receiver_states_shared.get(data.receivers_info[i].slot)
+                            {
+                                match shared_state.lock() {
+                                    Ok(mut guard) => *guard = 0,
+                                    Err(poisoned) => {
+                                        log::error!("Mutex for receiver_states_shared[{}] poisoned! Recovering and resetting.", i);
+                                        *poisoned.into_inner() = 0;
                                     }
                                 }
-
-                                log::debug!("Worker: Attempting to reopen receiver[{}] after final-send failure...", i);
-                                *device_opt = hidapi.open(
-                                    data.receivers_info[i].config.vendor_id,
-                                    data.receivers_info[i].config.product_id,
-                                ).ok().and_then(|d| {
-                                    d.set_blocking_mode(false).ok()?;
-                                    Some(d)
-                                });
-
-                                if device_opt.is_none() {
-                                    log::warn!("Reopen failed for receiver {}.", i);
-                                } else {
-                                    log::info!("Reopen successful for receiver {}.", i);
-                                }
                             }
-                        } // End match send actual state
-                    } // End Ok for zero send
-                    Err(e_zero) => {
-                        // Handle error sending the zero state reset
-                        log::warn!("Worker: Error sending zero state reset to receiver[{}]: {:?}", i, e_zero);
-                        // Reset UI state, attempt reopen
-                        if let Some(shared_state) = data.receiver_states_shared.get(i) {
-                            if let Ok(mut guard) = shared_state.lock() { *guard = 0; }
+
+                            outcome.failed_receivers.push(i); // Reopened by the caller
                         }
-                        log::debug!("Worker: Attempting to reopen receiver[{}] after zero-send failure...", i);
-                        *device_opt = hidapi.open( data.receivers_info[i].config.vendor_id,
-                                                   data.receivers_info[i].config.product_id
-                        ).ok().and_then(|d| {
-                            d.set_blocking_mode(false).ok()?;
-                            Some(d)
-                        });
-                        if device_opt.is_none() {
-                            log::warn!("Reopen failed for receiver {}.", i);
-                        } else {
-                            log::info!("Reopen successful for receiver {}.", i);
+                    } // End match send actual state
+                } // End Ok for zero send
+                Err(e_zero) => {
+                    // Handle error sending the zero state reset
+                    log::warn!(
+                        "Worker: Error sending zero state reset to receiver[{}]: {}",
+                        i,
+                        e_zero
+                    );
+                    // Reset UI state, attempt reopen
+                    if let Some(shared_state) =
+                        data.receiver_states_shared.get(data.receivers_info[i].slot)
+                    {
+                        if let Ok(mut guard) = shared_state.lock() {
+                            *guard = 0;
                         }
-                    } // End Err for zero send
+                    }
+                    outcome.failed_receivers.push(i); // Reopened by the caller
+                } // End Err for zero send
+            }
+        } else {
+            // Device not open, reset UI state
+            if let Some(shared_state) = data.receiver_states_shared.get(data.receivers_info[i].slot)
+            {
+                if let Ok(mut guard) = shared_state.lock() {
+                    *guard = 0;
                 }
-            } else {
-                // Device not open, reset UI state
-                if let Some(shared_state) = data.receiver_states_shared.get(i) {
-                    if let Ok(mut guard) = shared_state.lock() { *guard = 0; }
+            }
+        }
+    }
+
+    if let Some(trace) = recorder {
+        trace.end_cycle(final_state);
+    }
+    outcome.final_state = final_state;
+    outcome
+}
+
+// The core worker loop logic
+fn run_hid_worker_loop(hidapi: HidApi, mut data: WorkerData) {
+    log::info!("HID worker loop starting.");
+
+    // --- Device Opening ---
+    // Open sources and receivers, keeping track of which ones succeeded
+    let mut source_devices =
+        open_hid_devices(&hidapi, &mut data.sources_info, false, &data.descriptors);
+    let mut receiver_devices =
+        open_hid_devices(&hidapi, &mut data.receivers_info, true, &data.descriptors);
+
+    // Buffer for the zero reset sent when stopping
+    let mut write_buffer = [0u8; MAX_REPORT_SIZE];
+
+    // --- Trace Recording ---
+    let mut recorder = data.trace_path.as_ref().and_then(|path| {
+        match TraceWriter::create(path, trace_settings(&data)) {
+            Ok(writer) => {
+                info!("Worker: Recording a trace to {}.", path.display());
+                Some(writer)
+            }
+            Err(e) => {
+                error!("Worker: Not recording a trace: {}", e);
+                None
+            }
+        }
+    });
+
+    // --- Virtual Outputs ---
+    let mut joystick = open_virtual_joystick(&data);
+    let mut keyboard = open_virtual_keyboard(&data);
+    let mut previous_final_state: u16 = 0; // For key binding edge detection
+
+    // --- Hooks ---
+    let hooks = HookRunner::new(data.hooks.clone());
+    let mut source_online: Vec<bool> = source_devices.iter().map(Option::is_some).collect();
+    let mut receiver_online: Vec<bool> = receiver_devices.iter().map(Option::is_some).collect();
+    hooks.fire(HookEvent::WorkerStarted, &HookContext::default());
+
+    let run_state = data.run_state.clone(); // Own handle so `data` stays mutable
+    let &(ref run_lock, ref _run_cvar) = &*run_state;
+
+    loop {
+        // --- Check Run State ---
+        let should_run = {
+            // Scope for mutex guard
+            match run_lock.lock() {
+                Ok(guard) => *guard,
+                Err(_poisoned) => {
+                    error!("Run state mutex poisoned in worker loop!");
+                    false
                 }
             }
+        };
+
+        if !should_run {
+            info!("Stop signal received, exiting worker loop.");
+            break; // Exit the loop
+        }
+
+        // --- Apply Live Rule/Mask Edits (latest snapshot wins) ---
+        if let Some(settings) = data.live_updates.try_iter().last() {
+            apply_live_settings(&mut data, settings);
+            if let Some(trace) = recorder.as_mut() {
+                trace.settings(trace_settings(&data));
+            }
+        }
+
+        // --- Read Sources, Combine and Write Receivers ---
+        let outcome = run_cycle(&data, &source_devices, &receiver_devices, recorder.as_mut());
+        let final_state = outcome.final_state;
+
+        // Reopen what failed this cycle; the next cycle uses the new handles
+        for &i in &outcome.failed_sources {
+            log::debug!("Worker: Attempting to reopen source[{}]...", i);
+            source_devices[i] = reopen_slot_device(
+                &hidapi,
+                &mut data.sources_info[i],
+                i,
+                false,
+                &data.descriptors,
+            );
+            if source_devices[i].is_some() {
+                log::info!("Worker: Reopen successful for source[{}].", i);
+            } else {
+                log::warn!("Worker: Reopen failed for source[{}].", i);
+            }
+        }
+        fire_offline_hooks(
+            &hooks,
+            HookEvent::SourceOffline,
+            &data.sources_info,
+            &source_devices,
+            &mut source_online,
+        );
+
+        for &i in &outcome.failed_receivers {
+            log::debug!("Worker: Attempting to reopen receiver[{}]...", i);
+            receiver_devices[i] = reopen_slot_device(
+                &hidapi,
+                &mut data.receivers_info[i],
+                i,
+                true,
+                &data.descriptors,
+            );
+            if receiver_devices[i].is_none() {
+                log::warn!("Reopen failed for receiver {}.", i);
+            } else {
+                log::info!("Reopen successful for receiver {}.", i);
+            }
+        }
+
+        fire_offline_hooks(
+            &hooks,
+            HookEvent::ReceiverOffline,
+            &data.receivers_info,
+            &receiver_devices,
+            &mut receiver_online,
+        );
+
+        // --- 5. Mirror State to Virtual Joystick ---
+        if let Some(js) = joystick.as_mut() {
+            let mirrored_state = match data.virtual_joystick.source {
+                JoystickSource::Result => final_state,
+                JoystickSource::Receiver(idx) => data
+                    .receiver_states_shared
+                    .get(idx)
+                    .and_then(|shared_state| shared_state.lock().ok().map(|guard| *guard))
+                    .unwrap_or(0),
+            };
+            if let Err(e) = js.update(mirrored_state) {
+                log::warn!("Worker: Failed to update virtual joystick: {}", e);
+            }
+        }
+
+        // --- 6. Type Key Bindings on Result Transitions ---
+        if let Some(kb) = keyboard.as_mut() {
+            if let Err(e) = kb.handle_transition(previous_final_state, final_state) {
+                log::warn!("Worker: Failed to send key binding: {}", e);
+            }
+        }
+
+        // --- 7. Run Hooks on Result Change ---
+        if final_state != previous_final_state {
+            hooks.fire(
+                HookEvent::ResultChanged,
+                &HookContext {
+                    old_state: previous_final_state,
+                    new_state: final_state,
+                    ..Default::default()
+                },
+            );
         }
+        previous_final_state = final_state;
 
         // --- Sleep ---
         thread::sleep(Duration::from_millis(WORKER_SLEEP_MS));
@@ -512,23 +1277,346 @@ fn run_hid_worker_loop(hidapi: HidApi, data: WorkerData) {
 
             // --- 4a. Send Zero State Report First ---
             let zero_buffer_slice = receiver_format.pack_state(&mut write_buffer, 0);
-            if zero_buffer_slice.is_empty() { /* handle error */ continue; }
+            if zero_buffer_slice.is_empty() {
+                /* handle error */
+                continue;
+            }
 
-            log::trace!("Worker: Sending zero state reset ({} bytes) to receiver[{}] using format '{}'", receiver_format.total_size, i, receiver_format.name);
+            log::trace!(
+                "Worker: Sending zero state reset ({} bytes) to receiver[{}] using format '{}'",
+                receiver_format.total_size,
+                i,
+                receiver_format.name
+            );
             match device.send_feature_report(zero_buffer_slice) {
                 Ok(_) => {
                     log::trace!("Worker: Zero state sent successfully to receiver[{}].", i);
-                    if let Some(shared_state) = data.receiver_states_shared.get(i) {
-                        if let Ok(mut guard) = shared_state.lock() { *guard = 0; }
+                    if let Some(shared_state) =
+                        data.receiver_states_shared.get(data.receivers_info[i].slot)
+                    {
+                        if let Ok(mut guard) = shared_state.lock() {
+                            *guard = 0;
+                        }
                     }
                 }
                 Err(_e_actual) => {
-                    if let Some(shared_state) = data.receiver_states_shared.get(i) {
-                        if let Ok(mut guard) = shared_state.lock() { *guard = 0; }
+                    if let Some(shared_state) =
+                        data.receiver_states_shared.get(data.receivers_info[i].slot)
+                    {
+                        if let Ok(mut guard) = shared_state.lock() {
+                            *guard = 0;
+                        }
                     }
                 }
             }
         }
     }
+    // Release every virtual button before the device is destroyed
+    if let Some(js) = joystick.as_mut() {
+        if let Err(e) = js.update(0) {
+            log::warn!("Worker: Failed to release virtual joystick buttons: {}", e);
+        }
+    }
+    hooks.fire(
+        HookEvent::WorkerStopped,
+        &HookContext {
+            old_state: previous_final_state,
+            ..Default::default()
+        },
+    );
     log::info!("Worker thread cleanup complete. Exiting.");
 }
+
+// What a trace records about the slots the worker opened
+fn trace_settings(data: &WorkerData) -> TraceSettings {
+    let slot = |info: &DeviceWorkerInfo| TraceSlot {
+        slot: info.slot,
+        label: info.label.clone(),
+        report_size: info.format.total_size,
+        is_virtual: info.config.is_virtual(),
+        enabled: info.config.enabled,
+        state_enabled: info.config.state_enabled,
+    };
+    TraceSettings {
+        shift_modifiers: data.shift_modifiers,
+        sources: data.sources_info.iter().map(slot).collect(),
+        receivers: data.receivers_info.iter().map(slot).collect(),
+    }
+}
+
+/// Outcome of replaying a trace with `replay_trace`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplayReport {
+    pub cycles: usize,
+    pub mismatches: Vec<String>, // Receiver writes or results that differ from the trace
+}
+
+impl ReplayReport {
+    pub fn passed(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+impl std::fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.passed() {
+            return write!(
+                f,
+                "Replayed {} cycles; every receiver write matches.",
+                self.cycles
+            );
+        }
+        write!(
+            f,
+            "Replayed {} cycles with {} mismatches:",
+            self.cycles,
+            self.mismatches.len()
+        )?;
+        for mismatch in &self.mismatches {
+            write!(f, "\n  {}", mismatch)?;
+        }
+        Ok(())
+    }
+}
+
+// Simulated device for replay: answers reads with the reports from the trace
+// and keeps what the worker sends
+#[derive(Default)]
+struct ReplayDevice {
+    reads: RefCell<VecDeque<Result<Vec<u8>, String>>>,
+    write_errors: RefCell<VecDeque<Option<String>>>, // Recorded outcome of each write
+    writes: RefCell<Vec<Vec<u8>>>,
+}
+
+impl FeatureReports for ReplayDevice {
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize, String> {
+        match self.reads.borrow_mut().pop_front() {
+            Some(Ok(bytes)) => {
+                let len = bytes.len().min(buf.len());
+                buf[..len].copy_from_slice(&bytes[..len]);
+                Ok(len)
+            }
+            Some(Err(e)) => Err(e),
+            None => Err("the trace has no more reads for this device".to_string()),
+        }
+    }
+
+    fn send_feature_report(&self, data: &[u8]) -> Result<(), String> {
+        self.writes.borrow_mut().push(data.to_vec());
+        match self.write_errors.borrow_mut().pop_front() {
+            Some(Some(e)) => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Worker data for replaying with the recorded settings; nothing is opened
+fn replay_worker_data(
+    settings: &TraceSettings,
+    live_updates: Receiver<LiveSettings>,
+) -> Result<WorkerData, String> {
+    let info = |slot: &TraceSlot| -> Result<DeviceWorkerInfo, String> {
+        let format = util::format_for_report_size(slot.report_size)
+            .ok_or_else(|| format!("No report format is {} bytes long.", slot.report_size))?;
+        Ok(DeviceWorkerInfo {
+            config: SavedDevice {
+                enabled: slot.enabled,
+                state_enabled: slot.state_enabled,
+                kind: if slot.is_virtual {
+                    SourceKind::Virtual
+                } else {
+                    SourceKind::Hid
+                },
+                ..Default::default()
+            },
+            format,
+            label: slot.label.clone(),
+            product: slot.label.clone(),
+            path: None,
+            slot: slot.slot,
+        })
+    };
+    let shared_states = |slots: &[TraceSlot]| -> Vec<SharedDeviceState> {
+        let count = slots.iter().map(|s| s.slot + 1).max().unwrap_or(0);
+        (0..count).map(|_| Arc::new(Mutex::new(0))).collect()
+    };
+    Ok(WorkerData {
+        run_state: Arc::new((Mutex::new(true), Condvar::new())),
+        sources_info: settings
+            .sources
+            .iter()
+            .map(info)
+            .collect::<Result<_, _>>()?,
+        receivers_info: settings
+            .receivers
+            .iter()
+            .map(info)
+            .collect::<Result<_, _>>()?,
+        shift_modifiers: settings.shift_modifiers,
+        virtual_joystick: VirtualJoystickConfig::default(),
+        key_bindings: Vec::new(),
+        hooks: Vec::new(),
+        source_states_shared: shared_states(&settings.sources),
+        receiver_states_shared: shared_states(&settings.receivers),
+        final_shift_state_shared: Arc::new(Mutex::new(0)),
+        descriptors: SharedDescriptors::default(),
+        live_updates,
+        trace_path: None,
+    })
+}
+
+// Runs one recorded cycle through `run_cycle` and compares the receiver
+// writes and the result with the trace
+fn replay_cycle(
+    data: &WorkerData,
+    events: &[&TraceEvent],
+    expected_state: Option<u16>,
+    cycle: usize,
+    mismatches: &mut Vec<String>,
+) -> Result<(), String> {
+    let of =
+        |kind: TraceKind, i: usize| events.iter().filter(move |e| e.kind == kind && e.slot == i);
+    let report = |event: &TraceEvent| -> Result<Result<Vec<u8>, String>, String> {
+        match &event.error {
+            Some(e) => Ok(Err(e.clone())),
+            None => event
+                .report_bytes()
+                .map(Ok)
+                .map_err(|e| format!("Cycle {}: {}", cycle + 1, e)),
+        }
+    };
+
+    let mut source_devices: Vec<Option<ReplayDevice>> = Vec::new();
+    for (i, info) in data.sources_info.iter().enumerate() {
+        if info.config.is_virtual() {
+            // The worker reads virtual sources from their shared state
+            let state = of(TraceKind::SourceRead, i)
+                .find_map(|e| e.state)
+                .unwrap_or(0);
+            if let Some(shared_state) = data.source_states_shared.get(info.slot) {
+                if let Ok(mut guard) = shared_state.lock() {
+                    *guard = state;
+                }
+            }
+            source_devices.push(None);
+            continue;
+        }
+        let reads = of(TraceKind::SourceRead, i)
+            .map(|e| report(e))
+            .collect::<Result<VecDeque<_>, _>>()?;
+        source_devices.push((!reads.is_empty()).then(|| ReplayDevice {
+            reads: RefCell::new(reads),
+            ..Default::default()
+        }));
+    }
+
+    let mut receiver_devices: Vec<Option<ReplayDevice>> = Vec::new();
+    let mut expected_writes: Vec<Vec<Vec<u8>>> = Vec::new();
+    for i in 0..data.receivers_info.len() {
+        let reads = of(TraceKind::ReceiverRead, i)
+            .map(|e| report(e))
+            .collect::<Result<VecDeque<_>, _>>()?;
+        let writes: Vec<&TraceEvent> = of(TraceKind::ReceiverWrite, i).copied().collect();
+        let mut expected = Vec::new();
+        for write in &writes {
+            if write.error.is_none() {
+                expected.push(
+                    write
+                        .report_bytes()
+                        .map_err(|e| format!("Cycle {}: {}", cycle + 1, e))?,
+                );
+            }
+        }
+        expected_writes.push(expected);
+        receiver_devices.push(
+            (!reads.is_empty() || !writes.is_empty()).then(|| ReplayDevice {
+                reads: RefCell::new(reads),
+                write_errors: RefCell::new(writes.iter().map(|w| w.error.clone()).collect()),
+                ..Default::default()
+            }),
+        );
+    }
+
+    let outcome = run_cycle(data, &source_devices, &receiver_devices, None);
+
+    let hex = |writes: &[Vec<u8>]| {
+        writes
+            .iter()
+            .map(|w| crate::report_console::format_hex(w))
+            .collect::<Vec<_>>()
+            .join(" | ")
+    };
+    for (i, device) in receiver_devices.iter().enumerate() {
+        let written: Vec<Vec<u8>> = match device {
+            Some(device) => {
+                // Writes the trace recorded as failed don't count on either side
+                let errors: Vec<Option<String>> = of(TraceKind::ReceiverWrite, i)
+                    .map(|e| e.error.clone())
+                    .collect();
+                device
+                    .writes
+                    .borrow()
+                    .iter()
+                    .enumerate()
+                    .filter(|(n, _)| errors.get(*n).is_none_or(Option::is_none))
+                    .map(|(_, w)| w.clone())
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+        if written != expected_writes[i] {
+            mismatches.push(format!(
+                "Cycle {}: receiver {} was sent [{}], the trace has [{}]",
+                cycle + 1,
+                i,
+                hex(&written),
+                hex(&expected_writes[i])
+            ));
+        }
+    }
+    if let Some(expected) = expected_state.filter(|&expected| expected != outcome.final_state) {
+        mismatches.push(format!(
+            "Cycle {}: result {:08b}, the trace has {:08b}",
+            cycle + 1,
+            outcome.final_state,
+            expected
+        ));
+    }
+    Ok(())
+}
+
+/// Replays a recorded trace: a simulated backend answers the worker's reads
+/// with the recorded reports, and every receiver write and cycle result is
+/// compared with the recording. A settings line starts over with the slots
+/// and rules it describes, since each worker start (and live edit) writes one.
+pub fn replay_trace(trace: &Trace) -> Result<ReplayReport, String> {
+    let (_sender, live_updates) = mpsc::channel();
+    let mut data = match trace.lines.first() {
+        Some(TraceLine::Settings(settings)) => replay_worker_data(settings, live_updates)?,
+        _ => return Err("The trace doesn't start with the worker settings.".to_string()),
+    };
+    let mut report = ReplayReport::default();
+    let mut events: Vec<&TraceEvent> = Vec::new();
+    for line in &trace.lines[1..] {
+        match line {
+            TraceLine::Settings(settings) => {
+                let (_sender, live_updates) = mpsc::channel();
+                data = replay_worker_data(settings, live_updates)?;
+                events.clear(); // The worker stopped mid-cycle
+            }
+            TraceLine::Event(event) if event.kind == TraceKind::Cycle => {
+                replay_cycle(
+                    &data,
+                    &events,
+                    event.state,
+                    report.cycles,
+                    &mut report.mismatches,
+                )?;
+                report.cycles += 1;
+                events.clear();
+            }
+            TraceLine::Event(event) => events.push(event),
+        }
+    }
+    Ok(report)
+}
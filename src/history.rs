@@ -0,0 +1,192 @@
+use crate::config::{ConfigData, ModifiersArray};
+use crate::device::SavedDevice;
+use log::debug;
+
+/// Number of edits kept for undo. The oldest step is dropped beyond this.
+pub const MAX_UNDO_STEPS: usize = 100;
+
+/// The part of the config covered by undo/redo: slot devices, masks and rules.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoutingSnapshot {
+    pub sources: Vec<SavedDevice>,
+    pub receivers: Vec<SavedDevice>,
+    pub shift_modifiers: ModifiersArray,
+}
+
+impl RoutingSnapshot {
+    pub fn from_config(data: &ConfigData) -> Self {
+        Self {
+            sources: data.sources.clone(),
+            receivers: data.receivers.clone(),
+            shift_modifiers: data.shift_modifiers,
+        }
+    }
+
+    pub fn apply_to(&self, data: &mut ConfigData) {
+        data.sources = self.sources.clone();
+        data.receivers = self.receivers.clone();
+        data.shift_modifiers = self.shift_modifiers;
+    }
+
+    // Compares with the live config without cloning it
+    fn matches(&self, data: &ConfigData) -> bool {
+        self.sources == data.sources
+            && self.receivers == data.receivers
+            && self.shift_modifiers == data.shift_modifiers
+    }
+
+    /// True if both snapshots use the same devices in the same slots, so
+    /// switching between them only changes what the worker takes live.
+    pub fn same_devices(&self, other: &RoutingSnapshot) -> bool {
+        let devices = |slots: &[SavedDevice]| -> Vec<SavedDevice> {
+            slots.iter().map(SavedDevice::device_settings).collect()
+        };
+        devices(&self.sources) == devices(&other.sources)
+            && devices(&self.receivers) == devices(&other.receivers)
+    }
+}
+
+/// Undo/redo stacks of routing snapshots.
+///
+/// Edits aren't reported one by one; `record` is called once per frame and
+/// turns every difference from the previous frame into an undo step.
+#[derive(Debug, Default)]
+pub struct EditHistory {
+    undo: Vec<RoutingSnapshot>,
+    redo: Vec<RoutingSnapshot>,
+    current: Option<RoutingSnapshot>,
+}
+
+impl EditHistory {
+    /// Notes the routing as it is now. If it changed since the last call, the
+    /// previous routing becomes an undo step and the redo stack is dropped.
+    pub fn record(&mut self, data: &ConfigData) {
+        match &self.current {
+            Some(current) if current.matches(data) => {}
+            Some(_) => {
+                let previous = self.current.replace(RoutingSnapshot::from_config(data));
+                self.undo.extend(previous);
+                if self.undo.len() > MAX_UNDO_STEPS {
+                    self.undo.remove(0);
+                }
+                self.redo.clear();
+                debug!("Recorded undo step {}.", self.undo.len());
+            }
+            None => self.current = Some(RoutingSnapshot::from_config(data)),
+        }
+    }
+
+    /// Forgets all steps, e.g. after switching to another profile.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.current = None;
+    }
+
+    /// The routing `undo` would go back to.
+    pub fn undo_target(&self) -> Option<&RoutingSnapshot> {
+        self.undo.last()
+    }
+
+    /// The routing `redo` would go forward to.
+    pub fn redo_target(&self) -> Option<&RoutingSnapshot> {
+        self.redo.last()
+    }
+
+    /// Restores the previous routing into `data`. Returns false if there is nothing to undo.
+    pub fn undo(&mut self, data: &mut ConfigData) -> bool {
+        let target = match self.undo.pop() {
+            Some(target) => target,
+            None => return false,
+        };
+        self.redo.push(RoutingSnapshot::from_config(data));
+        target.apply_to(data);
+        self.current = Some(target);
+        true
+    }
+
+    /// Reapplies the last undone routing. Returns false if there is nothing to redo.
+    pub fn redo(&mut self, data: &mut ConfigData) -> bool {
+        let target = match self.redo.pop() {
+            Some(target) => target,
+            None => return false,
+        };
+        self.undo.push(RoutingSnapshot::from_config(data));
+        target.apply_to(data);
+        self.current = Some(target);
+        true
+    }
+}
+
+// Undo/redo, save and revert
+impl crate::ShiftTool {
+    /// True if the config differs from what was last loaded or saved.
+    pub(crate) fn has_unsaved_changes(&self) -> bool {
+        self.config.data != self.saved_data
+    }
+
+    /// True while the broken config file found at startup is still in place.
+    pub(crate) fn config_left_broken(&self) -> bool {
+        self.config_recovery.as_ref().is_some_and(|recovery| recovery.left_in_place())
+    }
+
+    /// Writes the config and makes it the new baseline for Revert.
+    pub(crate) fn save_config_now(&mut self) -> Result<(), String> {
+        if self.config_left_broken() {
+            return Err(format!(
+                "{} could not be read and is left untouched; fix or remove it first",
+                self.config.path.display()
+            ));
+        }
+        crate::config_file::save_config(&self.config, self.config_base.as_ref())?;
+        self.saved_data = self.config.data.clone();
+        log::info!("Configuration saved.");
+        Ok(())
+    }
+
+    /// Goes back to the last saved config. The revert itself can be undone.
+    pub(crate) fn revert_config(&mut self) {
+        self.config.data = self.saved_data.clone();
+        self.sync_slot_states();
+        self.process_watcher.set_rules(self.config.data.game_rules.clone());
+        log::info!("Reverted unsaved changes.");
+    }
+
+    /// Undo is allowed while running only if it leaves the devices alone.
+    pub(crate) fn can_undo(&self, thread_running: bool) -> bool {
+        self.history_step_allowed(self.edit_history.undo_target(), thread_running)
+    }
+
+    pub(crate) fn can_redo(&self, thread_running: bool) -> bool {
+        self.history_step_allowed(self.edit_history.redo_target(), thread_running)
+    }
+
+    fn history_step_allowed(&self, target: Option<&RoutingSnapshot>, thread_running: bool) -> bool {
+        match target {
+            Some(target) => {
+                !thread_running || target.same_devices(&RoutingSnapshot::from_config(&self.config.data))
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn handle_undo(&mut self) {
+        if self.edit_history.undo(&mut self.config.data) {
+            self.sync_slot_states();
+        }
+    }
+
+    pub(crate) fn handle_redo(&mut self) {
+        if self.edit_history.redo(&mut self.config.data) {
+            self.sync_slot_states();
+        }
+    }
+
+    /// Resizes the per-slot state tracking to match the configured slots.
+    pub(crate) fn sync_slot_states(&mut self) {
+        let num_sources = self.config.data.sources.len();
+        let num_receivers = self.config.data.receivers.len();
+        self.source_states.resize_with(num_sources, Default::default);
+        self.receiver_states.resize_with(num_receivers, Default::default);
+    }
+}
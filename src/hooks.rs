@@ -0,0 +1,135 @@
+use crate::config::{HookConfig, HookEvent};
+use crate::util;
+use log::{debug, error, warn};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How often a running hook is polled for completion
+const HOOK_POLL_MS: u64 = 50;
+
+/// Details about an event, exported to hook commands as environment variables.
+#[derive(Debug, Clone, Default)]
+pub struct HookContext {
+    pub old_state: u16,
+    pub new_state: u16,
+    pub slot: Option<usize>,   // 0-based source/receiver slot, for device events
+    pub serial_number: String, // Serial of the device involved, for device events
+    pub device_name: String,   // Nickname (or product name) of that device
+}
+
+impl HookContext {
+    /// Builds the environment passed to the hook command.
+    ///
+    /// `SHIFT_TOOL_CHANGED_BITS` lists the UI names of the bits that differ
+    /// between the old and new state, e.g. `1,DTNT`.
+    pub fn env_vars(&self, event: HookEvent) -> Vec<(String, String)> {
+        let changed = self.old_state ^ self.new_state;
+        let changed_bits: Vec<String> = (0..8u8)
+            .filter(|bit| util::read_bit(changed, *bit))
+            .map(util::bit_name)
+            .collect();
+
+        vec![
+            ("SHIFT_TOOL_EVENT".to_string(), event.to_string()),
+            ("SHIFT_TOOL_OLD_STATE".to_string(), self.old_state.to_string()),
+            ("SHIFT_TOOL_NEW_STATE".to_string(), self.new_state.to_string()),
+            ("SHIFT_TOOL_CHANGED_BITS".to_string(), changed_bits.join(",")),
+            (
+                "SHIFT_TOOL_SLOT".to_string(),
+                self.slot.map(|s| (s + 1).to_string()).unwrap_or_default(),
+            ),
+            ("SHIFT_TOOL_DEVICE_SERIAL".to_string(), self.serial_number.clone()),
+            ("SHIFT_TOOL_DEVICE_NAME".to_string(), self.device_name.clone()),
+        ]
+    }
+}
+
+/// Launches the configured hook commands for worker events.
+///
+/// Every command runs on its own thread so a slow or hanging script never
+/// stalls the HID loop; commands exceeding their timeout are killed.
+pub(crate) struct HookRunner {
+    hooks: Vec<HookConfig>,
+}
+
+impl HookRunner {
+    pub fn new(hooks: Vec<HookConfig>) -> Self {
+        Self { hooks }
+    }
+
+    /// Starts every hook registered for `event`. Returns immediately.
+    pub fn fire(&self, event: HookEvent, context: &HookContext) {
+        for hook in self.hooks.iter().filter(|h| h.event == event) {
+            if hook.command.trim().is_empty() {
+                continue;
+            }
+            let hook = hook.clone();
+            let env = context.env_vars(event);
+            thread::spawn(move || run_hook(&hook, &env));
+        }
+    }
+}
+
+// Builds the platform shell invocation for a hook command
+fn shell_command(command: &str) -> Command {
+    #[cfg(target_os = "windows")]
+    {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C").arg(command);
+        cmd
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(command);
+        cmd
+    }
+}
+
+// Runs a hook to completion or until its timeout expires
+fn run_hook(hook: &HookConfig, env: &[(String, String)]) {
+    debug!("Running {} hook: {}", hook.event, hook.command);
+    let child = shell_command(&hook.command)
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .stdin(Stdio::null())
+        .spawn();
+
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            error!("Failed to start {} hook '{}': {}", hook.event, hook.command, e);
+            return;
+        }
+    };
+
+    let deadline = Instant::now() + Duration::from_millis(hook.timeout_ms);
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => {
+                debug!("{} hook '{}' finished.", hook.event, hook.command);
+                return;
+            }
+            Ok(Some(status)) => {
+                warn!("{} hook '{}' exited with {}", hook.event, hook.command, status);
+                return;
+            }
+            Ok(None) if Instant::now() >= deadline => {
+                warn!(
+                    "{} hook '{}' exceeded its {} ms timeout. Killing it.",
+                    hook.event, hook.command, hook.timeout_ms
+                );
+                if let Err(e) = child.kill() {
+                    error!("Failed to kill {} hook '{}': {}", hook.event, hook.command, e);
+                }
+                let _ = child.wait(); // Reap the process
+                return;
+            }
+            Ok(None) => thread::sleep(Duration::from_millis(HOOK_POLL_MS)),
+            Err(e) => {
+                error!("Failed to wait for {} hook '{}': {}", hook.event, hook.command, e);
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use crate::config::VirtualHotkey;
+use crate::virtual_source::VirtualCommand;
+use log::{debug, warn};
+#[cfg(target_os = "linux")]
+use log::info;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+// How often a keyboard thread checks whether it should stop
+#[cfg(target_os = "linux")]
+const HOTKEY_POLL_MS: i32 = 100;
+
+/// Parses a hotkey into its chord of Linux key codes and the command it runs.
+///
+/// The keys use the key binding syntax but must be a single chord, e.g.
+/// `"Ctrl+Shift+F1"`; the command uses the control port protocol, e.g.
+/// `"toggle 2 DTNT"`.
+pub fn parse_hotkey(hotkey: &VirtualHotkey) -> Result<(Vec<u16>, VirtualCommand), String> {
+    let mut chords = crate::uinput::parse_key_sequence(&hotkey.keys)?;
+    if chords.len() != 1 {
+        return Err(format!("'{}' is a macro; a hotkey is a single chord", hotkey.keys.trim()));
+    }
+    let command = VirtualCommand::parse(&hotkey.command)?;
+    Ok((chords.remove(0), command))
+}
+
+/// Tracks the keys held on one keyboard and fires a hotkey when the last key
+/// of its chord goes down. Holding the chord (key repeat) fires it once.
+pub struct HotkeyMatcher {
+    hotkeys: Vec<(Vec<u16>, VirtualCommand)>,
+    pressed: HashSet<u16>,
+}
+
+impl HotkeyMatcher {
+    pub fn new(hotkeys: Vec<(Vec<u16>, VirtualCommand)>) -> Self {
+        Self { hotkeys, pressed: HashSet::new() }
+    }
+
+    /// Feeds one key event (value 1 = press, 0 = release, 2 = repeat) and
+    /// returns the commands of the hotkeys it completed.
+    pub fn key_event(&mut self, code: u16, value: i32) -> Vec<VirtualCommand> {
+        match value {
+            0 => {
+                self.pressed.remove(&code);
+                return Vec::new();
+            }
+            1 => {
+                self.pressed.insert(code);
+            }
+            _ => return Vec::new(),
+        }
+        self.hotkeys
+            .iter()
+            .filter(|(chord, _)| chord.contains(&code) && chord.iter().all(|key| self.pressed.contains(key)))
+            .map(|(_, command)| *command)
+            .collect()
+    }
+
+    /// Key codes any of the hotkeys uses.
+    pub fn keys(&self) -> HashSet<u16> {
+        self.hotkeys.iter().flat_map(|(chord, _)| chord.iter().copied()).collect()
+    }
+}
+
+/// Global hotkeys for virtual sources.
+///
+/// On Linux every keyboard under /dev/input the user may read is watched on
+/// a thread of its own, so hotkeys work while a game has focus. Fired
+/// commands are queued and applied on the UI thread by
+/// `ShiftTool::poll_hotkeys`. The threads are stopped and joined, closing
+/// their keyboards, when the hotkeys are replaced or the listener dropped.
+#[derive(Default)]
+pub struct HotkeyListener {
+    commands: Option<Receiver<VirtualCommand>>,
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl HotkeyListener {
+    /// Starts watching for `hotkeys`, replacing any earlier set. Hotkeys that
+    /// don't parse are skipped with a warning.
+    pub fn start(&mut self, hotkeys: &[VirtualHotkey]) {
+        self.stop_threads();
+        self.commands = None;
+
+        let parsed: Vec<(Vec<u16>, VirtualCommand)> = hotkeys
+            .iter()
+            .filter_map(|hotkey| match parse_hotkey(hotkey) {
+                Ok(parsed) => Some(parsed),
+                Err(e) => {
+                    warn!("Skipping hotkey '{}' -> '{}': {}", hotkey.keys, hotkey.command, e);
+                    None
+                }
+            })
+            .collect();
+        if parsed.is_empty() {
+            return;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        self.commands = Some(receiver);
+        self.watch_keyboards(parsed, sender);
+    }
+
+    // Ends the keyboard threads of the previous start and waits for them
+    fn stop_threads(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+        self.stop = Arc::new(AtomicBool::new(false));
+    }
+
+    #[cfg(target_os = "linux")]
+    fn watch_keyboards(&mut self, hotkeys: Vec<(Vec<u16>, VirtualCommand)>, sender: Sender<VirtualCommand>) {
+        let keys = HotkeyMatcher::new(hotkeys.clone()).keys();
+        for (path, device) in evdev::enumerate() {
+            // Skip our own virtual keyboard and devices without the hotkey keys
+            if device.name() == Some(crate::uinput::KEYBOARD_NAME) {
+                continue;
+            }
+            let has_keys = device
+                .supported_keys()
+                .is_some_and(|supported| keys.iter().all(|code| supported.contains(evdev::Key::new(*code))));
+            if !has_keys {
+                continue;
+            }
+            debug!("Watching {} ({}) for hotkeys.", path.display(), device.name().unwrap_or("unnamed"));
+            let matcher = HotkeyMatcher::new(hotkeys.clone());
+            let (sender, stop) = (sender.clone(), self.stop.clone());
+            self.threads.push(std::thread::spawn(move || watch_keyboard(device, matcher, sender, stop)));
+        }
+        if self.threads.is_empty() {
+            warn!("No keyboard could be opened for hotkeys; reading /dev/input needs the 'input' group (or root).");
+        } else {
+            info!("Hotkeys active on {} keyboard(s).", self.threads.len());
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn watch_keyboards(&mut self, _hotkeys: Vec<(Vec<u16>, VirtualCommand)>, _sender: Sender<VirtualCommand>) {
+        warn!("Hotkeys require Linux evdev; use the control port with a hotkey tool instead.");
+    }
+}
+
+impl Drop for HotkeyListener {
+    fn drop(&mut self) {
+        self.stop_threads();
+    }
+}
+
+// Reads key events until the listener is stopped or the keyboard goes away.
+// The device is non-blocking and polled with a timeout, so the stop flag is
+// noticed even when no key is pressed.
+#[cfg(target_os = "linux")]
+fn watch_keyboard(
+    mut device: evdev::Device,
+    mut matcher: HotkeyMatcher,
+    sender: Sender<VirtualCommand>,
+    stop: Arc<AtomicBool>,
+) {
+    use std::os::fd::AsRawFd;
+
+    let fd = device.as_raw_fd();
+    // SAFETY: fd belongs to `device`, which outlives every use below
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
+        warn!("Can't watch a keyboard for hotkeys: {}", std::io::Error::last_os_error());
+        return;
+    }
+
+    while !stop.load(Ordering::Relaxed) {
+        let mut poll_fd = libc::pollfd { fd, events: libc::POLLIN, revents: 0 };
+        // SAFETY: one valid pollfd, as the count says
+        if unsafe { libc::poll(&mut poll_fd, 1, HOTKEY_POLL_MS) } <= 0 {
+            continue; // Timed out (or interrupted): check the stop flag again
+        }
+        let events = match device.fetch_events() {
+            Ok(events) => events,
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => continue,
+            Err(e) => {
+                debug!("Stopped watching a keyboard for hotkeys: {}", e);
+                return;
+            }
+        };
+        for event in events.filter(|event| event.event_type() == evdev::EventType::KEY) {
+            for command in matcher.key_event(event.code(), event.value()) {
+                if stop.load(Ordering::Relaxed) || sender.send(command).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+// Hotkey handling
+impl crate::ShiftTool {
+    /// Applies the commands of fired hotkeys. Called once per UI frame.
+    pub(crate) fn poll_hotkeys(&mut self) {
+        let fired: Vec<VirtualCommand> = match &self.hotkeys.commands {
+            Some(commands) => commands.try_iter().collect(),
+            None => return,
+        };
+        for command in fired {
+            let slot = command.slot();
+            let result = match self.virtual_source_state(slot) {
+                Some(state) => self.set_virtual_source_state(slot, command.apply(state)),
+                None => Err(format!("Source {} is not a virtual source", slot + 1)),
+            };
+            match result {
+                Ok(()) => debug!("Hotkey applied: {:?}", command),
+                Err(e) => warn!("Hotkey {:?} ignored: {}", command, e),
+            }
+        }
+    }
+}
@@ -1,14 +1,28 @@
-// Export modules for testing
+// Modules of the app, used by main.rs and the tests
 pub mod about;
+pub mod catalog;
 pub mod config;
+pub mod config_file;
+pub mod descriptor;
 pub mod device;
 pub mod hid_worker;
+pub mod history;
+pub mod hooks;
+pub mod hotkeys;
+pub mod migration;
+pub mod process_watcher;
+pub mod profile_io;
+pub mod rebind;
+pub mod report_console;
 pub mod state;
+pub mod trace;
 pub mod ui;
+pub mod uinput;
 pub mod util;
+pub mod virtual_source;
 
-// Re-export main struct and types for testing
-pub use crate::config::ConfigData;
+// Re-export main types
+pub use crate::config::{ConfigData, GameRule};
 pub use crate::device::VpcDevice;
 pub use crate::state::State;
 
@@ -19,17 +33,28 @@ pub const INITIAL_HEIGHT: f32 = 260.0;
 
 // Type aliases for shared state
 pub use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
 pub type SharedStateFlag = Arc<(Mutex<bool>, Condvar)>;
 pub type SharedDeviceState = Arc<Mutex<u16>>;
 
 // Args struct for command line parsing
 use clap::Parser;
+use eframe::{egui, glow};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 pub struct Args {
     #[arg(short, long, default_value_t = false)]
     pub skip_firmware: bool,
+    /// Config file to use instead of the default location
+    #[arg(short, long)]
+    pub config: Option<std::path::PathBuf>,
+    /// Append every source read and receiver write of the worker to this trace file
+    #[arg(long, value_name = "FILE")]
+    pub record: Option<std::path::PathBuf>,
+    /// Replay a trace file against the worker logic, report any mismatches and exit
+    #[arg(long, value_name = "FILE")]
+    pub replay: Option<std::path::PathBuf>,
 }
 
 // Wrapper for ConfigData to match the actual structure
@@ -40,9 +65,17 @@ pub struct ShiftTool {
     // State
     pub state: State,
     pub thread_state: SharedStateFlag, // Is the worker thread running?
+    pub worker_handle: Option<std::thread::JoinHandle<()>>, // Handle of the running worker thread
+    pub worker_updates: Option<std::sync::mpsc::Sender<hid_worker::LiveSettings>>, // Live rule/mask edits for the worker
+    pub live_settings: Option<hid_worker::LiveSettings>, // Rules/masks the worker is currently using
+    pub worker_devices: Vec<String>, // Devices the matcher slots resolved to when the worker started
 
     // Device Data
     pub device_list: Vec<VpcDevice>, // List of discovered compatible devices
+    pub descriptors: descriptor::SharedDescriptors, // Report descriptors read so far, per device
+    pub interface_prober: device::InterfaceProber, // Background descriptor reads of composite device interfaces
+    pub details_device: Option<String>, // Device (by key) shown in the details window
+    pub report_console: report_console::ReportConsole, // Raw report console of the details window
 
     // Shared state between UI and Worker Thread
     pub shift_state: SharedDeviceState, // Current shift state
@@ -51,28 +84,209 @@ pub struct ShiftTool {
 
     // Configuration
     pub config: Config<ConfigData>,
-    pub selected_source: usize,
-    pub selected_receiver: usize,
+    pub config_base: Option<serde_json::Value>, // System-wide layer the config is saved relative to
+    pub config_reload_error: Option<String>, // Why the last outside edit of the config was rejected
+    pub config_watcher: config_file::ConfigWatcher,
+    pub config_recovery: Option<config_file::ConfigRecovery>, // Set when a broken config file was replaced at startup
+    pub skip_firmware: bool, // --skip-firmware: accept devices the firmware policy rejects
+    pub trace_path: Option<std::path::PathBuf>, // --record: trace file for the worker
+    pub saved_data: ConfigData, // Config as last loaded or saved; differs while there are unsaved changes
+    pub edit_history: history::EditHistory, // Undo/redo of routing edits
+    pub confirm_close: bool, // Showing the unsaved changes prompt on exit
+    pub confirm_switch: Option<String>, // Profile picked in the UI, waiting for the unsaved changes prompt
+    pub close_confirmed: bool, // The user chose to exit anyway
+
+    // Automatic profile switching
+    pub process_watcher: process_watcher::ProcessWatcher,
+    pub active_game: Option<GameRule>, // Game rule the current profile was switched for
+    pub deferred_profile: Option<String>, // Profile a game wants, held back until unsaved changes are saved or reverted
+    pub pending_import: Option<profile_io::PendingImport>, // Profile import waiting for device rebinding
+    pub rebind_offers: Vec<rebind::RebindOffer>, // Missing devices with a replacement connected
+    pub dismissed_rebinds: Vec<String>, // Missing devices (by key) the user chose not to rebind
+
+    // Script control of virtual sources
+    pub control_server: virtual_source::ControlServer,
+    pub hotkeys: hotkeys::HotkeyListener, // Global hotkeys for virtual sources
 }
 
 // Implementations for ShiftTool
 impl ShiftTool {
-    // Add a new source state tracking object
+    /// Loads the config at `config_path` (see `config_file::resolve_config_path`)
+    /// and builds the app around it. Nothing is started yet: the device scan,
+    /// watchers and control port come with the first frame.
+    ///
+    /// Only filesystem errors end up here; bad file contents are recovered.
+    pub fn new(
+        config_path: &std::path::Path,
+        skip_firmware: bool,
+        trace_path: Option<std::path::PathBuf>,
+    ) -> Result<Self, String> {
+        // Recover from a broken file, migrate older schemas and apply the system base
+        let loaded = config_file::load_config(config_path)?;
+        Ok(Self {
+            state: State::Initialising,
+            device_list: vec![],
+            descriptors: Default::default(),
+            interface_prober: device::InterfaceProber::default(),
+            details_device: None,
+            report_console: report_console::ReportConsole::default(),
+            source_states: vec![],
+            receiver_states: vec![],
+            shift_state: Arc::new(Mutex::new(0)),
+            thread_state: Arc::new((Mutex::new(false), Condvar::new())),
+            worker_handle: None,
+            worker_updates: None,
+            live_settings: None,
+            worker_devices: Vec::new(),
+            saved_data: loaded.config.data.clone(),
+            config: loaded.config,
+            config_base: loaded.base,
+            config_reload_error: None,
+            config_watcher: config_file::ConfigWatcher::default(),
+            config_recovery: loaded.recovery,
+            skip_firmware,
+            trace_path,
+            edit_history: history::EditHistory::default(),
+            confirm_close: false,
+            confirm_switch: None,
+            close_confirmed: false,
+            process_watcher: process_watcher::ProcessWatcher::default(),
+            active_game: None,
+            deferred_profile: None,
+            pending_import: None,
+            rebind_offers: vec![],
+            dismissed_rebinds: vec![],
+            control_server: virtual_source::ControlServer::default(),
+            hotkeys: hotkeys::HotkeyListener::default(),
+        })
+    }
+
+    // Initialization logic called once at the start
+    fn init(&mut self) {
+        // Load config and populate initial sources/receivers based on config
+        // The config is already loaded in Default::default()
+        let num_sources = self.config.data.sources.len();
+        let num_receivers = self.config.data.receivers.len();
+
+        for _ in 0..num_sources {
+            self.add_source_state(); // Add state tracking
+        }
+        for _ in 0..num_receivers {
+            self.add_receiver_state(); // Add state tracking
+        }
+
+        // Initial device scan
+        self.refresh_devices(); // Now calls the method defined in device.rs
+
+        // Pick up hand edits of the config file while running
+        self.config_watcher.start(&self.config.path);
+
+        // Start watching for games that have a profile assigned
+        self.process_watcher.set_rules(self.config.data.game_rules.clone());
+        self.process_watcher.start();
+
+        // Let scripts drive virtual sources if a control port is configured
+        if self.config.data.control_port != 0 {
+            self.control_server.start(self.config.data.control_port);
+        }
+        self.hotkeys.start(&self.config.data.virtual_hotkeys);
+
+        self.state = State::Running;
+        log::info!("Initialization complete. State set to Running.");
+    }
+
+    // Helper to add state tracking for a new source
     pub fn add_source_state(&mut self) {
-        self.source_states.push(Arc::new(Mutex::new(0)));
+        self.source_states
+            .push(Arc::new(Mutex::new(0)));
     }
 
-    // Add a new receiver state tracking object
+    // Helper to add state tracking for a new receiver
     pub fn add_receiver_state(&mut self) {
-        self.receiver_states.push(Arc::new(Mutex::new(0)));
+        self.receiver_states
+            .push(Arc::new(Mutex::new(0)));
     }
 
     // Get the current thread status
     pub fn get_thread_status(&self) -> bool {
-        let &(ref lock, _) = &*self.thread_state;
-        match lock.lock() {
+        match self.thread_state.0.lock() {
             Ok(guard) => *guard,
-            Err(_) => false, // Return false if the mutex is poisoned
+            Err(poisoned) => {
+                log::error!("Thread state mutex poisoned!");
+                **poisoned.get_ref() // Still try to get the value
+            }
         }
     }
+
+    // Graceful shutdown logic
+    fn shutdown_app(&mut self) {
+        log::info!("Shutdown requested.");
+        // Signal the worker thread to stop
+        {
+            let &(ref lock, ref cvar) = &*self.thread_state;
+            match lock.lock() {
+                Ok(mut started) => {
+                    *started = false;
+                    log::info!("Signaling worker thread to stop.");
+                }
+                Err(_) => {
+                    log::error!("Thread state mutex poisoned during shutdown!");
+                }
+            }
+            cvar.notify_all(); // Wake up thread if it's waiting
+        }
+
+        // Unsaved changes were already saved or discarded in the exit prompt
+        if self.has_unsaved_changes() {
+            log::warn!("Exiting with unsaved configuration changes.");
+        }
+
+        // Give the thread a moment to process the stop signal (optional)
+        // Note: Joining the thread handle would be more robust if we kept it.
+        std::thread::sleep(Duration::from_millis(250));
+        log::info!("Shutdown complete.");
+    }
+}
+
+// Main eframe application loop
+impl eframe::App for ShiftTool {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        log::debug!("Update Called.");
+        // Request repaint ensures GUI updates even if worker is slow
+        ctx.request_repaint_after(Duration::from_millis(50));
+
+        // Ask before closing with unsaved changes
+        ui::handle_close_request(self, ctx);
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            egui::Resize::default()
+                .default_width(INITIAL_WIDTH)
+                .default_height(INITIAL_HEIGHT)
+                .auto_sized()
+                .show(ui, |ui| match self.state {
+                    State::Initialising => {
+                        // Show a simple "Loading..." message while init runs
+                        ui.centered_and_justified(|ui| {
+                            ui.label("Initialising...");
+                        });
+                        // Actual init logic runs once after this frame
+                        self.init();
+                    }
+                    State::About => {
+                        // Call the UI drawing function from the ui module
+                        ui::draw_about_screen(self, ui);
+                    }
+                    State::Running => {
+                        // Call the UI drawing function from the ui module
+                        ui::draw_running_state(self, ui, ctx);
+                    }
+                });
+        });
+    }
+
+    // Called when the application is about to close
+    fn on_exit(&mut self, _gl: Option<&glow::Context>) {
+        self.shutdown_app();
+    }
 }
+
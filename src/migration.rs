@@ -0,0 +1,173 @@
+use log::{info, warn};
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Schema version written by this build.
+///
+/// Version history:
+/// - 0: 0.4.0 and earlier (no `schema_version` field)
+/// - 1: output, hook, profile and virtual source settings; sources carry a `kind`
+/// - 2: sections still at their defaults are left out, so a system-wide base can set them
+/// - 3: interface overrides name the interface number and usage, not just the number
+pub const CURRENT_SCHEMA_VERSION: u32 = 3;
+
+type Migration = fn(&mut Map<String, Value>) -> Result<(), String>;
+
+// MIGRATIONS[n] upgrades a schema n config to schema n + 1. Keep them in order.
+const MIGRATIONS: [Migration; CURRENT_SCHEMA_VERSION as usize] =
+    [migrate_v0_to_v1, migrate_v1_to_v2, migrate_v2_to_v3];
+
+/// Reads the schema version of a parsed config. Files without the field are version 0.
+pub fn schema_version(value: &Value) -> u32 {
+    value
+        .get("schema_version")
+        .and_then(Value::as_u64)
+        .map(|v| v as u32)
+        .unwrap_or(0)
+}
+
+/// Runs every migration needed to bring `value` up to `CURRENT_SCHEMA_VERSION`.
+///
+/// Returns the version the config had before migrating. Configs written by a
+/// newer build are returned unchanged.
+pub fn migrate_value(value: &mut Value) -> Result<u32, String> {
+    let from_version = schema_version(value);
+    let object = value
+        .as_object_mut()
+        .ok_or_else(|| "Config root is not an object".to_string())?;
+
+    for version in from_version..CURRENT_SCHEMA_VERSION {
+        MIGRATIONS[version as usize](object)
+            .map_err(|e| format!("Migration to schema {} failed: {}", version + 1, e))?;
+        object.insert("schema_version".to_string(), json!(version + 1));
+    }
+    Ok(from_version)
+}
+
+/// Migrates the config file at `path` in place, if it needs it.
+///
+/// The original file is copied to a `.v<old version>.bak` file next to it
+/// before anything is written. Returns the backup path when a backup was made.
+/// A missing file is not an error; `fast_config` creates it with defaults.
+pub fn migrate_config_file(path: &Path) -> Result<Option<PathBuf>, String> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+    };
+    let mut value: Value = json5::from_str(&text)
+        .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+
+    let version = schema_version(&value);
+    if version == CURRENT_SCHEMA_VERSION {
+        return Ok(None);
+    }
+
+    // Keep the original around whether we upgrade it or a newer build wrote it
+    let backup_path = backup_path_for(path, version);
+    fs::copy(path, &backup_path)
+        .map_err(|e| format!("Failed to back up {} to {}: {}", path.display(), backup_path.display(), e))?;
+
+    if version > CURRENT_SCHEMA_VERSION {
+        warn!(
+            "{} uses schema {} but this build only knows schema {}. Unknown settings will be dropped on save; the original is kept at {}.",
+            path.display(),
+            version,
+            CURRENT_SCHEMA_VERSION,
+            backup_path.display()
+        );
+        return Ok(Some(backup_path));
+    }
+
+    migrate_value(&mut value)?;
+    let migrated = serde_json::to_string_pretty(&value)
+        .map_err(|e| format!("Failed to serialize migrated config: {}", e))?;
+    crate::config_file::write_atomic(path, &migrated)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+    info!(
+        "Migrated {} from schema {} to {} (backup at {}).",
+        path.display(),
+        version,
+        CURRENT_SCHEMA_VERSION,
+        backup_path.display()
+    );
+    Ok(Some(backup_path))
+}
+
+// shift_tool.json -> shift_tool.json.v0.bak, with a timestamp if that already exists
+fn backup_path_for(path: &Path, version: u32) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "shift_tool.json".to_string());
+    let backup = path.with_file_name(format!("{}.v{}.bak", file_name, version));
+    if !backup.exists() {
+        return backup;
+    }
+    let timestamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    path.with_file_name(format!("{}.v{}-{}.bak", file_name, version, timestamp))
+}
+
+// Sections schema 1 added, with the values migrate_v0_to_v1 wrote for them
+fn schema_1_defaults() -> Vec<(&'static str, Value)> {
+    let mut defaults: Vec<(&str, Value)> = ["sources", "receivers", "key_bindings", "hooks", "profiles", "game_rules"]
+        .into_iter()
+        .map(|key| (key, json!([])))
+        .collect();
+    defaults.push(("virtual_joystick", json!({ "enabled": false, "source": "Result" })));
+    defaults.push(("active_profile", json!("")));
+    defaults.push(("default_profile", json!("")));
+    defaults.push(("control_port", json!(0)));
+    defaults
+}
+
+// Schema 0 -> 1: write out the new sections explicitly and tag every
+// saved source as a HID device, which was the only kind before virtual sources.
+fn migrate_v0_to_v1(config: &mut Map<String, Value>) -> Result<(), String> {
+    for (key, default) in schema_1_defaults() {
+        config.entry(key).or_insert(default);
+    }
+
+    let sources = config
+        .get_mut("sources")
+        .and_then(Value::as_array_mut)
+        .ok_or_else(|| "'sources' is not a list".to_string())?;
+    for source in sources.iter_mut() {
+        let source = source
+            .as_object_mut()
+            .ok_or_else(|| "Source entry is not an object".to_string())?;
+        source.entry("kind").or_insert_with(|| json!("Hid"));
+    }
+    Ok(())
+}
+
+// Schema 1 -> 2: drop the sections migrate_v0_to_v1 wrote out that are still
+// at those defaults. Left in, they would mask a system-wide base config;
+// missing, they fall back to the base or the serde defaults.
+fn migrate_v1_to_v2(config: &mut Map<String, Value>) -> Result<(), String> {
+    for (key, default) in schema_1_defaults() {
+        if config.get(key) == Some(&default) {
+            config.remove(key);
+        }
+    }
+    Ok(())
+}
+
+// Schema 2 -> 3: interface overrides were a bare interface number. They
+// become an object without a usage, which matches any collection of it.
+fn migrate_v2_to_v3(config: &mut Map<String, Value>) -> Result<(), String> {
+    let overrides = match config.get_mut("interface_overrides") {
+        Some(overrides) => overrides
+            .as_object_mut()
+            .ok_or_else(|| "'interface_overrides' is not a map".to_string())?,
+        None => return Ok(()),
+    };
+    for number in overrides.values_mut() {
+        if number.is_number() {
+            *number = json!({ "interface_number": number.clone() });
+        }
+    }
+    Ok(())
+}
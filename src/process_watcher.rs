@@ -0,0 +1,190 @@
+use crate::config::GameRule;
+use log::{debug, info, warn};
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// How often /proc is scanned for configured games
+const PROCESS_SCAN_MS: u64 = 2000;
+
+/// Returns true if a process with this command line (and `comm` name) is `executable`.
+///
+/// Each argument is reduced to its file name using both `/` and `\` as
+/// separators, so native binaries (`/usr/bin/foo`), Wine paths
+/// (`C:\Games\DCS.exe`) and Proton launch lines (`proton run /…/DCS.exe`)
+/// all match. Comparison is case-insensitive.
+pub fn process_matches(executable: &str, comm: &str, cmdline_args: &[&str]) -> bool {
+    let wanted = executable.trim();
+    if wanted.is_empty() {
+        return false;
+    }
+    if comm.trim().eq_ignore_ascii_case(wanted) {
+        return true;
+    }
+    cmdline_args.iter().any(|arg| {
+        let file_name = arg.rsplit(['/', '\\']).next().unwrap_or(arg);
+        file_name.eq_ignore_ascii_case(wanted)
+    })
+}
+
+/// Scans `/proc` and returns the first rule whose executable is running.
+///
+/// Rules are checked in config order, so earlier rules win when several
+/// games are running. Always returns `None` on systems without `/proc`.
+pub fn find_running_game(rules: &[GameRule]) -> Option<GameRule> {
+    if rules.is_empty() {
+        return None;
+    }
+    let entries = match fs::read_dir("/proc") {
+        Ok(entries) => entries,
+        Err(_) => return None, // Not Linux, or /proc not mounted
+    };
+
+    let mut processes: Vec<(String, String)> = Vec::new(); // (comm, raw cmdline)
+    for entry in entries.flatten() {
+        let file_name = entry.file_name();
+        if !file_name.to_string_lossy().bytes().all(|b| b.is_ascii_digit()) {
+            continue; // Not a PID directory
+        }
+        // Processes can exit mid-scan, so missing files are simply skipped
+        let comm = fs::read_to_string(entry.path().join("comm")).unwrap_or_default();
+        let cmdline = fs::read(entry.path().join("cmdline")).unwrap_or_default();
+        processes.push((comm, String::from_utf8_lossy(&cmdline).into_owned()));
+    }
+
+    for rule in rules {
+        for (comm, cmdline) in &processes {
+            let args: Vec<&str> = cmdline.split('\0').filter(|a| !a.is_empty()).collect();
+            if process_matches(&rule.executable, comm, &args) {
+                return Some(rule.clone());
+            }
+        }
+    }
+    None
+}
+
+/// Background thread that periodically looks for configured games.
+///
+/// The UI hands it the current rules with `set_rules` and reads the latest
+/// match with `current_match`; the actual profile switch happens on the UI thread.
+pub struct ProcessWatcher {
+    rules: Arc<Mutex<Vec<GameRule>>>,
+    current_match: Arc<Mutex<Option<GameRule>>>,
+    started: bool,
+}
+
+impl Default for ProcessWatcher {
+    fn default() -> Self {
+        Self {
+            rules: Arc::new(Mutex::new(vec![])),
+            current_match: Arc::new(Mutex::new(None)),
+            started: false,
+        }
+    }
+}
+
+impl ProcessWatcher {
+    /// Replaces the rules the watcher looks for.
+    pub fn set_rules(&self, rules: Vec<GameRule>) {
+        match self.rules.lock() {
+            Ok(mut guard) => *guard = rules,
+            Err(_) => warn!("Process watcher rules mutex poisoned!"),
+        }
+    }
+
+    /// The rule matched by the last scan, if any.
+    pub fn current_match(&self) -> Option<GameRule> {
+        match self.current_match.lock() {
+            Ok(guard) => guard.clone(),
+            Err(poisoned) => (**poisoned.get_ref()).clone(),
+        }
+    }
+
+    /// Starts the scanning thread. Calling it again is a no-op.
+    pub fn start(&mut self) {
+        if self.started {
+            return;
+        }
+        self.started = true;
+
+        let rules = self.rules.clone();
+        let current_match = self.current_match.clone();
+        thread::spawn(move || loop {
+            let rules_snapshot = rules.lock().map(|guard| guard.clone()).unwrap_or_default();
+            let found = find_running_game(&rules_snapshot);
+
+            if let Ok(mut guard) = current_match.lock() {
+                if *guard != found {
+                    match &found {
+                        Some(rule) => info!("Detected game '{}' (profile '{}').", rule.executable, rule.profile),
+                        None => info!("No configured game running anymore."),
+                    }
+                    *guard = found;
+                }
+            }
+            debug!("Process scan complete ({} rule(s)).", rules_snapshot.len());
+            thread::sleep(Duration::from_millis(PROCESS_SCAN_MS));
+        });
+        info!("Process watcher started.");
+    }
+}
+
+// Profile switching driven by the process watcher
+impl crate::ShiftTool {
+    /// Applies the watcher's latest result: loads the matched game's profile,
+    /// or the default profile once the game has exited.
+    ///
+    /// Switching would throw away unsaved routing edits, so while there are
+    /// any the switch waits in `deferred_profile` until they are saved or
+    /// reverted.
+    pub(crate) fn poll_game_profile(&mut self) {
+        let found = self.process_watcher.current_match();
+        if found != self.active_game {
+            let target = match &found {
+                Some(rule) => rule.profile.clone(),
+                None => self.config.data.default_profile.clone(),
+            };
+            self.active_game = found;
+            self.deferred_profile = Some(target).filter(|target| !target.is_empty());
+            if let Some(target) = &self.deferred_profile {
+                if self.has_unsaved_changes() && *target != self.config.data.active_profile {
+                    info!("Not switching to profile '{}' yet: save or revert the unsaved changes first.", target);
+                }
+            }
+        }
+
+        if self.has_unsaved_changes() {
+            return;
+        }
+        if let Some(target) = self.deferred_profile.take() {
+            if target != self.config.data.active_profile {
+                self.switch_profile(&target);
+            }
+        }
+    }
+
+    /// Loads a profile and restarts the worker if it was running.
+    ///
+    /// Unsaved edits are replaced; callers check `has_unsaved_changes` first.
+    /// Without any, the switch also becomes the baseline for Revert, so a
+    /// game switching profiles doesn't leave unsaved changes behind.
+    pub(crate) fn switch_profile(&mut self, name: &str) {
+        let was_saved = !self.has_unsaved_changes();
+        if !self.config.data.load_profile(name) {
+            warn!("Cannot switch to unknown profile '{}'.", name);
+            return;
+        }
+        info!("Switched to profile '{}'.", name);
+        if was_saved {
+            self.saved_data = self.config.data.clone();
+        }
+
+        // Keep per-slot UI state in step with the new routing
+        self.sync_slot_states();
+        // Undo steps from the previous profile don't apply to this one
+        self.edit_history.clear();
+
+        self.restart_worker();
+    }
+}
@@ -0,0 +1,281 @@
+use crate::config::{ConfigData, ModifiersArray, Profile};
+use crate::device::{SavedDevice, VpcDevice};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Version of the exported profile file layout.
+pub const PROFILE_FILE_VERSION: u32 = 1;
+
+/// A device slot in an exported profile.
+///
+/// The product name is stored next to the IDs so the slot can still be
+/// matched on a PC where the serial number (or even the PID) differs.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ExportedDevice {
+    #[serde(flatten)]
+    pub device: SavedDevice,
+    #[serde(default)]
+    pub product_name: String,
+}
+
+/// A standalone profile file that can be imported on another PC.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ProfileFile {
+    #[serde(default)]
+    pub format_version: u32,
+    pub name: String,
+    #[serde(default)]
+    pub sources: Vec<ExportedDevice>,
+    #[serde(default)]
+    pub receivers: Vec<ExportedDevice>,
+    #[serde(default)]
+    pub shift_modifiers: ModifiersArray,
+}
+
+impl ProfileFile {
+    /// Captures the current sources, receivers and rules under `name`.
+    pub fn from_config(name: &str, data: &ConfigData, device_list: &[VpcDevice]) -> Self {
+        let export = |devices: &[SavedDevice]| -> Vec<ExportedDevice> {
+            devices
+                .iter()
+                .map(|device| ExportedDevice {
+                    device: device.clone(),
+                    product_name: product_name_for(device, device_list),
+                })
+                .collect()
+        };
+        Self {
+            format_version: PROFILE_FILE_VERSION,
+            name: name.to_string(),
+            sources: export(&data.sources),
+            receivers: export(&data.receivers),
+            shift_modifiers: data.shift_modifiers,
+        }
+    }
+}
+
+// Product name of the connected device a slot points at, if any
+fn product_name_for(device: &SavedDevice, device_list: &[VpcDevice]) -> String {
+    device_list
+        .iter()
+        .find(|d| {
+            d.vendor_id == device.vendor_id
+                && d.product_id == device.product_id
+                && d.serial_number == device.serial_number
+        })
+        .map(|d| d.name.to_string())
+        .unwrap_or_default()
+}
+
+/// How an imported slot was matched to a connected device.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DeviceMatch {
+    Virtual,  // Virtual source, nothing to bind
+    Exact,    // Same VID, PID and serial number
+    SameModel, // Same VID and PID, different serial number
+    SameName, // Same product name
+    NotFound,
+}
+
+impl std::fmt::Display for DeviceMatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DeviceMatch::Virtual => write!(f, "virtual"),
+            DeviceMatch::Exact => write!(f, "exact match"),
+            DeviceMatch::SameModel => write!(f, "same model"),
+            DeviceMatch::SameName => write!(f, "same product name"),
+            DeviceMatch::NotFound => write!(f, "not found"),
+        }
+    }
+}
+
+/// Finds the connected device an exported slot should bind to.
+///
+/// Returns the index into `device_list` (0, the "no connection" entry, when
+/// nothing fits) and how it was matched. Tries the exact serial first, then
+/// the same model, then the product name.
+pub fn resolve_device(exported: &ExportedDevice, device_list: &[VpcDevice]) -> (usize, DeviceMatch) {
+    resolve_device_except(exported, device_list, &HashSet::new())
+}
+
+// Like `resolve_device`, but never picks a device in `taken`
+fn resolve_device_except(
+    exported: &ExportedDevice,
+    device_list: &[VpcDevice],
+    taken: &HashSet<usize>,
+) -> (usize, DeviceMatch) {
+    let saved = &exported.device;
+    if saved.is_virtual() {
+        return (0, DeviceMatch::Virtual);
+    }
+    let real_devices = || {
+        device_list
+            .iter()
+            .enumerate()
+            .filter(|(_, d)| d.vendor_id != 0 || d.product_id != 0) // Skip "no connection"
+            .filter(|(_, d)| d.unsupported.is_none()) // Rejected by the firmware policy
+            .filter(|(idx, _)| !taken.contains(idx))
+    };
+
+    if let Some((idx, _)) = real_devices().find(|(_, d)| {
+        d.vendor_id == saved.vendor_id
+            && d.product_id == saved.product_id
+            && d.serial_number == saved.serial_number
+    }) {
+        return (idx, DeviceMatch::Exact);
+    }
+    if let Some((idx, _)) =
+        real_devices().find(|(_, d)| d.vendor_id == saved.vendor_id && d.product_id == saved.product_id)
+    {
+        return (idx, DeviceMatch::SameModel);
+    }
+    let wanted_name = exported.product_name.trim();
+    if !wanted_name.is_empty() {
+        if let Some((idx, _)) = real_devices().find(|(_, d)| d.name.trim().eq_ignore_ascii_case(wanted_name)) {
+            return (idx, DeviceMatch::SameName);
+        }
+    }
+    (0, DeviceMatch::NotFound)
+}
+
+/// Writes a profile file as pretty JSON.
+pub fn export_profile(path: &Path, profile: &ProfileFile) -> Result<(), String> {
+    let contents = serde_json::to_string_pretty(profile)
+        .map_err(|e| format!("Failed to serialize profile: {}", e))?;
+    crate::config_file::write_atomic(path, &contents)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    info!("Exported profile '{}' to {}.", profile.name, path.display());
+    Ok(())
+}
+
+/// Reads a profile file written by `export_profile` (JSON5 is accepted too).
+pub fn read_profile_file(path: &Path) -> Result<ProfileFile, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let profile: ProfileFile = json5::from_str(&text)
+        .map_err(|e| format!("{} is not a profile file: {}", path.display(), e))?;
+    if profile.format_version > PROFILE_FILE_VERSION {
+        return Err(format!(
+            "{} was written by a newer version (format {}).",
+            path.display(),
+            profile.format_version
+        ));
+    }
+    Ok(profile)
+}
+
+/// An import waiting for the user to confirm the device for each slot.
+#[derive(Debug, Clone)]
+pub struct PendingImport {
+    pub profile: ProfileFile,
+    pub source_choices: Vec<usize>, // Index into the device list per source slot
+    pub receiver_choices: Vec<usize>,
+    pub source_matches: Vec<DeviceMatch>,
+    pub receiver_matches: Vec<DeviceMatch>,
+}
+
+impl PendingImport {
+    /// Pre-selects the best matching connected device for every slot.
+    ///
+    /// Slots whose exact device is connected claim it first; the others then
+    /// pick among the devices no other slot has, so two exported throttles
+    /// with changed serials end up on two different throttles.
+    pub fn new(profile: ProfileFile, device_list: &[VpcDevice]) -> Self {
+        let slots = || profile.sources.iter().chain(&profile.receivers);
+        let mut taken: HashSet<usize> = slots()
+            .map(|slot| resolve_device(slot, device_list))
+            .filter(|&(_, how)| how == DeviceMatch::Exact)
+            .map(|(idx, _)| idx)
+            .collect();
+        let (mut source_choices, mut source_matches): (Vec<usize>, Vec<DeviceMatch>) = slots()
+            .map(|slot| match resolve_device(slot, device_list) {
+                exact @ (_, DeviceMatch::Exact) => exact,
+                _ => {
+                    let (idx, how) = resolve_device_except(slot, device_list, &taken);
+                    if idx != 0 {
+                        taken.insert(idx);
+                    }
+                    (idx, how)
+                }
+            })
+            .unzip();
+        let receiver_choices = source_choices.split_off(profile.sources.len());
+        let receiver_matches = source_matches.split_off(profile.sources.len());
+        Self {
+            profile,
+            source_choices,
+            receiver_choices,
+            source_matches,
+            receiver_matches,
+        }
+    }
+
+    /// Builds the profile with every slot bound to its chosen device.
+    ///
+    /// Slots left on "no connection" keep the exported IDs, so they bind
+    /// automatically if that exact device is plugged in later.
+    pub fn finish(&self, device_list: &[VpcDevice]) -> Profile {
+        let bind = |exported: &[ExportedDevice], choices: &[usize]| -> Vec<SavedDevice> {
+            exported
+                .iter()
+                .zip(choices)
+                .map(|(slot, &choice)| {
+                    let mut device = slot.device.clone();
+                    match device_list.get(choice) {
+                        Some(chosen) if !device.is_virtual() && choice != 0 => {
+                            device.vendor_id = chosen.vendor_id;
+                            device.product_id = chosen.product_id;
+                            device.serial_number = chosen.serial_number.clone();
+                        }
+                        _ => {}
+                    }
+                    device
+                })
+                .collect()
+        };
+        Profile {
+            name: self.profile.name.clone(),
+            sources: bind(&self.profile.sources, &self.source_choices),
+            receivers: bind(&self.profile.receivers, &self.receiver_choices),
+            shift_modifiers: self.profile.shift_modifiers,
+        }
+    }
+}
+
+// Profile import/export
+impl crate::ShiftTool {
+    /// Exports the current routing under the active profile's name.
+    pub(crate) fn export_current_profile(&self, path: &Path) -> Result<(), String> {
+        let name = match self.config.data.active_profile.as_str() {
+            "" => "Exported",
+            name => name,
+        };
+        let profile = ProfileFile::from_config(name, &self.config.data, &self.device_list);
+        export_profile(path, &profile)
+    }
+
+    /// Reads a profile file and starts the rebinding step.
+    pub(crate) fn begin_profile_import(&mut self, path: &Path) -> Result<(), String> {
+        let profile = read_profile_file(path)?;
+        self.pending_import = Some(PendingImport::new(profile, &self.device_list));
+        Ok(())
+    }
+
+    /// Saves the pending import as a profile (replacing one with the same name) and switches to it.
+    pub(crate) fn finish_profile_import(&mut self) {
+        let pending = match self.pending_import.take() {
+            Some(pending) => pending,
+            None => return,
+        };
+        let profile = pending.finish(&self.device_list);
+        let name = profile.name.clone();
+        match self.config.data.profiles.iter_mut().find(|p| p.name == name) {
+            Some(existing) => *existing = profile,
+            None => self.config.data.profiles.push(profile),
+        }
+        info!("Imported profile '{}'.", name);
+        self.switch_profile(&name);
+    }
+}
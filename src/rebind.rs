@@ -0,0 +1,173 @@
+use crate::config::ConfigData;
+use crate::device::{SavedDevice, SlotMatcher, VpcDevice};
+use log::{error, info};
+
+/// A configured device that isn't connected while an unused device of the
+/// same model is, e.g. after a firmware update or an RMA swap changed the
+/// serial number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RebindOffer {
+    pub is_source: bool,
+    pub slot: usize,            // First slot using the missing device
+    pub missing: SavedDevice,   // The slot as configured
+    pub candidates: Vec<usize>, // Indices into the device list
+    pub unambiguous: bool,      // One candidate, and no other missing device wants it
+}
+
+impl RebindOffer {
+    /// "Source 2" / "Receiver 1"
+    pub fn slot_label(&self) -> String {
+        let kind = if self.is_source { "Source" } else { "Receiver" };
+        format!("{} {}", kind, self.slot + 1)
+    }
+}
+
+/// Finds configured devices that could be rebound to a connected one.
+///
+/// A candidate has the same VID and PID as the missing device, passes the
+/// firmware policy and isn't used by any slot. Slots sharing a missing device
+/// get one offer, since a rebind moves all of them.
+pub fn find_rebind_offers(device_list: &[VpcDevice], data: &ConfigData) -> Vec<RebindOffer> {
+    let slots = || {
+        data.sources
+            .iter()
+            .enumerate()
+            .map(|(i, slot)| (true, i, slot))
+            .chain(data.receivers.iter().enumerate().map(|(i, slot)| (false, i, slot)))
+    };
+    let same_device = |device: &VpcDevice, slot: &SavedDevice| {
+        device.vendor_id == slot.vendor_id
+            && device.product_id == slot.product_id
+            && device.serial_number == slot.serial_number
+    };
+    let in_use = |device: &VpcDevice| slots().any(|(_, _, slot)| same_device(device, slot));
+
+    let mut offers: Vec<RebindOffer> = Vec::new();
+    for (is_source, i, slot) in slots() {
+        if slot.is_virtual() || slot.matcher != SlotMatcher::Exact || (slot.vendor_id == 0 && slot.product_id == 0) {
+            continue; // Matcher slots follow the devices by themselves
+        }
+        if device_list.iter().any(|device| same_device(device, slot)) {
+            continue; // Connected
+        }
+        if offers.iter().any(|offer| offer.missing.device_key() == slot.device_key()) {
+            continue; // Already offered for an earlier slot
+        }
+        let candidates: Vec<usize> = device_list
+            .iter()
+            .enumerate()
+            .skip(1) // "No connection"
+            .filter(|(_, device)| {
+                device.vendor_id == slot.vendor_id
+                    && device.product_id == slot.product_id
+                    && device.unsupported.is_none()
+                    && !in_use(device)
+            })
+            .map(|(idx, _)| idx)
+            .collect();
+        if !candidates.is_empty() {
+            offers.push(RebindOffer {
+                is_source,
+                slot: i,
+                missing: slot.clone(),
+                candidates,
+                unambiguous: false,
+            });
+        }
+    }
+
+    // Two missing devices of the same model and one spare: don't guess
+    for idx in 0..offers.len() {
+        let offer = &offers[idx];
+        let contested = offers.iter().any(|other| {
+            other.missing.device_key() != offer.missing.device_key()
+                && other.candidates.contains(&offer.candidates[0])
+        });
+        offers[idx].unambiguous = offers[idx].candidates.len() == 1 && !contested;
+    }
+    offers
+}
+
+/// Points every slot using `missing` (in the routing and in all profiles) at
+/// `device`, and moves its nickname and interface override along.
+/// Returns the number of slots changed.
+pub fn rebind_device(data: &mut ConfigData, missing: &SavedDevice, device: &VpcDevice) -> usize {
+    let mut changed = 0;
+    let slots = data
+        .sources
+        .iter_mut()
+        .chain(data.receivers.iter_mut())
+        .chain(data.profiles.iter_mut().flat_map(|p| p.sources.iter_mut().chain(p.receivers.iter_mut())));
+    for slot in slots {
+        if !slot.is_virtual() && slot.device_key() == missing.device_key() {
+            slot.vendor_id = device.vendor_id;
+            slot.product_id = device.product_id;
+            slot.serial_number = device.serial_number.clone();
+            changed += 1;
+        }
+    }
+
+    let old_key = missing.device_key();
+    if let Some(nickname) = data.device_nicknames.remove(&old_key) {
+        data.device_nicknames.entry(device.full_name.clone()).or_insert(nickname);
+    }
+    if let Some(chosen) = data.interface_overrides.remove(&old_key) {
+        data.interface_overrides.entry(device.full_name.clone()).or_insert(chosen);
+    }
+    changed
+}
+
+// Rebinding slots whose device was replaced
+impl crate::ShiftTool {
+    /// Looks for missing devices with a replacement connected. With
+    /// `auto_rebind` on, unambiguous ones are rebound right away (only while
+    /// stopped, since it changes devices).
+    pub(crate) fn update_rebind_offers(&mut self) {
+        let mut offers = find_rebind_offers(&self.device_list, &self.config.data);
+        offers.retain(|offer| !self.dismissed_rebinds.contains(&offer.missing.device_key()));
+
+        if self.config.data.auto_rebind && !self.get_thread_status() {
+            if let Some(offer) = offers.iter().find(|offer| offer.unambiguous).cloned() {
+                info!("Rebinding {} automatically (one matching device connected).", offer.slot_label());
+                self.apply_rebind(&offer.missing, offer.candidates[0]);
+                return; // The next refresh looks again
+            }
+        }
+
+        for offer in &offers {
+            if !self.rebind_offers.iter().any(|known| known.missing.device_key() == offer.missing.device_key()) {
+                info!(
+                    "{}: {} isn't connected, but {} unused device(s) of the same model are.",
+                    offer.slot_label(),
+                    offer.missing.device_key(),
+                    offer.candidates.len()
+                );
+            }
+        }
+        self.rebind_offers = offers;
+    }
+
+    /// Rebinds `missing` to a connected device. The change is saved straight
+    /// away unless other edits are pending, so it doesn't sweep those along.
+    pub(crate) fn apply_rebind(&mut self, missing: &SavedDevice, device_idx: usize) {
+        let device = match self.device_list.get(device_idx).filter(|_| device_idx != 0) {
+            Some(device) => device.clone(),
+            None => return,
+        };
+        let was_saved = !self.has_unsaved_changes();
+        let changed = rebind_device(&mut self.config.data, missing, &device);
+        info!("Rebound {} slot(s) from {} to {}.", changed, missing.device_key(), device);
+        self.rebind_offers.retain(|offer| offer.missing.device_key() != missing.device_key());
+        if was_saved {
+            if let Err(e) = self.save_config_now() {
+                error!("Failed to save the rebind: {}", e);
+            }
+        }
+    }
+
+    /// Stops offering a rebind for this missing device until restart.
+    pub(crate) fn dismiss_rebind(&mut self, missing: &SavedDevice) {
+        self.dismissed_rebinds.push(missing.device_key());
+        self.rebind_offers.retain(|offer| offer.missing.device_key() != missing.device_key());
+    }
+}
@@ -0,0 +1,180 @@
+use hidapi::HidDevice;
+use std::collections::VecDeque;
+
+/// Largest report the console reads or sends, report ID included.
+pub const MAX_CONSOLE_REPORT_SIZE: usize = 1024;
+/// Requests kept in the console history.
+pub const MAX_CONSOLE_HISTORY: usize = 100;
+
+/// A request made from the raw report console.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConsoleRequest {
+    Get { report_id: u8, length: usize }, // get_feature_report into `length` bytes
+    Send(Vec<u8>),                        // send_feature_report; the first byte is the report ID
+}
+
+// e.g. "GET 04 (19 bytes)" / "SEND 04 00 01"
+impl std::fmt::Display for ConsoleRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConsoleRequest::Get { report_id, length } => write!(f, "GET {:02X} ({} bytes)", report_id, length),
+            ConsoleRequest::Send(bytes) => write!(f, "SEND {}", format_hex(bytes)),
+        }
+    }
+}
+
+/// One request from the console and what came of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsoleEntry {
+    pub time: String,   // Local time, HH:MM:SS
+    pub device: String, // Device key
+    pub request: ConsoleRequest,
+    pub result: Result<Vec<u8>, String>, // Bytes read, or bytes sent
+}
+
+// e.g. "12:00:01 GET 04 (19 bytes) → 04 00 01 ..."
+impl std::fmt::Display for ConsoleEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match (&self.request, &self.result) {
+            (_, Err(e)) => write!(f, "{} {} → error: {}", self.time, self.request, e),
+            (ConsoleRequest::Get { .. }, Ok(bytes)) => write!(f, "{} {} → {}", self.time, self.request, format_hex(bytes)),
+            (ConsoleRequest::Send(_), Ok(_)) => write!(f, "{} {} → sent", self.time, self.request),
+        }
+    }
+}
+
+/// Inputs and history of the raw report console in the device details window.
+#[derive(Debug, Clone)]
+pub struct ReportConsole {
+    pub report_id: String,               // Hex, for Get
+    pub length: usize,                   // Bytes to read, report ID included
+    pub payload: String,                 // Hex bytes starting with the report ID, for Send
+    pub input_error: Option<String>,     // Why the last input couldn't be turned into a request
+    pub history: VecDeque<ConsoleEntry>, // Newest first, all devices
+}
+
+impl Default for ReportConsole {
+    fn default() -> Self {
+        Self {
+            report_id: format!("{:02X}", crate::util::FEATURE_REPORT_ID_SHIFT),
+            length: crate::util::MAX_REPORT_SIZE,
+            payload: String::new(),
+            input_error: None,
+            history: VecDeque::new(),
+        }
+    }
+}
+
+impl ReportConsole {
+    /// The Get request for the current inputs.
+    pub fn get_request(&self) -> Result<ConsoleRequest, String> {
+        let report_id = match parse_hex(&self.report_id)?.as_slice() {
+            [id] => *id,
+            _ => return Err("The report ID must be a single byte.".to_string()),
+        };
+        if self.length == 0 || self.length > MAX_CONSOLE_REPORT_SIZE {
+            return Err(format!("The length must be 1 to {} bytes.", MAX_CONSOLE_REPORT_SIZE));
+        }
+        Ok(ConsoleRequest::Get { report_id, length: self.length })
+    }
+
+    /// The Send request for the current inputs.
+    pub fn send_request(&self) -> Result<ConsoleRequest, String> {
+        let bytes = parse_hex(&self.payload)?;
+        if bytes.is_empty() {
+            return Err("Enter the report to send, starting with its report ID.".to_string());
+        }
+        if bytes.len() > MAX_CONSOLE_REPORT_SIZE {
+            return Err(format!("Reports are at most {} bytes.", MAX_CONSOLE_REPORT_SIZE));
+        }
+        Ok(ConsoleRequest::Send(bytes))
+    }
+
+    /// Adds an entry to the history, dropping the oldest past `MAX_CONSOLE_HISTORY`.
+    pub fn record(&mut self, entry: ConsoleEntry) {
+        self.history.push_front(entry);
+        self.history.truncate(MAX_CONSOLE_HISTORY);
+    }
+
+    /// Puts an earlier request back into the inputs.
+    pub fn recall(&mut self, request: &ConsoleRequest) {
+        match request {
+            ConsoleRequest::Get { report_id, length } => {
+                self.report_id = format!("{:02X}", report_id);
+                self.length = *length;
+            }
+            ConsoleRequest::Send(bytes) => self.payload = format_hex(bytes),
+        }
+        self.input_error = None;
+    }
+}
+
+/// Parses hex bytes separated by spaces or commas ("04 00 1F", "0x04,0x00",
+/// or run together as "04001F").
+pub fn parse_hex(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    for token in text.split(|c: char| c.is_whitespace() || c == ',').filter(|t| !t.is_empty()) {
+        let digits = token.trim_start_matches("0x").trim_start_matches("0X");
+        if digits.is_empty() || (digits.len() % 2 != 0 && digits.len() != 1) {
+            return Err(format!("'{}' isn't a hex byte.", token));
+        }
+        let pairs: Vec<&str> = if digits.len() == 1 {
+            vec![digits]
+        } else {
+            (0..digits.len()).step_by(2).map(|i| digits.get(i..i + 2).unwrap_or("")).collect()
+        };
+        for pair in pairs {
+            bytes.push(u8::from_str_radix(pair, 16).map_err(|_| format!("'{}' isn't a hex byte.", token))?);
+        }
+    }
+    Ok(bytes)
+}
+
+/// Bytes as upper-case hex separated by spaces.
+pub fn format_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ")
+}
+
+/// Carries out a console request on an open device.
+pub fn run_request(device: &HidDevice, request: &ConsoleRequest) -> Result<Vec<u8>, String> {
+    match request {
+        ConsoleRequest::Get { report_id, length } => {
+            let mut buffer = vec![0u8; *length];
+            buffer[0] = *report_id;
+            let read = device
+                .get_feature_report(&mut buffer)
+                .map_err(|e| format!("get_feature_report failed: {}", e))?;
+            buffer.truncate(read);
+            Ok(buffer)
+        }
+        ConsoleRequest::Send(bytes) => {
+            device
+                .send_feature_report(bytes)
+                .map_err(|e| format!("send_feature_report failed: {}", e))?;
+            Ok(bytes.clone())
+        }
+    }
+}
+
+// Raw report console for the device details window
+impl crate::ShiftTool {
+    /// Opens a listed device, runs a console request on it and records the result.
+    pub(crate) fn run_console_request(&mut self, device_idx: usize, request: ConsoleRequest) {
+        let device = match self.device_list.get(device_idx).filter(|_| device_idx != 0) {
+            Some(device) => device,
+            None => return,
+        };
+        let result = device.open().and_then(|opened| run_request(&opened, &request));
+        match &result {
+            Ok(bytes) => log::info!("Console: {} on {} → {}", request, device, format_hex(bytes)),
+            Err(e) => log::warn!("Console: {} on {} failed: {}", request, device, e),
+        }
+        let entry = ConsoleEntry {
+            time: chrono::Local::now().format("%H:%M:%S").to_string(),
+            device: device.full_name.clone(),
+            request,
+            result,
+        };
+        self.report_console.record(entry);
+    }
+}
@@ -0,0 +1,145 @@
+use crate::config::ModifiersArray;
+use crate::report_console::{format_hex, parse_hex};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::Instant;
+
+/// One source or receiver the worker opened, as recorded in a trace.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TraceSlot {
+    pub slot: usize, // Config slot it was resolved from
+    #[serde(default)]
+    pub label: String,
+    pub report_size: usize, // Shift report length, report ID included; picks the format
+    #[serde(default)]
+    pub is_virtual: bool,
+    pub enabled: bool,
+    pub state_enabled: [bool; 8],
+}
+
+/// The rules and slots the worker ran with. Written when the worker starts
+/// and whenever it applies a live rule/mask edit.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TraceSettings {
+    pub shift_modifiers: ModifiersArray,
+    pub sources: Vec<TraceSlot>,
+    pub receivers: Vec<TraceSlot>,
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceKind {
+    SourceRead,    // get_feature_report on a source
+    ReceiverRead,  // get_feature_report on a receiver, merged into what is sent
+    ReceiverWrite, // send_feature_report on a receiver (the zero reset, then the state)
+    Cycle,         // End of a worker cycle; `state` is the result
+}
+
+/// A single report read or written by the worker.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TraceEvent {
+    pub t_ms: u64, // Since recording started
+    pub kind: TraceKind,
+    #[serde(default)]
+    pub slot: usize, // Index into the settings' sources or receivers
+    #[serde(default)]
+    pub report: String, // Hex bytes, report ID first
+    #[serde(default)]
+    pub state: Option<u16>, // Decoded shift state
+    #[serde(default)]
+    pub error: Option<String>, // Set if the read or write failed
+}
+
+impl TraceEvent {
+    pub fn report_bytes(&self) -> Result<Vec<u8>, String> {
+        parse_hex(&self.report)
+    }
+}
+
+/// A line of a trace file.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TraceLine {
+    Settings(TraceSettings),
+    Event(TraceEvent),
+}
+
+/// A whole trace file, in order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trace {
+    pub lines: Vec<TraceLine>,
+}
+
+/// Reads a trace file (one JSON object per line, blank lines ignored).
+pub fn read_trace_file(path: &Path) -> Result<Trace, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut trace = Trace::default();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let parsed = serde_json::from_str(&line)
+            .map_err(|e| format!("{} line {}: {}", path.display(), number + 1, e))?;
+        trace.lines.push(parsed);
+    }
+    match trace.lines.first() {
+        Some(TraceLine::Settings(_)) => Ok(trace),
+        _ => Err(format!("{} doesn't start with the worker settings.", path.display())),
+    }
+}
+
+/// Writes what the worker reads and sends to a trace file as it runs.
+pub struct TraceWriter {
+    out: BufWriter<File>,
+    started: Instant,
+}
+
+impl TraceWriter {
+    /// Opens the trace file for appending (creating it if needed) and writes
+    /// the starting settings. Appending keeps earlier runs when the worker restarts.
+    pub fn create(path: &Path, settings: TraceSettings) -> Result<Self, String> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        let mut writer = Self { out: BufWriter::new(file), started: Instant::now() };
+        writer.write_line(&TraceLine::Settings(settings));
+        Ok(writer)
+    }
+
+    /// Records a live rule/mask edit.
+    pub fn settings(&mut self, settings: TraceSettings) {
+        self.write_line(&TraceLine::Settings(settings));
+    }
+
+    /// Records a report read or written. `result` holds the bytes, or the error.
+    pub fn record(&mut self, kind: TraceKind, slot: usize, result: Result<&[u8], &str>, state: Option<u16>) {
+        let (report, error) = match result {
+            Ok(bytes) => (format_hex(bytes), None),
+            Err(e) => (String::new(), Some(e.to_string())),
+        };
+        let t_ms = self.started.elapsed().as_millis() as u64;
+        self.write_line(&TraceLine::Event(TraceEvent { t_ms, kind, slot, report, state, error }));
+    }
+
+    /// Ends a cycle with its result and flushes, so a crash loses at most one cycle.
+    pub fn end_cycle(&mut self, final_state: u16) {
+        self.record(TraceKind::Cycle, 0, Ok(&[]), Some(final_state));
+        if let Err(e) = self.out.flush() {
+            log::warn!("Failed to write the trace file: {}", e);
+        }
+    }
+
+    fn write_line(&mut self, line: &TraceLine) {
+        let result = serde_json::to_string(line)
+            .map_err(|e| e.to_string())
+            .and_then(|text| writeln!(self.out, "{}", text).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            log::warn!("Failed to write the trace file: {}", e);
+        }
+    }
+}
@@ -1,9 +1,11 @@
 use crate::about;
-use crate::config::{ShiftModifiers};
-use crate::device::VpcDevice; // Assuming VpcDevice has Display impl
+use crate::catalog::{CatalogEntry, DeviceRole};
+use crate::config::{BitEdge, HookEvent, JoystickSource, ShiftModifiers};
+use crate::device::{SourceKind, VpcDevice}; // Assuming VpcDevice has Display impl
 use crate::{ShiftTool, INITIAL_WIDTH, PROGRAM_TITLE}; // Import main struct
 use crate::state::State;
-use crate::util::read_bit; // Import utility
+use crate::uinput::parse_key_sequence;
+use crate::util::{bit_name, read_bit}; // Import utility
 use eframe::egui::{self, Color32, Context, ScrollArea, Ui};
 
 const DISABLED_COLOR: Color32 = Color32::from_rgb(255, 0, 0); // Red for disabled
@@ -41,49 +43,156 @@ impl ShiftTool {
                 cvar.notify_all();
             } else {
                 log::info!("Worker thread started.");
-                // Save config on start
-                if let Err(e) = self.config.save() {
-                    log::error!("Failed to save config on start: {}", e);
-                }
             }
         } else {
             // If we just stopped it
             log::info!("Worker thread stopped.");
             self.stop_worker_cleanup(); // Perform cleanup actions
-            // Save config on stop
-            if let Err(e) = self.config.save() {
-                log::error!("Failed to save config on stop: {}", e);
-            }
         }
     }
 
     fn handle_add_source(&mut self) {
         self.add_source_state(); // Add state tracking
-        self.config.data.sources.push(Default::default()); // Add config entry
+        let slot = self.suggested_slot(DeviceRole::Source);
+        self.config.data.sources.push(slot); // Add config entry
         log::debug!("Added source device slot.");
     }
 
-    fn handle_remove_source(&mut self) {
-        if self.config.data.sources.len() > 1 {
-            self.source_states.pop();
-            self.config.data.sources.pop();
-            log::debug!("Removed last source device slot.");
+    fn handle_remove_source(&mut self, idx: usize) {
+        if self.config.data.sources.len() > 1 && idx < self.config.data.sources.len() {
+            self.source_states.remove(idx);
+            self.config.data.sources.remove(idx);
+            log::debug!("Removed source device slot {}.", idx + 1);
+        }
+    }
+
+    // Swaps two source slots along with their state tracking
+    fn handle_swap_sources(&mut self, a: usize, b: usize) {
+        let len = self.config.data.sources.len();
+        if a < len && b < len {
+            self.config.data.sources.swap(a, b);
+            self.source_states.swap(a, b);
+            log::debug!("Swapped source slots {} and {}.", a + 1, b + 1);
         }
     }
 
     fn handle_add_receiver(&mut self) {
         self.add_receiver_state(); // Add state tracking
-        self.config.data.receivers.push(Default::default()); // Add config entry
+        let slot = self.suggested_slot(DeviceRole::Receiver);
+        self.config.data.receivers.push(slot); // Add config entry
         log::debug!("Added receiver device slot.");
     }
 
-    fn handle_remove_receiver(&mut self) {
-        if !self.config.data.receivers.is_empty() {
-            self.receiver_states.pop();
-            self.config.data.receivers.pop();
-            log::debug!("Removed last receiver device slot.");
+    fn handle_remove_receiver(&mut self, idx: usize) {
+        if idx < self.config.data.receivers.len() {
+            self.receiver_states.remove(idx);
+            self.config.data.remove_receiver(idx);
+            log::debug!("Removed receiver device slot {}.", idx + 1);
         }
     }
+
+    // Swaps two receiver slots along with their state tracking
+    fn handle_swap_receivers(&mut self, a: usize, b: usize) {
+        let len = self.config.data.receivers.len();
+        if a < len && b < len {
+            self.config.data.swap_receivers(a, b);
+            self.receiver_states.swap(a, b);
+            log::debug!("Swapped receiver slots {} and {}.", a + 1, b + 1);
+        }
+    }
+
+    fn handle_slot_action(&mut self, action: SlotAction, is_source: bool) {
+        match (action, is_source) {
+            (SlotAction::MoveUp(idx), true) => self.handle_swap_sources(idx, idx - 1),
+            (SlotAction::MoveDown(idx), true) => self.handle_swap_sources(idx, idx + 1),
+            (SlotAction::Remove(idx), true) => self.handle_remove_source(idx),
+            (SlotAction::MoveUp(idx), false) => self.handle_swap_receivers(idx, idx - 1),
+            (SlotAction::MoveDown(idx), false) => self.handle_swap_receivers(idx, idx + 1),
+            (SlotAction::Remove(idx), false) => self.handle_remove_receiver(idx),
+        }
+    }
+
+    fn handle_add_key_binding(&mut self) {
+        self.config.data.key_bindings.push(Default::default());
+        log::debug!("Added key binding.");
+    }
+
+    fn handle_remove_key_binding(&mut self, idx: usize) {
+        if idx < self.config.data.key_bindings.len() {
+            self.config.data.key_bindings.remove(idx);
+            log::debug!("Removed key binding {}.", idx + 1);
+        }
+    }
+
+    fn handle_save_profile(&mut self, name: &str) {
+        let name = name.trim();
+        if name.is_empty() {
+            return;
+        }
+        self.config.data.store_profile(name);
+        self.config.data.active_profile = name.to_string();
+        log::info!("Saved current setup as profile '{}'.", name);
+    }
+
+    fn handle_delete_profile(&mut self) {
+        let name = self.config.data.active_profile.clone();
+        self.config.data.profiles.retain(|p| p.name != name);
+        if self.config.data.default_profile == name {
+            self.config.data.default_profile.clear();
+        }
+        self.config.data.active_profile.clear();
+        log::info!("Deleted profile '{}'.", name);
+    }
+
+    fn handle_add_game_rule(&mut self) {
+        self.config.data.game_rules.push(Default::default());
+        self.process_watcher.set_rules(self.config.data.game_rules.clone());
+        log::debug!("Added game rule.");
+    }
+
+    fn handle_remove_game_rule(&mut self, idx: usize) {
+        if idx < self.config.data.game_rules.len() {
+            self.config.data.game_rules.remove(idx);
+            self.process_watcher.set_rules(self.config.data.game_rules.clone());
+            log::debug!("Removed game rule {}.", idx + 1);
+        }
+    }
+
+    fn handle_add_hook(&mut self) {
+        self.config.data.hooks.push(Default::default());
+        log::debug!("Added hook.");
+    }
+
+    fn handle_remove_hook(&mut self, idx: usize) {
+        if idx < self.config.data.hooks.len() {
+            self.config.data.hooks.remove(idx);
+            log::debug!("Removed hook {}.", idx + 1);
+        }
+    }
+
+    fn handle_restore_backup(&mut self, backup: &std::path::Path) {
+        if self.config_left_broken() {
+            log::error!("Not restoring {}: it would overwrite the unreadable config file.", backup.display());
+            return;
+        }
+        if let Err(e) = crate::config_file::restore_backup(&mut self.config, self.config_base.as_ref(), backup) {
+            log::error!("Failed to restore backup {}: {}", backup.display(), e);
+            return;
+        }
+        // The restored config was saved, so it is the new baseline for Revert
+        self.saved_data = self.config.data.clone();
+        // Keep per-slot state tracking and game rules in step with the restored config
+        self.sync_slot_states();
+        self.process_watcher.set_rules(self.config.data.game_rules.clone());
+    }
+}
+
+// Slot layout change picked in a source/receiver row, applied after the rows are drawn
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum SlotAction {
+    MoveUp(usize),
+    MoveDown(usize),
+    Remove(usize),
 }
 
 // --- UI Drawing Functions ---
@@ -108,6 +217,10 @@ pub(crate) fn draw_running_state(
     ui: &mut Ui,
     ctx: &Context,
 ) {
+    app.poll_game_profile(); // May restart the worker with another profile
+    app.poll_control_commands(); // Apply virtual source commands from scripts
+    app.poll_hotkeys(); // And from hotkeys
+    app.poll_config_reload(); // Apply outside edits of the config file
     let thread_running = app.get_thread_status();
     app.refresh_devices(); // Need to be careful about frequent HID API calls
 
@@ -116,17 +229,30 @@ pub(crate) fn draw_running_state(
         app.handle_add_source();
     }
 
+    handle_edit_shortcuts(app, ctx, thread_running);
+    draw_config_error_banner(app, ui);
+    draw_rebind_banner(app, ui, thread_running);
+    draw_import_window(app, ctx);
+    handle_switch_request(app, ctx);
+    draw_device_details_window(app, ctx);
+
     ui.columns(2, |columns| {
         columns[0].set_width(612 as f32);
         ScrollArea::vertical()
             .auto_shrink([false, false])
             .show(&mut columns[0], |ui| {
                 ui.vertical(|ui| {
+                    draw_profiles_section(app, ui);
+                    ui.separator();
                     draw_sources_section(app, ui, thread_running);
                     ui.separator();
-                    draw_rules_section(app, ui, thread_running);
+                    draw_rules_section(app, ui);
                     ui.separator();
                     draw_receivers_section(app, ui, thread_running);
+                    ui.separator();
+                    draw_outputs_section(app, ui, thread_running);
+                    ui.separator();
+                    draw_hooks_section(app, ui, thread_running);
                     ui.add_space(10.0);
                 });
             });
@@ -136,6 +262,604 @@ pub(crate) fn draw_running_state(
             draw_control_buttons(app, ui, ctx, thread_running);
         });
     });
+
+    app.push_live_settings(); // Hand rule/mask edits to the running worker
+    // Turn this frame's edits into an undo step. Slot notes are part of the
+    // routing, so while a text field has focus the step waits for it to lose
+    // focus; otherwise every keystroke in a note would be its own step.
+    if !ctx.wants_keyboard_input() {
+        app.edit_history.record(&app.config.data);
+    }
+}
+
+// Ctrl+Z / Ctrl+Shift+Z / Ctrl+Y and Ctrl+S, unless a text field has focus
+fn handle_edit_shortcuts(app: &mut ShiftTool, ctx: &Context, thread_running: bool) {
+    if ctx.wants_keyboard_input() {
+        return; // Text fields have their own undo
+    }
+    let undo = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Z);
+    let redo = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND | egui::Modifiers::SHIFT, egui::Key::Z);
+    let redo_alt = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Y);
+    let save = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::S);
+
+    // Check the longer shortcut first; consume_shortcut ignores extra modifiers
+    let redo_pressed = ctx.input_mut(|i| i.consume_shortcut(&redo) || i.consume_shortcut(&redo_alt));
+    let undo_pressed = !redo_pressed && ctx.input_mut(|i| i.consume_shortcut(&undo));
+    if redo_pressed && app.can_redo(thread_running) {
+        app.handle_redo();
+    }
+    if undo_pressed && app.can_undo(thread_running) {
+        app.handle_undo();
+    }
+    if ctx.input_mut(|i| i.consume_shortcut(&save)) && app.has_unsaved_changes() {
+        if let Err(e) = app.save_config_now() {
+            log::error!("Failed to save config: {}", e);
+        }
+    }
+}
+
+/// Stops the window from closing with unsaved changes and asks what to do.
+pub(crate) fn handle_close_request(app: &mut ShiftTool, ctx: &Context) {
+    if ctx.input(|i| i.viewport().close_requested())
+        && app.has_unsaved_changes()
+        && !app.close_confirmed
+    {
+        ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+        app.confirm_close = true;
+    }
+    if !app.confirm_close {
+        return;
+    }
+
+    egui::Window::new("Unsaved changes")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .show(ctx, |ui| {
+            ui.label("The configuration has unsaved changes.");
+            ui.horizontal(|ui| {
+                if ui.button("Save and exit").clicked() {
+                    match app.save_config_now() {
+                        Ok(()) => {
+                            app.close_confirmed = true;
+                            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                        }
+                        Err(e) => log::error!("Failed to save config: {}", e),
+                    }
+                    app.confirm_close = false;
+                }
+                if ui.button("Exit without saving").clicked() {
+                    app.close_confirmed = true;
+                    app.confirm_close = false;
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                }
+                if ui.button("Cancel").clicked() {
+                    app.confirm_close = false;
+                }
+            });
+        });
+}
+
+// Asks what to do with unsaved changes before switching to the profile picked in the UI
+fn handle_switch_request(app: &mut ShiftTool, ctx: &Context) {
+    let name = match app.confirm_switch.clone() {
+        Some(name) => name,
+        None => return,
+    };
+
+    egui::Window::new("Unsaved changes")
+        .id(egui::Id::new("confirm_profile_switch"))
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .show(ctx, |ui| {
+            ui.label(format!("The configuration has unsaved changes. Switching to '{}' replaces them.", name));
+            ui.horizontal(|ui| {
+                if ui.button("Save and switch").clicked() {
+                    match app.save_config_now() {
+                        Ok(()) => app.switch_profile(&name),
+                        Err(e) => log::error!("Failed to save config: {}", e),
+                    }
+                    app.confirm_switch = None;
+                }
+                if ui.button("Switch without saving").clicked() {
+                    app.revert_config();
+                    app.switch_profile(&name);
+                    app.confirm_switch = None;
+                }
+                if ui.button("Cancel").clicked() {
+                    app.confirm_switch = None;
+                }
+            });
+        });
+}
+
+// Path field with Export/Import buttons for sharing profiles between PCs
+fn draw_profile_file_row(app: &mut ShiftTool, ui: &mut Ui) {
+    let path_id = ui.make_persistent_id("profile_file_path");
+    let status_id = ui.make_persistent_id("profile_file_status");
+    let mut path = ui.data_mut(|d| d.get_temp::<String>(path_id).unwrap_or_default());
+    let mut status = ui.data_mut(|d| d.get_temp::<Option<String>>(status_id).unwrap_or_default());
+
+    ui.horizontal(|ui| {
+        ui.label("File:");
+        ui.add(egui::TextEdit::singleline(&mut path).hint_text("/path/to/profile.json").desired_width(220.0));
+        let has_path = !path.trim().is_empty();
+        if ui.add_enabled(has_path, egui::Button::new("Export")).clicked() {
+            status = app.export_current_profile(std::path::Path::new(path.trim())).err();
+        }
+        if ui.add_enabled(has_path, egui::Button::new("Import…")).clicked() {
+            status = app.begin_profile_import(std::path::Path::new(path.trim())).err();
+        }
+        if let Some(e) = &status {
+            ui.colored_label(DISABLED_COLOR, "Failed").on_hover_text(e);
+        }
+    });
+
+    ui.data_mut(|d| {
+        d.insert_temp(path_id, path);
+        d.insert_temp(status_id, status);
+    });
+}
+
+/// Lets the user confirm or change the device for every slot of an imported profile.
+fn draw_import_window(app: &mut ShiftTool, ctx: &Context) {
+    let mut pending = match app.pending_import.take() {
+        Some(pending) => pending,
+        None => return,
+    };
+    let mut finished = false;
+    let mut cancelled = false;
+
+    egui::Window::new(format!("Import profile '{}'", pending.profile.name))
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.label("Choose the connected device for each slot:");
+            let slots = pending
+                .profile
+                .sources
+                .iter()
+                .zip(pending.source_choices.iter_mut().zip(&pending.source_matches))
+                .enumerate()
+                .map(|(i, slot)| (format!("Source {}", i + 1), slot))
+                .chain(
+                    pending
+                        .profile
+                        .receivers
+                        .iter()
+                        .zip(pending.receiver_choices.iter_mut().zip(&pending.receiver_matches))
+                        .enumerate()
+                        .map(|(i, slot)| (format!("Receiver {}", i + 1), slot)),
+                );
+
+            for (label, (exported, (choice, found))) in slots {
+                ui.horizontal(|ui| {
+                    ui.label(&label);
+                    if *found == crate::profile_io::DeviceMatch::Virtual {
+                        ui.label("Virtual source");
+                        return;
+                    }
+                    let wanted = if exported.product_name.is_empty() {
+                        format!("VID:{:04X} PID:{:04X}", exported.device.vendor_id, exported.device.product_id)
+                    } else {
+                        exported.product_name.clone()
+                    };
+                    device_selector_combo(
+                        ui,
+                        format!("import_{}", label),
+                        &app.device_list,
+                        &app.config.data.device_catalog,
+                        *choice,
+                        |idx| *choice = idx,
+                        false,
+                    );
+                    ui.label(egui::RichText::new(format!("{} ({})", wanted, found)).color(Color32::GRAY));
+                });
+            }
+
+            ui.horizontal(|ui| {
+                finished = ui.button("Import").clicked();
+                cancelled = ui.button("Cancel").clicked();
+            });
+        });
+
+    if cancelled {
+        return;
+    }
+    app.pending_import = Some(pending);
+    if finished {
+        app.finish_profile_import();
+    }
+}
+
+/// Window with everything known about one device, including its parsed
+/// HID report descriptor.
+fn draw_device_details_window(app: &mut ShiftTool, ctx: &Context) {
+    let key = match &app.details_device {
+        Some(key) => key.clone(),
+        None => return,
+    };
+    let device_idx = app.device_list.iter().position(|d| d.full_name == key);
+    let thread_running = app.get_thread_status();
+    let cached_descriptor = app.cached_descriptor(&key);
+    // Taken out while drawing so the window can edit it next to `app`
+    let mut console = std::mem::take(&mut app.report_console);
+    let mut console_request = None;
+    let mut open = true;
+    let mut read_descriptor = false;
+
+    egui::Window::new("Device details")
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(true)
+        .show(ctx, |ui| {
+            let device = match device_idx.and_then(|idx| app.device_list.get(idx)) {
+                Some(device) => device,
+                None => {
+                    ui.label(format!("{} is not connected.", key));
+                    return;
+                }
+            };
+            let entry = app.catalog_entry(device);
+            egui::Grid::new("device_details_grid").num_columns(2).show(ui, |ui| {
+                ui.label("Device:");
+                ui.label(device.to_string());
+                ui.end_row();
+                ui.label("IDs:");
+                ui.label(format!(
+                    "VID:{:04X} PID:{:04X} SN:{}",
+                    device.vendor_id,
+                    device.product_id,
+                    if device.serial_number.is_empty() { "N/A" } else { &device.serial_number }
+                ));
+                ui.end_row();
+                ui.label("Model:");
+                ui.label(entry.map_or_else(|| "Unknown model".to_string(), |e| e.to_string()));
+                ui.end_row();
+                ui.label("Firmware:");
+                ui.label(match device.firmware.diagnostic() {
+                    Some(problem) => format!("{} (⚠ {})", device.firmware, problem),
+                    None => device.firmware.to_string(),
+                });
+                ui.end_row();
+                if let Some(reason) = &device.unsupported {
+                    ui.label("Status:");
+                    ui.colored_label(Color32::YELLOW, reason);
+                    ui.end_row();
+                }
+                for (j, interface) in device.interfaces.iter().enumerate() {
+                    ui.label(if j == 0 { "Interfaces:" } else { "" });
+                    let marker = if j == device.interface { " (used)" } else { "" };
+                    ui.label(format!("{}{}", interface, marker));
+                    ui.end_row();
+                }
+                if let Some(interface) = device.chosen_interface() {
+                    ui.label("Path:");
+                    ui.label(egui::RichText::new(interface.path.to_string_lossy()).monospace());
+                    ui.end_row();
+                }
+                let shift_report_size = match &cached_descriptor {
+                    Some(Ok(descriptor)) => descriptor.shift_report().map(|report| report.size()),
+                    _ => None,
+                };
+                let (format, reason) = crate::util::explain_report_format(
+                    &device.name,
+                    &device.firmware,
+                    entry.map(|e| e.format),
+                    shift_report_size,
+                );
+                ui.label("Report format:");
+                ui.label(format!("{} ({})", format.name, reason));
+                ui.end_row();
+            });
+
+            ui.separator();
+            ui.label(egui::RichText::new("HID report descriptor").strong());
+            match &cached_descriptor {
+                Some(Ok(descriptor)) => {
+                    match descriptor.shift_report() {
+                        Some(report) => ui.label(format!("✔ Shift {}", report)),
+                        None => ui.colored_label(
+                            Color32::YELLOW,
+                            format!(
+                                "⚠ No feature report {}: the device can't receive the shift state.",
+                                crate::util::FEATURE_REPORT_ID_SHIFT
+                            ),
+                        ),
+                    };
+                    for (page, usage) in &descriptor.collections {
+                        ui.label(format!("Collection: usage {:04X}:{:04X}", page, usage));
+                    }
+                    for report in &descriptor.reports {
+                        ui.label(report.to_string());
+                    }
+                    egui::CollapsingHeader::new(format!("Raw descriptor ({} bytes)", descriptor.raw.len()))
+                        .id_salt(format!("raw_descriptor_{}", key))
+                        .show(ui, |ui| {
+                            ui.label(egui::RichText::new(descriptor.hex_dump()).monospace());
+                        });
+                }
+                Some(Err(e)) => {
+                    ui.colored_label(Color32::YELLOW, e);
+                }
+                None => {
+                    ui.label("Not read yet. The worker reads it when it opens the device.");
+                }
+            }
+            read_descriptor = ui.button("Read descriptor").clicked();
+
+            ui.separator();
+            console_request = draw_report_console(ui, &mut console, &key, thread_running);
+        });
+
+    app.report_console = console;
+    if let Some(idx) = device_idx {
+        if read_descriptor {
+            app.read_descriptor_now(idx);
+        }
+        if let Some(request) = console_request {
+            app.run_console_request(idx, request);
+        }
+    }
+    if !open {
+        app.details_device = None;
+    }
+}
+
+// Raw feature report console of the details window; returns the request to
+// run when Get or Send was clicked
+fn draw_report_console(
+    ui: &mut Ui,
+    console: &mut crate::report_console::ReportConsole,
+    device_key: &str,
+    thread_running: bool,
+) -> Option<crate::report_console::ConsoleRequest> {
+    use crate::report_console::MAX_CONSOLE_REPORT_SIZE;
+    let mut request = None;
+    ui.label(egui::RichText::new("Raw report console").strong());
+    if thread_running {
+        ui.colored_label(Color32::YELLOW, "⚠ The worker is running and may overwrite reports you send.");
+    }
+    ui.horizontal(|ui| {
+        ui.label("Report ID:");
+        ui.add(egui::TextEdit::singleline(&mut console.report_id).desired_width(30.0));
+        ui.label("Length:");
+        ui.add(egui::DragValue::new(&mut console.length).range(1..=MAX_CONSOLE_REPORT_SIZE))
+            .on_hover_text("Bytes to read, including the report ID");
+        if ui.button("Get feature report").clicked() {
+            request = Some(console.get_request());
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.label("Bytes:");
+        ui.add(egui::TextEdit::singleline(&mut console.payload).hint_text("04 00 01 ...").desired_width(260.0))
+            .on_hover_text("Hex bytes, starting with the report ID");
+        if ui.button("Send feature report").clicked() {
+            request = Some(console.send_request());
+        }
+    });
+    let request = match request {
+        Some(Ok(request)) => {
+            console.input_error = None;
+            Some(request)
+        }
+        Some(Err(e)) => {
+            console.input_error = Some(e);
+            None
+        }
+        None => None,
+    };
+    if let Some(e) = &console.input_error {
+        ui.colored_label(Color32::YELLOW, e);
+    }
+
+    let mut recall = None;
+    ScrollArea::vertical()
+        .id_salt("report_console_history")
+        .max_height(150.0)
+        .show(ui, |ui| {
+            for entry in console.history.iter().filter(|entry| entry.device == device_key) {
+                ui.horizontal(|ui| {
+                    if ui.small_button("↺").on_hover_text("Use this request again").clicked() {
+                        recall = Some(entry.request.clone());
+                    }
+                    let text = egui::RichText::new(entry.to_string()).monospace();
+                    if entry.result.is_err() {
+                        ui.colored_label(Color32::YELLOW, text);
+                    } else {
+                        ui.label(text);
+                    }
+                });
+            }
+        });
+    if let Some(previous) = recall {
+        console.recall(&previous);
+    }
+    request
+}
+
+// Explains a config file that was replaced at startup, or a rejected edit, until dismissed
+fn draw_config_error_banner(app: &mut ShiftTool, ui: &mut Ui) {
+    let message = match (&app.config_recovery, &app.config_reload_error) {
+        (Some(recovery), _) => recovery.to_string(),
+        (None, Some(e)) => format!(
+            "The edited configuration file was rejected ({}). The running configuration is unchanged.",
+            e
+        ),
+        (None, None) => return,
+    };
+    egui::Frame::none()
+        .fill(Color32::from_rgb(90, 20, 20))
+        .inner_margin(6.0)
+        .show(ui, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                ui.colored_label(Color32::WHITE, message);
+                // Stays up while saves are refused because of the broken file
+                if !app.config_left_broken() && ui.button("Dismiss").clicked() {
+                    if app.config_recovery.is_some() {
+                        app.config_recovery = None;
+                    } else {
+                        app.config_reload_error = None;
+                    }
+                }
+            });
+        });
+    ui.add_space(4.0);
+}
+
+// Offers to rebind slots whose device is missing while a replacement is connected
+fn draw_rebind_banner(app: &mut ShiftTool, ui: &mut Ui, thread_running: bool) {
+    if app.rebind_offers.is_empty() {
+        return;
+    }
+    let mut rebind = None;
+    let mut dismiss = None;
+    egui::Frame::none()
+        .fill(Color32::from_rgb(70, 60, 10))
+        .inner_margin(6.0)
+        .show(ui, |ui| {
+            for offer in &app.rebind_offers {
+                ui.horizontal_wrapped(|ui| {
+                    let missing = &offer.missing;
+                    ui.colored_label(
+                        Color32::WHITE,
+                        format!(
+                            "{}: the device with SN {}{} isn't connected, but an unused one of the same model is.",
+                            offer.slot_label(),
+                            if missing.serial_number.is_empty() { "N/A" } else { &missing.serial_number },
+                            app.config.data.nickname_suffix(missing)
+                        ),
+                    );
+                    for &idx in &offer.candidates {
+                        let serial = &app.device_list[idx].serial_number;
+                        if ui
+                            .add_enabled(!thread_running, egui::Button::new(format!("Rebind to SN {}", serial)))
+                            .on_hover_text("Moves every slot and profile using the missing device to this one")
+                            .on_disabled_hover_text("Stop the worker to change devices")
+                            .clicked()
+                        {
+                            rebind = Some((missing.clone(), idx));
+                        }
+                    }
+                    if ui.button("Ignore").clicked() {
+                        dismiss = Some(missing.clone());
+                    }
+                });
+            }
+            ui.checkbox(&mut app.config.data.auto_rebind, "Rebind automatically when there is only one match");
+        });
+    ui.add_space(4.0);
+
+    if let Some((missing, idx)) = rebind {
+        app.apply_rebind(&missing, idx);
+    }
+    if let Some(missing) = dismiss {
+        app.dismiss_rebind(&missing);
+    }
+}
+
+fn draw_profiles_section(app: &mut ShiftTool, ui: &mut Ui) {
+    ui.heading("Profiles");
+    let profile_names: Vec<String> =
+        app.config.data.profiles.iter().map(|p| p.name.clone()).collect();
+
+    ui.horizontal(|ui| {
+        ui.label("Profile:");
+        let active = app.config.data.active_profile.clone();
+        let mut selected = None;
+        egui::ComboBox::from_id_salt("active_profile")
+            .width(160.0)
+            .selected_text(if active.is_empty() { "(unsaved)" } else { &active })
+            .show_ui(ui, |ui| {
+                for name in &profile_names {
+                    if ui.selectable_label(*name == active, name).clicked() && *name != active {
+                        selected = Some(name.clone());
+                    }
+                }
+            });
+        if let Some(name) = selected {
+            if app.has_unsaved_changes() {
+                app.confirm_switch = Some(name); // Asked in handle_switch_request
+            } else {
+                app.switch_profile(&name);
+            }
+        }
+
+        // Name for "Save As", kept in egui's temporary memory between frames
+        let name_id = ui.make_persistent_id("new_profile_name");
+        let mut new_name = ui.data_mut(|d| d.get_temp::<String>(name_id).unwrap_or_default());
+        ui.add(egui::TextEdit::singleline(&mut new_name).hint_text("profile name").desired_width(120.0));
+        if ui.add_enabled(!new_name.trim().is_empty(), egui::Button::new("Save As")).clicked() {
+            app.handle_save_profile(&new_name);
+            new_name.clear();
+        }
+        ui.data_mut(|d| d.insert_temp(name_id, new_name));
+
+        if ui.add_enabled(!active.is_empty(), egui::Button::new("Delete")).clicked() {
+            app.handle_delete_profile();
+        }
+    });
+
+    draw_profile_file_row(app, ui);
+
+    ui.horizontal(|ui| {
+        ui.label("When no game runs:");
+        let default_profile = &mut app.config.data.default_profile;
+        egui::ComboBox::from_id_salt("default_profile")
+            .width(160.0)
+            .selected_text(if default_profile.is_empty() { "(keep current)" } else { default_profile.as_str() })
+            .show_ui(ui, |ui| {
+                ui.selectable_value(default_profile, String::new(), "(keep current)");
+                for name in &profile_names {
+                    ui.selectable_value(default_profile, name.clone(), name);
+                }
+            });
+    });
+
+    let mut rules_changed = false;
+    let mut remove_idx = None;
+    for (idx, rule) in app.config.data.game_rules.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            ui.label("When");
+            rules_changed |= ui
+                .add(egui::TextEdit::singleline(&mut rule.executable).hint_text("DCS.exe").desired_width(140.0))
+                .changed();
+            ui.label("runs, use");
+            egui::ComboBox::from_id_salt(format!("game_rule_profile_{}", idx))
+                .width(140.0)
+                .selected_text(rule.profile.clone())
+                .show_ui(ui, |ui| {
+                    for name in &profile_names {
+                        rules_changed |= ui.selectable_value(&mut rule.profile, name.clone(), name).changed();
+                    }
+                });
+            if ui.button("Remove").clicked() {
+                remove_idx = Some(idx);
+            }
+        });
+    }
+    if rules_changed {
+        app.process_watcher.set_rules(app.config.data.game_rules.clone());
+    }
+    if let Some(idx) = remove_idx {
+        app.handle_remove_game_rule(idx);
+    }
+
+    ui.horizontal(|ui| {
+        if ui.button("Add Game Rule").clicked() {
+            app.handle_add_game_rule();
+        }
+        let mut detected = match &app.active_game {
+            Some(rule) => format!("Detected: {} (profile '{}')", rule.executable, rule.profile),
+            None => "No game detected".to_string(),
+        };
+        if let Some(target) = app.deferred_profile.as_ref().filter(|t| **t != app.config.data.active_profile) {
+            detected.push_str(&format!(" - switching to '{}' once unsaved changes are saved or reverted", target));
+        }
+        ui.label(egui::RichText::new(detected).color(Color32::GRAY));
+    });
+    ui.add_space(10.0);
 }
 
 fn draw_sources_section(
@@ -144,16 +868,23 @@ fn draw_sources_section(
     thread_running: bool,
 ) {
     ui.heading("Sources");
-    for i in 0..app.config.data.sources.len() {
+    let num_sources = app.config.data.sources.len();
+    let mut slot_action = None;
+    let mut open_details = None;
+    for i in 0..num_sources {
         // --- Immutable Operations First ---
         let saved_config_for_find = app.config.data.sources[i].clone();
         let selected_device_idx = crate::device::find_device_index_for_saved(
             &app.device_list, // Pass immutable borrow of device_list
             &saved_config_for_find,
         );
+        let warning = app.slot_warning(&saved_config_for_find, false, i);
 
         // --- Now get mutable borrow for UI elements that might change config ---
         let source_config = &mut app.config.data.sources[i];
+        let nicknames = &mut app.config.data.device_nicknames;
+        let interface_overrides = &mut app.config.data.interface_overrides;
+        let catalog = &app.config.data.device_catalog;
         let device_list = &app.device_list; // Re-borrow immutably (allowed alongside mutable borrow of a *different* field)
         let source_states = &app.source_states;
 
@@ -161,24 +892,57 @@ fn draw_sources_section(
         let pid = source_config.product_id;
 
         ui.horizontal(|ui| {
-            ui.label(format!("Source {}:", i + 1));
-            // Device Selector Combo Box
-            device_selector_combo(
+            slot_label(ui, format!("Source {}:", i + 1), source_config.enabled);
+            if source_config.is_virtual() {
+                ui.add_sized(
+                    [300.0, 18.0],
+                    egui::Label::new(egui::RichText::new("Virtual source (set by software)").italics()),
+                );
+            } else {
+                // Device Selector Combo Box
+                device_selector_combo(
+                    ui,
+                    format!("source_combo_{}", i),
+                    device_list, // Pass immutable borrow
+                    catalog,
+                    selected_device_idx,
+                    |selected_idx| {
+                        if selected_idx < device_list.len() { // Bounds check
+                            source_config.vendor_id = device_list[selected_idx].vendor_id;
+                            source_config.product_id = device_list[selected_idx].product_id;
+                            source_config.serial_number =
+                                device_list[selected_idx].serial_number.clone();
+                        }
+                    },
+                    thread_running,
+                );
+            }
+
+            let mut is_virtual = source_config.is_virtual();
+            if ui
+                .add_enabled(!thread_running, egui::Checkbox::new(&mut is_virtual, "Virtual"))
+                .on_hover_text("Virtual sources are set from the UI or the control port instead of a device")
+                .changed()
+            {
+                source_config.kind = if is_virtual { SourceKind::Virtual } else { SourceKind::Hid };
+            }
+            draw_slot_warning(ui, warning.as_deref());
+            let device = device_list.get(selected_device_idx).filter(|_| selected_device_idx != 0);
+            draw_slot_details_menu(
                 ui,
-                format!("source_combo_{}", i),
-                device_list, // Pass immutable borrow
-                selected_device_idx,
-                |selected_idx| {
-                    if selected_idx < device_list.len() { // Bounds check
-                        source_config.vendor_id = device_list[selected_idx].vendor_id;
-                        source_config.product_id = device_list[selected_idx].product_id;
-                        source_config.serial_number =
-                            device_list[selected_idx].serial_number.clone();
-                    }
-                },
+                &format!("source_{}", i),
+                source_config,
+                device,
+                nicknames,
+                interface_overrides,
                 thread_running,
             );
+            open_details = open_details.take().or(details_button(ui, device));
+            // The last source can't be removed; the section always keeps one slot
+            slot_action = slot_action.or(draw_slot_controls(ui, source_config, i, num_sources, num_sources > 1, thread_running));
         }); // Mutable borrow of source_config might end here or after status bits
+        draw_slot_note(ui, source_config);
+        let is_virtual = source_config.is_virtual();
 
         // Draw status bits for this source
         if let Some(state_arc) = source_states.get(i) {
@@ -202,42 +966,59 @@ fn draw_sources_section(
                 vid,
                 pid,
                 thread_running,
-                thread_running,
-                true
+                false, // Masks can be edited live
+                !is_virtual
             );
+
+            if is_virtual {
+                draw_virtual_source_toggles(ui, state_arc, state_val);
+            }
         } else {
             ui.colored_label(Color32::RED, "Error: State mismatch");
         }
 
         ui.add_space(5.0);
     } // Mutable borrow of source_config definitely ends here
+
+    if let Some(action) = slot_action {
+        app.handle_slot_action(action, true);
+    }
+    if open_details.is_some() {
+        app.details_device = open_details;
+    }
+
+    if app.config.data.sources.iter().any(|source| source.is_virtual()) {
+        ui.horizontal(|ui| {
+            ui.label("Control port:");
+            ui.add_enabled(
+                !thread_running,
+                egui::DragValue::new(&mut app.config.data.control_port).range(0..=65535),
+            )
+            .on_hover_text("Localhost TCP port for scripts, e.g. 'toggle 2 DTNT'. 0 disables it. Applies on next launch.");
+        });
+    }
     ui.add_space(10.0);
 }
 
-fn draw_rules_section(
-    app: &mut ShiftTool,
-    ui: &mut Ui,
-    thread_running: bool,
-) {
+fn draw_rules_section(app: &mut ShiftTool, ui: &mut Ui) {
     ui.heading("Rules & Result");
     ui.horizontal(|ui| {
         ui.label("Rules:");
-        ui.add_enabled_ui(!thread_running, |ui| {
-            for j in 0..8 {
-                let current_modifier = app.config.data.shift_modifiers[j];
-                if ui
-                    .selectable_label(false, format!("{}", current_modifier))
-                    .clicked()
-                {
-                    // Cycle through modifiers on click
-                    app.config.data.shift_modifiers[j] = match current_modifier {
-                        ShiftModifiers::OR => ShiftModifiers::AND,
-                        ShiftModifiers::AND => ShiftModifiers::XOR,
-                        ShiftModifiers::XOR => ShiftModifiers::OR,
-                    };
-                }
+        // Editable while running; changes are pushed to the worker live
+        for j in 0..8 {
+            let current_modifier = app.config.data.shift_modifiers[j];
+            if ui
+                .selectable_label(false, format!("{}", current_modifier))
+                .clicked()
+            {
+                // Cycle through modifiers on click
+                app.config.data.shift_modifiers[j] = match current_modifier {
+                    ShiftModifiers::OR => ShiftModifiers::AND,
+                    ShiftModifiers::AND => ShiftModifiers::XOR,
+                    ShiftModifiers::XOR => ShiftModifiers::OR,
+                };
             }
-        });
+        }
     });
 
     // Display combined result state
@@ -266,16 +1047,23 @@ fn draw_receivers_section(
         ui.label("(Add a receiver using the controls on the right)");
     }
     // Iterate by index
-    for i in 0..app.config.data.receivers.len() {
+    let num_receivers = app.config.data.receivers.len();
+    let mut slot_action = None;
+    let mut open_details = None;
+    for i in 0..num_receivers {
         // --- Immutable Operations First ---
         let saved_config_for_find = app.config.data.receivers[i].clone();
         let selected_device_idx = crate::device::find_device_index_for_saved(
             &app.device_list,
             &saved_config_for_find,
         );
+        let warning = app.slot_warning(&saved_config_for_find, true, i);
 
         // --- Mutable Borrow Scope ---
         let receiver_config = &mut app.config.data.receivers[i];
+        let nicknames = &mut app.config.data.device_nicknames;
+        let interface_overrides = &mut app.config.data.interface_overrides;
+        let catalog = &app.config.data.device_catalog;
         let device_list = &app.device_list;
         let receiver_states = &app.receiver_states;
 
@@ -283,11 +1071,12 @@ fn draw_receivers_section(
         let pid = receiver_config.product_id;
 
         ui.horizontal(|ui| {
-            ui.label(format!("Receiver {}:", i + 1));
+            slot_label(ui, format!("Receiver {}:", i + 1), receiver_config.enabled);
             device_selector_combo(
                 ui,
                 format!("receiver_combo_{}", i),
                 device_list,
+                catalog,
                 selected_device_idx,
                 |selected_idx| {
                     if selected_idx < device_list.len() { // Bounds check
@@ -299,7 +1088,21 @@ fn draw_receivers_section(
                 },
                 thread_running,
             );
+            draw_slot_warning(ui, warning.as_deref());
+            let device = device_list.get(selected_device_idx).filter(|_| selected_device_idx != 0);
+            draw_slot_details_menu(
+                ui,
+                &format!("receiver_{}", i),
+                receiver_config,
+                device,
+                nicknames,
+                interface_overrides,
+                thread_running,
+            );
+            open_details = open_details.take().or(details_button(ui, device));
+            slot_action = slot_action.or(draw_slot_controls(ui, receiver_config, i, num_receivers, true, thread_running));
         }); // Mut borrow might end here
+        draw_slot_note(ui, receiver_config);
 
         if let Some(state_arc) = receiver_states.get(i) {
             let state_val = match state_arc.lock() { // Use match
@@ -320,7 +1123,7 @@ fn draw_receivers_section(
                 vid,
                 pid,
                 thread_running,
-                thread_running,
+                false, // Masks can be edited live
                 true
             );
         } else {
@@ -329,6 +1132,313 @@ fn draw_receivers_section(
 
         ui.add_space(5.0);
     } // Mut borrow ends here
+
+    if let Some(action) = slot_action {
+        app.handle_slot_action(action, false);
+    }
+    if open_details.is_some() {
+        app.details_device = open_details;
+    }
+}
+
+// Slot name, greyed out when the slot is disabled
+fn slot_label(ui: &mut Ui, text: String, enabled: bool) {
+    if enabled {
+        ui.label(text);
+    } else {
+        ui.label(egui::RichText::new(text).weak().strikethrough())
+            .on_hover_text("Disabled: the worker skips this slot");
+    }
+}
+
+/// Menu for the nickname of the slot's device and the slot's own note.
+/// Neither affects the worker, so both stay editable while running.
+fn draw_slot_details_menu(
+    ui: &mut Ui,
+    id: &str, // Side and index of the slot, e.g. "source_0", for widget ids
+    slot: &mut crate::device::SavedDevice,
+    device: Option<&VpcDevice>, // Connected device the slot points at
+    nicknames: &mut std::collections::BTreeMap<String, String>,
+    interface_overrides: &mut std::collections::BTreeMap<String, crate::device::InterfaceOverride>,
+    thread_running: bool,
+) {
+    ui.menu_button("✏", |ui| {
+        if !slot.is_virtual() && (slot.vendor_id != 0 || slot.product_id != 0) {
+            // Shared by every slot using this device
+            let key = slot.device_key();
+            let mut nickname = nicknames.get(&key).cloned().unwrap_or_default();
+            ui.label("Device nickname:");
+            if ui
+                .add(egui::TextEdit::singleline(&mut nickname).hint_text("e.g. Left grip"))
+                .changed()
+            {
+                if nickname.trim().is_empty() {
+                    nicknames.remove(&key);
+                } else {
+                    nicknames.insert(key, nickname);
+                }
+            }
+        }
+        if !slot.is_virtual() {
+            draw_slot_matcher(ui, id, slot, device, thread_running);
+        }
+        if let Some(device) = device.filter(|d| d.interfaces.len() > 1) {
+            draw_interface_override(ui, id, device, interface_overrides, thread_running);
+        }
+        ui.label("Slot note:");
+        ui.add(egui::TextEdit::multiline(&mut slot.note).desired_rows(2));
+    })
+    .response
+    .on_hover_text("Nickname, device match, interface and note");
+}
+
+// Picks how the slot finds its device(s): the exact device, any device of the
+// model, or every device whose name matches. Only changes while stopped.
+fn draw_slot_matcher(
+    ui: &mut Ui,
+    id: &str,
+    slot: &mut crate::device::SavedDevice,
+    device: Option<&VpcDevice>,
+    thread_running: bool,
+) {
+    use crate::device::SlotMatcher;
+    ui.label("Device match:");
+    ui.add_enabled_ui(!thread_running, |ui| {
+        egui::ComboBox::from_id_salt(format!("slot_matcher_{}", id))
+            .width(260.0)
+            .selected_text(slot.matcher.to_string())
+            .show_ui(ui, |ui| {
+                for matcher in [SlotMatcher::Exact, SlotMatcher::SameModel, SlotMatcher::NamePattern] {
+                    if ui.selectable_value(&mut slot.matcher, matcher, matcher.to_string()).clicked()
+                        && matcher == SlotMatcher::NamePattern
+                        && slot.name_pattern.is_empty()
+                    {
+                        // Start from the selected device's product name
+                        slot.name_pattern = device.map_or_else(String::new, |d| d.name.to_string());
+                    }
+                }
+            });
+        if slot.matcher == SlotMatcher::NamePattern {
+            ui.add(egui::TextEdit::singleline(&mut slot.name_pattern).hint_text("e.g. Throttle"))
+                .on_hover_text("Part of the product name (case-insensitive); every connected device containing it is used.");
+        }
+    });
+}
+
+// Picks which HID interface of a composite device is opened. Like the device
+// itself, it can only change while stopped.
+fn draw_interface_override(
+    ui: &mut Ui,
+    id: &str,
+    device: &VpcDevice,
+    interface_overrides: &mut std::collections::BTreeMap<String, crate::device::InterfaceOverride>,
+    thread_running: bool,
+) {
+    use crate::device::InterfaceOverride;
+    let key = device.full_name.clone();
+    let current = interface_overrides.get(&key).copied();
+    let chosen = device
+        .chosen_interface()
+        .map_or_else(|| "none".to_string(), |interface| interface.to_string());
+    let selected_text = match current {
+        Some(_) => chosen,
+        None => format!("Automatic: {}", chosen),
+    };
+    ui.label("HID interface:");
+    ui.add_enabled_ui(!thread_running, |ui| {
+        egui::ComboBox::from_id_salt(format!("interface_{}", id))
+            .width(260.0)
+            .selected_text(selected_text)
+            .show_ui(ui, |ui| {
+                if ui.selectable_label(current.is_none(), "Automatic").clicked() {
+                    interface_overrides.remove(&key);
+                }
+                for interface in &device.interfaces {
+                    let selected = current.is_some_and(|chosen| chosen.matches(interface));
+                    if ui.selectable_label(selected, interface.to_string()).clicked() {
+                        interface_overrides.insert(key.clone(), InterfaceOverride::of(interface));
+                    }
+                }
+            });
+    });
+}
+
+// Button opening the details window for the slot's device; returns its key when clicked
+fn details_button(ui: &mut Ui, device: Option<&VpcDevice>) -> Option<String> {
+    let device = device?;
+    ui.button("ℹ")
+        .on_hover_text("Device details")
+        .clicked()
+        .then(|| device.full_name.clone())
+}
+
+// Warning sign next to a slot whose device can't be used as configured
+fn draw_slot_warning(ui: &mut Ui, warning: Option<&str>) {
+    if let Some(warning) = warning {
+        ui.label(egui::RichText::new("⚠").color(Color32::YELLOW)).on_hover_text(warning);
+    }
+}
+
+// The slot's note under its row, if it has one
+fn draw_slot_note(ui: &mut Ui, slot: &crate::device::SavedDevice) {
+    if !slot.note.trim().is_empty() {
+        ui.label(egui::RichText::new(format!("   {}", slot.note.trim())).weak().italics());
+    }
+}
+
+/// Draws the enable toggle and the move/remove buttons of a slot row.
+/// Layout changes need the worker stopped, like device selection.
+fn draw_slot_controls(
+    ui: &mut Ui,
+    slot: &mut crate::device::SavedDevice,
+    idx: usize,
+    len: usize,
+    can_remove: bool,
+    thread_running: bool,
+) -> Option<SlotAction> {
+    let mut action = None;
+    ui.add_enabled(!thread_running, egui::Checkbox::new(&mut slot.enabled, "On"))
+        .on_hover_text("Disabled slots keep their settings but are left out when running");
+    if ui.add_enabled(!thread_running && idx > 0, egui::Button::new("▲").small()).on_hover_text("Move up").clicked() {
+        action = Some(SlotAction::MoveUp(idx));
+    }
+    if ui.add_enabled(!thread_running && idx + 1 < len, egui::Button::new("▼").small()).on_hover_text("Move down").clicked() {
+        action = Some(SlotAction::MoveDown(idx));
+    }
+    if ui.add_enabled(!thread_running && can_remove, egui::Button::new("✖").small()).on_hover_text("Remove").clicked() {
+        action = Some(SlotAction::Remove(idx));
+    }
+    action
+}
+
+fn draw_outputs_section(
+    app: &mut ShiftTool,
+    ui: &mut Ui,
+    thread_running: bool,
+) {
+    ui.heading("Outputs");
+    let uinput_available = cfg!(target_os = "linux");
+    let num_receivers = app.config.data.receivers.len();
+    let joystick_config = &mut app.config.data.virtual_joystick;
+
+    ui.horizontal(|ui| {
+        ui.add_enabled_ui(!thread_running && uinput_available, |ui| {
+            ui.checkbox(&mut joystick_config.enabled, "Virtual Joystick")
+                .on_hover_text("Create a uinput joystick whose buttons mirror the shift bits");
+
+            ui.label("Mirror:");
+            egui::ComboBox::from_id_salt("virtual_joystick_source")
+                .selected_text(format!("{}", joystick_config.source))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut joystick_config.source, JoystickSource::Result, "Result");
+                    for idx in 0..num_receivers {
+                        let source = JoystickSource::Receiver(idx);
+                        ui.selectable_value(&mut joystick_config.source, source, format!("{}", source));
+                    }
+                });
+        });
+
+        if !uinput_available {
+            ui.label(egui::RichText::new("(Linux only)").color(Color32::GRAY));
+        }
+    });
+
+    // Key bindings typed through the virtual keyboard on result transitions
+    let mut remove_idx = None;
+    for (idx, binding) in app.config.data.key_bindings.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            ui.add_enabled_ui(!thread_running && uinput_available, |ui| {
+                ui.label("When result");
+                egui::ComboBox::from_id_salt(format!("key_binding_bit_{}", idx))
+                    .width(60.0)
+                    .selected_text(bit_name(binding.bit))
+                    .show_ui(ui, |ui| {
+                        for bit in 0..8u8 {
+                            ui.selectable_value(&mut binding.bit, bit, bit_name(bit));
+                        }
+                    });
+                egui::ComboBox::from_id_salt(format!("key_binding_edge_{}", idx))
+                    .width(80.0)
+                    .selected_text(format!("{}", binding.edge))
+                    .show_ui(ui, |ui| {
+                        for edge in [BitEdge::Rising, BitEdge::Falling] {
+                            ui.selectable_value(&mut binding.edge, edge, format!("{}", edge));
+                        }
+                    });
+                ui.label("press");
+                ui.add(
+                    egui::TextEdit::singleline(&mut binding.keys)
+                        .hint_text("Ctrl+F3")
+                        .desired_width(140.0),
+                );
+                if let Err(e) = parse_key_sequence(&binding.keys) {
+                    ui.colored_label(DISABLED_COLOR, "Invalid").on_hover_text(e);
+                }
+                if ui.button("Remove").clicked() {
+                    remove_idx = Some(idx);
+                }
+            });
+        });
+    }
+    if let Some(idx) = remove_idx {
+        app.handle_remove_key_binding(idx);
+    }
+
+    if ui
+        .add_enabled(!thread_running && uinput_available, egui::Button::new("Add Key Binding"))
+        .clicked()
+    {
+        app.handle_add_key_binding();
+    }
+    ui.add_space(10.0);
+}
+
+fn draw_hooks_section(
+    app: &mut ShiftTool,
+    ui: &mut Ui,
+    thread_running: bool,
+) {
+    ui.heading("Hooks");
+    let mut remove_idx = None;
+    for (idx, hook) in app.config.data.hooks.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            ui.add_enabled_ui(!thread_running, |ui| {
+                ui.label("On");
+                egui::ComboBox::from_id_salt(format!("hook_event_{}", idx))
+                    .width(120.0)
+                    .selected_text(format!("{}", hook.event))
+                    .show_ui(ui, |ui| {
+                        for event in HookEvent::ALL {
+                            ui.selectable_value(&mut hook.event, event, format!("{}", event));
+                        }
+                    });
+                ui.label("run");
+                ui.add(
+                    egui::TextEdit::singleline(&mut hook.command)
+                        .hint_text("command")
+                        .desired_width(200.0),
+                )
+                .on_hover_text("Receives SHIFT_TOOL_EVENT, SHIFT_TOOL_OLD_STATE, SHIFT_TOOL_NEW_STATE, SHIFT_TOOL_CHANGED_BITS, SHIFT_TOOL_SLOT and SHIFT_TOOL_DEVICE_SERIAL");
+                ui.add(
+                    egui::DragValue::new(&mut hook.timeout_ms)
+                        .range(100..=600_000)
+                        .suffix(" ms"),
+                )
+                .on_hover_text("The command is killed if it runs longer than this");
+                if ui.button("Remove").clicked() {
+                    remove_idx = Some(idx);
+                }
+            });
+        });
+    }
+    if let Some(idx) = remove_idx {
+        app.handle_remove_hook(idx);
+    }
+
+    if ui.add_enabled(!thread_running, egui::Button::new("Add Hook")).clicked() {
+        app.handle_add_hook();
+    }
+    ui.add_space(10.0);
 }
 
 // --- UI Helper Widgets ---
@@ -338,6 +1448,7 @@ fn device_selector_combo(
     ui: &mut Ui,
     id_source: impl std::hash::Hash,
     device_list: &[VpcDevice],
+    catalog: &[CatalogEntry], // Extra catalog entries from the config
     selected_device_idx: usize,
     mut on_select: impl FnMut(usize), // Closure called when selection changes
     disabled: bool,
@@ -356,11 +1467,26 @@ fn device_selector_combo(
             .show_ui(ui, |ui| {
                 for (j, device) in device_list.iter().enumerate() {
                     // Use selectable_value to handle selection logic
+                    let mut details = match crate::catalog::lookup(catalog, device.product_id, &device.name) {
+                        Some(entry) if j != 0 => entry.to_string(),
+                        _ if j != 0 => "Unknown model".to_string(),
+                        _ => "Leave the slot unconnected".to_string(),
+                    };
+                    if j != 0 {
+                        details.push_str(&format!("\nFirmware: {}", device.firmware));
+                        if let Some(interface) = device.chosen_interface().filter(|_| device.interfaces.len() > 1) {
+                            details.push_str(&format!("\nInterface: {}", interface));
+                        }
+                        if let Some(problem) = device.firmware.diagnostic() {
+                            details.push_str(&format!("\n⚠ {}", problem));
+                        }
+                    }
+                    // Devices the firmware policy rejects are listed but can't be picked
+                    let item = egui::SelectableLabel::new(j == selected_device_idx, format!("{}", device));
                     if ui
-                        .selectable_label(
-                            j == selected_device_idx,
-                            format!("{}", device),
-                        )
+                        .add_enabled(device.unsupported.is_none(), item)
+                        .on_hover_text(details)
+                        .on_disabled_hover_text(device.unsupported.as_deref().unwrap_or_default())
                         .clicked()
                     {
                         if j != selected_device_idx {
@@ -464,6 +1590,25 @@ fn draw_status_bits(
     });
 }
 
+/// Draws clickable bits that set the state of a virtual source.
+/// Stays enabled while the worker runs; it picks the state up next cycle.
+fn draw_virtual_source_toggles(ui: &mut Ui, state_arc: &crate::SharedDeviceState, state_value: u16) {
+    ui.horizontal(|ui| {
+        ui.label("     Set:");
+        for bit_pos in 0..8u8 {
+            if ui
+                .selectable_label(read_bit(state_value, bit_pos), bit_name(bit_pos))
+                .clicked()
+            {
+                match state_arc.lock() {
+                    Ok(mut guard) => *guard ^= 1 << bit_pos,
+                    Err(_) => log::error!("UI: Mutex poisoned while toggling virtual source bit!"),
+                }
+            }
+        }
+    });
+}
+
 /// Draws the control buttons in the right column.
 fn draw_control_buttons(
     app: &mut ShiftTool,
@@ -490,39 +1635,74 @@ fn draw_control_buttons(
 
     // ui.separator();
 
-    // Add/Remove Source Buttons
+    // Add Source/Receiver Buttons; slots are removed from their own row
     if ui.add_enabled(!thread_running, egui::Button::new("Add Source")).clicked() {
         app.handle_add_source();
     }
-    if app.config.data.sources.len() > 1 { // Only show remove if more than 1
-        if ui.add_enabled(!thread_running, egui::Button::new("Remove Source")).clicked() {
-            app.handle_remove_source();
-        }
-    }
 
     // ui.separator();
 
-    // Add/Remove Receiver Buttons
     if ui.add_enabled(!thread_running, egui::Button::new("Add Receiver")).clicked() {
         app.handle_add_receiver();
     }
-    if !app.config.data.receivers.is_empty() { // Only show remove if > 0
-        if ui.add_enabled(!thread_running, egui::Button::new("Remove Receiver")).clicked() {
-            app.handle_remove_receiver();
-        }
-    }
 
     // ui.separator();
 
+    // Undo/Redo; steps that change devices have to wait until the worker is stopped
+    ui.horizontal(|ui| {
+        if ui
+            .add_enabled(app.can_undo(thread_running), egui::Button::new("Undo"))
+            .on_hover_text("Ctrl+Z")
+            .clicked()
+        {
+            app.handle_undo();
+        }
+        if ui
+            .add_enabled(app.can_redo(thread_running), egui::Button::new("Redo"))
+            .on_hover_text("Ctrl+Shift+Z")
+            .clicked()
+        {
+            app.handle_redo();
+        }
+    });
+
     // Other Buttons
-    // if ui.add_enabled(!thread_running, egui::Button::new("Save Config")).clicked() {
-    //     if let Err(e) = app.config.save() {
-    //         log::error!("Failed to manually save config: {}", e);
-    //         // Optionally show feedback to user in UI
-    //     } else {
-    //         log::info!("Configuration saved manually.");
-    //     }
-    // }
+    let dirty = app.has_unsaved_changes();
+    if ui
+        .add_enabled(dirty, egui::Button::new("Save Config"))
+        .on_hover_text("Ctrl+S")
+        .clicked()
+    {
+        if let Err(e) = app.save_config_now() {
+            log::error!("Failed to save config: {}", e);
+        }
+    }
+    if ui.add_enabled(dirty && !thread_running, egui::Button::new("Revert")).clicked() {
+        app.revert_config();
+    }
+    if dirty {
+        ui.colored_label(Color32::YELLOW, "● Unsaved changes");
+    }
+
+    // Newest first; restoring is only allowed while stopped, like other routing changes
+    ui.add_enabled_ui(!thread_running, |ui| {
+        ui.menu_button("Restore backup…", |ui| {
+            let backups = crate::config_file::backup_files(&app.config.path);
+            if backups.is_empty() {
+                ui.label("No backups yet");
+            }
+            for backup in backups {
+                let label = backup
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                if ui.button(label).on_hover_text(backup.display().to_string()).clicked() {
+                    app.handle_restore_backup(&backup);
+                    ui.close_menu();
+                }
+            }
+        });
+    });
 
     if ui.add_enabled(!thread_running, egui::Button::new("Refresh Devices")).clicked() {
         log::info!("Refreshing device list manually.");
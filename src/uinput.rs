@@ -0,0 +1,309 @@
+use crate::config::{BitEdge, KeyBinding};
+use log::{debug, warn};
+#[cfg(target_os = "linux")]
+use log::info;
+use std::io;
+#[cfg(target_os = "linux")]
+use std::sync::mpsc;
+#[cfg(target_os = "linux")]
+use std::time::Duration;
+
+#[cfg(target_os = "linux")]
+use evdev::{
+    uinput::{VirtualDevice, VirtualDeviceBuilder},
+    AbsInfo, AbsoluteAxisType, AttributeSet, BusType, EventType, InputEvent, InputId, Key,
+    UinputAbsSetup,
+};
+
+pub const JOYSTICK_NAME: &str = "OpenVPC Shift Tool Joystick";
+pub const KEYBOARD_NAME: &str = "OpenVPC Shift Tool Keyboard";
+
+// How long each chord is held down. Games polling input once per frame
+// miss presses that are released within the same SYN_REPORT.
+#[cfg(target_os = "linux")]
+const KEY_HOLD_MS: u64 = 20;
+
+// Buttons used for shift bits 0-7 (1-5, DTNT, ZOOM, TRIM).
+// They sit in the BTN_JOYSTICK range so games enumerate the device as a joystick.
+#[cfg(target_os = "linux")]
+const JOYSTICK_BUTTONS: [Key; 8] = [
+    Key::BTN_TRIGGER,
+    Key::BTN_THUMB,
+    Key::BTN_THUMB2,
+    Key::BTN_TOP,
+    Key::BTN_TOP2,
+    Key::BTN_PINKIE,
+    Key::BTN_BASE,
+    Key::BTN_BASE2,
+];
+
+/// A virtual joystick whose buttons mirror the bits of a shift state.
+///
+/// Backed by a uinput device on Linux. On other platforms `new` always fails,
+/// so callers can treat the output as unavailable without extra `cfg` checks.
+pub struct VirtualJoystick {
+    #[cfg(target_os = "linux")]
+    device: VirtualDevice,
+    last_state: u16,
+}
+
+impl VirtualJoystick {
+    /// Creates the uinput device. Requires write access to `/dev/uinput`.
+    #[cfg(target_os = "linux")]
+    pub fn new() -> io::Result<Self> {
+        let mut buttons = AttributeSet::<Key>::new();
+        for button in JOYSTICK_BUTTONS {
+            buttons.insert(button);
+        }
+
+        // Some games ignore joysticks without axes, so expose a centred X/Y pair.
+        let axis_info = AbsInfo::new(0, -1, 1, 0, 0, 0);
+
+        let device = VirtualDeviceBuilder::new()?
+            .name(JOYSTICK_NAME)
+            .input_id(InputId::new(BusType::BUS_VIRTUAL, 0, 0, 1))
+            .with_keys(&buttons)?
+            .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_X, axis_info))?
+            .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_Y, axis_info))?
+            .build()?;
+
+        info!("Created virtual joystick '{}'.", JOYSTICK_NAME);
+        Ok(Self {
+            device,
+            last_state: 0,
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn new() -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Virtual joystick output requires Linux uinput",
+        ))
+    }
+
+    /// Presses or releases the buttons whose bits changed since the last update.
+    pub fn update(&mut self, state: u16) -> io::Result<()> {
+        let changed = self.last_state ^ state;
+        if changed == 0 {
+            return Ok(());
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            let events: Vec<InputEvent> = JOYSTICK_BUTTONS
+                .iter()
+                .enumerate()
+                .filter(|(bit, _)| crate::util::read_bit(changed, *bit as u8))
+                .map(|(bit, button)| {
+                    let pressed = crate::util::read_bit(state, bit as u8);
+                    InputEvent::new(EventType::KEY, button.code(), pressed as i32)
+                })
+                .collect();
+            self.device.emit(&events)?;
+        }
+
+        debug!("Virtual joystick state {} -> {}", self.last_state, state);
+        self.last_state = state;
+        Ok(())
+    }
+}
+
+/// Parses a key binding string into chords of Linux key codes.
+///
+/// Chords are separated by whitespace and keys inside a chord are joined
+/// with `+`, e.g. `"Ctrl+F3"` or `"Esc Ctrl+S"`. Key names are the evdev
+/// names with or without the `KEY_` prefix (`F3`, `KEY_F3`), plus the
+/// aliases `Ctrl`, `Shift`, `Alt`, `AltGr`, `Super`/`Win` and their `R`-prefixed
+/// right-hand variants.
+pub fn parse_key_sequence(text: &str) -> Result<Vec<Vec<u16>>, String> {
+    let mut chords = Vec::new();
+    for chord_text in text.split_whitespace() {
+        let mut chord = Vec::new();
+        for key_name in chord_text.split('+') {
+            if key_name.is_empty() {
+                return Err(format!("Empty key name in '{}'", chord_text));
+            }
+            chord.push(parse_key_name(key_name)?);
+        }
+        chords.push(chord);
+    }
+    if chords.is_empty() {
+        return Err("No keys given".to_string());
+    }
+    Ok(chords)
+}
+
+#[cfg(target_os = "linux")]
+fn parse_key_name(name: &str) -> Result<u16, String> {
+    use std::str::FromStr;
+
+    let upper = name.to_ascii_uppercase();
+    let alias = match upper.as_str() {
+        "CTRL" | "CONTROL" => Some(Key::KEY_LEFTCTRL),
+        "RCTRL" => Some(Key::KEY_RIGHTCTRL),
+        "SHIFT" => Some(Key::KEY_LEFTSHIFT),
+        "RSHIFT" => Some(Key::KEY_RIGHTSHIFT),
+        "ALT" => Some(Key::KEY_LEFTALT),
+        "ALTGR" | "RALT" => Some(Key::KEY_RIGHTALT),
+        "SUPER" | "WIN" | "META" => Some(Key::KEY_LEFTMETA),
+        "RSUPER" | "RWIN" | "RMETA" => Some(Key::KEY_RIGHTMETA),
+        _ => None,
+    };
+    if let Some(key) = alias {
+        return Ok(key.code());
+    }
+
+    let full_name = if upper.starts_with("KEY_") { upper } else { format!("KEY_{}", upper) };
+    Key::from_str(&full_name)
+        .map(|key| key.code())
+        .map_err(|_| format!("Unknown key '{}'", name))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn parse_key_name(_name: &str) -> Result<u16, String> {
+    Err("Keyboard output requires Linux uinput".to_string())
+}
+
+// A key binding with its key string already parsed
+struct ParsedBinding {
+    bit: u8,
+    edge: BitEdge,
+    chords: Vec<Vec<u16>>,
+}
+
+/// A virtual keyboard that types the configured key bindings when result bits change.
+///
+/// The keys are typed on a thread of their own, in the order the bindings
+/// fired, so holding each chord down never holds up the caller. The thread
+/// ends when the keyboard is dropped.
+pub struct VirtualKeyboard {
+    #[cfg(target_os = "linux")]
+    typist: mpsc::Sender<Vec<Vec<u16>>>, // Chords of each fired binding
+    bindings: Vec<ParsedBinding>,
+}
+
+impl VirtualKeyboard {
+    /// Parses the bindings and creates the uinput device.
+    ///
+    /// Bindings with invalid key strings are skipped with a warning so one
+    /// typo doesn't disable the whole keyboard output.
+    pub fn new(bindings: &[KeyBinding]) -> io::Result<Self> {
+        let mut parsed = Vec::new();
+        for binding in bindings {
+            match parse_key_sequence(&binding.keys) {
+                Ok(chords) => parsed.push(ParsedBinding {
+                    bit: binding.bit,
+                    edge: binding.edge,
+                    chords,
+                }),
+                Err(e) => warn!(
+                    "Skipping key binding for bit {} ({}): {}",
+                    crate::util::bit_name(binding.bit), binding.keys, e
+                ),
+            }
+        }
+        Self::create(parsed)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn create(bindings: Vec<ParsedBinding>) -> io::Result<Self> {
+        let mut keys = AttributeSet::<Key>::new();
+        for binding in &bindings {
+            for code in binding.chords.iter().flatten() {
+                keys.insert(Key::new(*code));
+            }
+        }
+
+        let device = VirtualDeviceBuilder::new()?
+            .name(KEYBOARD_NAME)
+            .input_id(InputId::new(BusType::BUS_VIRTUAL, 0, 0, 1))
+            .with_keys(&keys)?
+            .build()?;
+
+        let (typist, queue) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("key-typist".to_string())
+            .spawn(move || type_queued(device, queue))?;
+
+        info!("Created virtual keyboard '{}' with {} key binding(s).", KEYBOARD_NAME, bindings.len());
+        Ok(Self { typist, bindings })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn create(_bindings: Vec<ParsedBinding>) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Keyboard output requires Linux uinput",
+        ))
+    }
+
+    /// Queues every binding whose bit changed in the configured direction.
+    pub fn handle_transition(&mut self, old_state: u16, new_state: u16) -> io::Result<()> {
+        let changed = old_state ^ new_state;
+        if changed == 0 {
+            return Ok(());
+        }
+
+        for idx in 0..self.bindings.len() {
+            let bit = self.bindings[idx].bit;
+            if !crate::util::read_bit(changed, bit) {
+                continue;
+            }
+            let is_high = crate::util::read_bit(new_state, bit);
+            let fires = match self.bindings[idx].edge {
+                BitEdge::Rising => is_high,
+                BitEdge::Falling => !is_high,
+            };
+            if fires {
+                debug!("Key binding fired: bit {} {}", crate::util::bit_name(bit), self.bindings[idx].edge);
+                self.type_binding(idx)?;
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    fn type_binding(&mut self, idx: usize) -> io::Result<()> {
+        self.typist
+            .send(self.bindings[idx].chords.clone())
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Key typing thread has stopped"))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn type_binding(&mut self, _idx: usize) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Types queued macros until the keyboard is dropped
+#[cfg(target_os = "linux")]
+fn type_queued(mut device: VirtualDevice, queue: mpsc::Receiver<Vec<Vec<u16>>>) {
+    for chords in queue {
+        if let Err(e) = type_chords(&mut device, &chords) {
+            warn!("Failed to send key binding: {}", e);
+        }
+    }
+    debug!("Virtual keyboard closed.");
+}
+
+#[cfg(target_os = "linux")]
+fn type_chords(device: &mut VirtualDevice, chords: &[Vec<u16>]) -> io::Result<()> {
+    for chord in chords {
+        let press: Vec<InputEvent> = chord
+            .iter()
+            .map(|code| InputEvent::new(EventType::KEY, *code, 1))
+            .collect();
+        // Release in reverse order so modifiers are let go last
+        let release: Vec<InputEvent> = chord
+            .iter()
+            .rev()
+            .map(|code| InputEvent::new(EventType::KEY, *code, 0))
+            .collect();
+
+        device.emit(&press)?;
+        std::thread::sleep(Duration::from_millis(KEY_HOLD_MS));
+        device.emit(&release)?;
+    }
+    Ok(())
+}
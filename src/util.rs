@@ -1,4 +1,4 @@
-use clap::Parser;
+use crate::catalog::ShiftFormat;
 use chrono::NaiveDate;
 use log::{error, trace, warn};
 
@@ -140,9 +140,15 @@ const FORMAT_NEW: ReportFormat = ReportFormat {
     low_byte_idx: 2,
 };
 
+// First firmware build using the 19 byte shift report
+const NEW_FORMAT_SINCE: NaiveDate = match NaiveDate::from_ymd_opt(2024, 12, 26) {
+    Some(date) => date,
+    None => panic!("invalid date"),
+};
+
 struct FormatRule {
-    // Criteria: Function that takes firmware string and returns true if it matches
-    matches: fn(&str, &str) -> bool,
+    // Criteria: Function that takes the product name and firmware and returns true if it matches
+    matches: fn(&str, &FirmwareVersion) -> bool,
     // Result: The format to use if criteria matches
     format: ReportFormat,
 }
@@ -150,22 +156,12 @@ struct FormatRule {
 const FORMAT_RULES: &[FormatRule] = &[
     // Rule 1: Check for Original format based on date
     FormatRule {
-        matches: |_name, fw| {
-            const THRESHOLD: &str = "2024-12-26";
-            let date_str = fw.split_whitespace().last().unwrap_or("");
-            if date_str.len() == 8 {
-                if let Ok(fw_date) = NaiveDate::parse_from_str(date_str, "%Y%m%d") {
-                    if let Ok(t_date) = NaiveDate::parse_from_str(THRESHOLD, "%Y-%m-%d") {
-                        return fw_date < t_date; // Return true if older
-                    }
-                }
-            }
-            false // Don't match if parsing fails or format wrong
-        },
+        // Don't match if the firmware has no build date
+        matches: |_name, fw| fw.date.is_some_and(|date| date < NEW_FORMAT_SINCE),
         format: FORMAT_ORIGINAL,
     },
     // Rule 2: Add more rules here if needed (e.g., for FORMAT_MIDDLE)
-    // FormatRule { matches: |fw| fw.contains("SPECIAL"), format: FORMAT_MIDDLE },
+    // FormatRule { matches: |_name, fw| fw.raw.contains("SPECIAL"), format: FORMAT_MIDDLE },
 
     // Rule N: Default rule (matches anything if previous rules didn't)
     // This isn't strictly needed if we have a default below, but can be explicit.
@@ -173,26 +169,77 @@ const FORMAT_RULES: &[FormatRule] = &[
 ];
 
 // --- The main function to determine the format ---
-pub(crate) fn determine_report_format(name: &str, firmware: &str) -> ReportFormat {
+pub(crate) fn determine_report_format(name: &str, firmware: &FirmwareVersion) -> ReportFormat {
     // Iterate through the rules
     for rule in FORMAT_RULES {
         if (rule.matches)(name, firmware) {
-            trace!("Device '{}' Firmware '{}' matched rule for format '{}'", name, firmware, rule.format.name);
+            trace!("Device '{}' Firmware '{}' matched rule for format '{}'", name, firmware.raw, rule.format.name);
             return rule.format;
         }
     }
 
     // If no rules matched, return a default (e.g., the newest format)
-    let default_format = FORMAT_NEW; // Define the default
+    let default_format = DEFAULT_REPORT_FORMAT; // Define the default
     warn!(
         "Firmware '{}' did not match any specific rules. Defaulting to format '{}'",
-        firmware, default_format.name
+        firmware.raw, default_format.name
     );
     default_format
 }
 
+/// Report format for a device whose catalog entry may pin one; `Auto` (or no
+/// entry) falls back to the firmware rules.
+pub(crate) fn report_format_for(name: &str, firmware: &FirmwareVersion, format: Option<ShiftFormat>) -> ReportFormat {
+    match format {
+        Some(ShiftFormat::Original) => FORMAT_ORIGINAL,
+        Some(ShiftFormat::New) => FORMAT_NEW,
+        Some(ShiftFormat::Auto) | Some(ShiftFormat::Unsupported) | None => determine_report_format(name, firmware),
+    }
+}
+
+/// The format the worker ends up using for a device, and why, for the device
+/// details window. `shift_report_size` is the shift report length from the
+/// device's descriptor, if it has been read. Logs nothing, unlike
+/// `determine_report_format`, since the UI asks every frame.
+pub(crate) fn explain_report_format(
+    name: &str,
+    firmware: &FirmwareVersion,
+    pinned: Option<ShiftFormat>,
+    shift_report_size: Option<usize>,
+) -> (ReportFormat, String) {
+    if let Some(size) = shift_report_size {
+        if let Some(format) = format_for_report_size(size) {
+            return (format, format!("the HID descriptor declares a {} byte shift report", size));
+        }
+    }
+    match pinned {
+        Some(ShiftFormat::Original) => return (FORMAT_ORIGINAL, "pinned by the device catalog".to_string()),
+        Some(ShiftFormat::New) => return (FORMAT_NEW, "pinned by the device catalog".to_string()),
+        _ => {}
+    }
+    let format = FORMAT_RULES
+        .iter()
+        .find(|rule| (rule.matches)(name, firmware))
+        .map_or(DEFAULT_REPORT_FORMAT, |rule| rule.format);
+    let reason = match firmware.date {
+        Some(date) if date < NEW_FORMAT_SINCE => format!("firmware built {}, before {}", date, NEW_FORMAT_SINCE),
+        Some(date) => format!("firmware built {}, not before {}", date, NEW_FORMAT_SINCE),
+        None => "no firmware build date; using the default".to_string(),
+    };
+    (format, reason)
+}
+
+/// The format whose report has `size` bytes (report ID included), as
+/// declared by a device's report descriptor.
+pub(crate) fn format_for_report_size(size: usize) -> Option<ReportFormat> {
+    [FORMAT_ORIGINAL, FORMAT_NEW].into_iter().find(|format| format.total_size == size)
+}
+
 pub(crate) const MAX_REPORT_SIZE: usize = FORMAT_NEW.total_size;
 
+// Format used when nothing more specific is known (newest firmware layout)
+pub(crate) const DEFAULT_REPORT_FORMAT: ReportFormat = FORMAT_NEW;
+
 /// Reads a specific bit from a u16 value.
 /// `position` is 0-indexed (0-15).
 pub(crate) fn read_bit(value: u16, position: u8) -> bool {
@@ -203,25 +250,103 @@ pub(crate) fn read_bit(value: u16, position: u8) -> bool {
     (value & (1 << position)) != 0
 }
 
+/// Returns the UI name of a shift bit: "1"-"5", "DTNT", "ZOOM" or "TRIM".
+pub(crate) fn bit_name(position: u8) -> String {
+    match position {
+        0..=4 => format!("{}", position + 1),
+        5 => "DTNT".to_string(),
+        6 => "ZOOM".to_string(),
+        7 => "TRIM".to_string(),
+        _ => format!("BIT{}", position),
+    }
+}
+
 
-/// Checks if a device firmware string is supported.
-/// TODO: Implement actual firmware checking logic if needed.
-pub(crate) fn is_supported(firmware_string: String) -> bool {
-    // Currently allows all devices.
-    let args = crate::Args::parse(); // Need to handle args properly
-    if args.skip_firmware { return true; }
-
-    // Example fixed list check:
-    // let supported_firmware = [
-    //     // "VIRPIL Controls 20220720",
-    //     // "VIRPIL Controls 20230328",
-    //     // "VIRPIL Controls 20240323",
-    //     "VIRPIL Controls 20241226",
-    // ];
-
-    if firmware_string.is_empty() || firmware_string == "Unknown Firmware" {
-        warn!("Device has missing or unknown firmware string.");
-        // Decide if these should be allowed or not. Allowing for now.
+/// A device's firmware string (the HID manufacturer string), parsed once
+/// when the device is found, e.g. "VIRPIL Controls 20241226".
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FirmwareVersion {
+    pub raw: String,             // As reported, trimmed
+    pub vendor: String,          // "VIRPIL Controls"; the whole string if there is no date
+    pub date: Option<NaiveDate>, // Build date; None if the string doesn't end in a YYYYMMDD date
+}
+
+impl FirmwareVersion {
+    pub fn parse(firmware: &str) -> Self {
+        let raw = firmware.trim();
+        let (vendor, last) = match raw.rsplit_once(char::is_whitespace) {
+            Some((vendor, last)) => (vendor.trim_end(), last),
+            None => ("", raw),
+        };
+        let date = if last.len() == 8 && last.bytes().all(|b| b.is_ascii_digit()) {
+            NaiveDate::parse_from_str(last, "%Y%m%d").ok()
+        } else {
+            None
+        };
+        Self {
+            raw: raw.to_string(),
+            vendor: if date.is_some() { vendor } else { raw }.to_string(),
+            date,
+        }
+    }
+
+    /// True if the device reported no firmware string at all.
+    pub fn is_unknown(&self) -> bool {
+        self.raw.is_empty() || self.raw == "Unknown Firmware"
+    }
+
+    /// What's wrong with a firmware string we don't recognise, or None if it
+    /// looks like a normal VIRPIL one.
+    pub fn diagnostic(&self) -> Option<String> {
+        if self.is_unknown() {
+            Some("no firmware string reported; the report format falls back to the newest layout".to_string())
+        } else if self.date.is_none() {
+            Some(format!(
+                "'{}' doesn't end in a YYYYMMDD build date; the report format and firmware policy can't use it",
+                self.raw
+            ))
+        } else if !self.vendor.to_ascii_lowercase().contains("virpil") {
+            Some(format!("'{}' isn't from VIRPIL firmware", self.raw))
+        } else {
+            None
+        }
+    }
+}
+
+// e.g. "VIRPIL Controls 2024-12-26"; unrecognised strings are shown as reported
+impl std::fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.date {
+            Some(date) if self.vendor.is_empty() => write!(f, "{}", date),
+            Some(date) => write!(f, "{} {}", self.vendor, date),
+            None => write!(f, "{}", self.raw),
+        }
+    }
+}
+
+/// Parses a date from the config, written "YYYY-MM-DD" or "YYYYMMDD".
+pub(crate) fn parse_date_setting(text: &str) -> Option<NaiveDate> {
+    let text = text.trim();
+    NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(text, "%Y%m%d"))
+        .ok()
+}
+
+/// Checks a device's firmware against the policy from the config.
+/// Returns why the device isn't supported; `skip_firmware` (the
+/// `--skip-firmware` option) accepts everything.
+pub(crate) fn is_supported(
+    firmware: &FirmwareVersion,
+    policy: &crate::config::FirmwarePolicy,
+    skip_firmware: bool,
+) -> Result<(), String> {
+    if skip_firmware {
+        return Ok(());
+    }
+    if firmware.is_unknown() {
+        // Nothing to check against (refresh_devices reports it); allow it
+        // rather than lock the device out
+        return Ok(());
     }
-    true
+    policy.check(firmware)
 }
@@ -0,0 +1,215 @@
+use log::{debug, error, info, warn};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+// How long a control connection waits for the UI thread to apply a command
+const CONTROL_REPLY_TIMEOUT_MS: u64 = 1000;
+
+/// A command for a virtual source slot, received on the control port.
+///
+/// Slots are 1-based as shown in the UI. Bits accept the UI names
+/// (`1`-`5`, `DTNT`, `ZOOM`, `TRIM`, case-insensitive).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VirtualCommand {
+    Set { slot: usize, state: u16 }, // set <slot> <state>
+    Press { slot: usize, bit: u8 },  // press <slot> <bit>
+    Release { slot: usize, bit: u8 }, // release <slot> <bit>
+    Toggle { slot: usize, bit: u8 }, // toggle <slot> <bit>
+}
+
+impl VirtualCommand {
+    /// Parses one line of the control protocol.
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let (verb, slot_text, arg) = match parts.as_slice() {
+            [verb, slot, arg] => (verb.to_ascii_lowercase(), *slot, *arg),
+            _ => return Err("Expected '<set|press|release|toggle> <slot> <value>'".to_string()),
+        };
+
+        let slot = match slot_text.parse::<usize>() {
+            Ok(slot) if slot >= 1 => slot - 1,
+            _ => return Err(format!("Invalid slot '{}'", slot_text)),
+        };
+
+        match verb.as_str() {
+            "set" => arg
+                .parse::<u16>()
+                .map(|state| VirtualCommand::Set { slot, state })
+                .map_err(|_| format!("Invalid state '{}'", arg)),
+            "press" => parse_bit(arg).map(|bit| VirtualCommand::Press { slot, bit }),
+            "release" => parse_bit(arg).map(|bit| VirtualCommand::Release { slot, bit }),
+            "toggle" => parse_bit(arg).map(|bit| VirtualCommand::Toggle { slot, bit }),
+            _ => Err(format!("Unknown command '{}'", verb)),
+        }
+    }
+
+    /// The 0-based slot this command targets.
+    pub fn slot(&self) -> usize {
+        match *self {
+            VirtualCommand::Set { slot, .. }
+            | VirtualCommand::Press { slot, .. }
+            | VirtualCommand::Release { slot, .. }
+            | VirtualCommand::Toggle { slot, .. } => slot,
+        }
+    }
+
+    /// Returns the new state after applying this command to `state`.
+    pub fn apply(&self, state: u16) -> u16 {
+        match *self {
+            VirtualCommand::Set { state: new_state, .. } => new_state,
+            VirtualCommand::Press { bit, .. } => state | (1 << bit),
+            VirtualCommand::Release { bit, .. } => state & !(1 << bit),
+            VirtualCommand::Toggle { bit, .. } => state ^ (1 << bit),
+        }
+    }
+}
+
+// Accepts a UI bit name ("1"-"5", "DTNT", "ZOOM", "TRIM")
+fn parse_bit(text: &str) -> Result<u8, String> {
+    (0..8u8)
+        .find(|bit| crate::util::bit_name(*bit).eq_ignore_ascii_case(text))
+        .ok_or_else(|| format!("Invalid bit '{}'", text))
+}
+
+// A parsed command plus the channel its connection waits on for the result
+struct ControlRequest {
+    command: VirtualCommand,
+    reply: Sender<Result<u16, String>>,
+}
+
+/// Line-based TCP control port on localhost for scripts and hotkey daemons.
+///
+/// Connections are handled on background threads; commands are queued and
+/// applied on the UI thread by `ShiftTool::poll_control_commands`.
+/// Example: `echo "toggle 2 DTNT" | nc -q1 127.0.0.1 <port>`.
+#[derive(Default)]
+pub struct ControlServer {
+    requests: Option<Receiver<ControlRequest>>,
+}
+
+impl ControlServer {
+    /// Binds `127.0.0.1:port` and starts accepting connections.
+    pub fn start(&mut self, port: u16) {
+        let listener = match TcpListener::bind((Ipv4Addr::LOCALHOST, port)) {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("Failed to start control port on 127.0.0.1:{}: {}", port, e);
+                return;
+            }
+        };
+        info!("Control port listening on 127.0.0.1:{}.", port);
+
+        let (sender, receiver) = mpsc::channel();
+        self.requests = Some(receiver);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let sender = sender.clone();
+                thread::spawn(move || handle_connection(stream, sender));
+            }
+        });
+    }
+}
+
+// Reads commands line by line and writes "OK <state>" or "ERR <reason>" back
+fn handle_connection(stream: TcpStream, requests: Sender<ControlRequest>) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(e) => {
+            warn!("Control port: failed to clone connection: {}", e);
+            return;
+        }
+    };
+
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break, // Connection closed
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        debug!("Control port command: {}", line);
+
+        let result = VirtualCommand::parse(&line).and_then(|command| {
+            let (reply, reply_receiver) = mpsc::channel();
+            requests
+                .send(ControlRequest { command, reply })
+                .map_err(|_| "Application is shutting down".to_string())?;
+            reply_receiver
+                .recv_timeout(Duration::from_millis(CONTROL_REPLY_TIMEOUT_MS))
+                .map_err(|_| "Timed out waiting for the application".to_string())?
+        });
+
+        let response = match result {
+            Ok(state) => format!("OK {}\n", state),
+            Err(e) => format!("ERR {}\n", e),
+        };
+        if writer.write_all(response.as_bytes()).is_err() {
+            break;
+        }
+    }
+}
+
+// Virtual source control
+impl crate::ShiftTool {
+    /// Sets the state of a virtual source slot (0-based).
+    ///
+    /// Returns an error if the slot doesn't exist or isn't a virtual source.
+    /// The worker picks the new state up on its next cycle.
+    pub fn set_virtual_source_state(&mut self, slot: usize, state: u16) -> Result<(), String> {
+        match self.config.data.sources.get(slot) {
+            Some(source) if source.is_virtual() => {}
+            Some(_) => return Err(format!("Source {} is not a virtual source", slot + 1)),
+            None => return Err(format!("Source {} does not exist", slot + 1)),
+        }
+        let shared_state = self
+            .source_states
+            .get(slot)
+            .ok_or_else(|| format!("Source {} has no state tracking", slot + 1))?;
+        match shared_state.lock() {
+            Ok(mut guard) => *guard = state,
+            Err(poisoned) => *poisoned.into_inner() = state,
+        }
+        debug!("Virtual source {} set to {}", slot + 1, state);
+        Ok(())
+    }
+
+    /// Returns the current state of a virtual source slot (0-based).
+    pub fn virtual_source_state(&self, slot: usize) -> Option<u16> {
+        let source = self.config.data.sources.get(slot)?;
+        if !source.is_virtual() {
+            return None;
+        }
+        let shared_state = self.source_states.get(slot)?;
+        let state = match shared_state.lock() {
+            Ok(guard) => *guard,
+            Err(poisoned) => **poisoned.get_ref(),
+        };
+        Some(state)
+    }
+
+    /// Applies commands queued by the control port. Called once per UI frame.
+    pub(crate) fn poll_control_commands(&mut self) {
+        let mut pending = Vec::new();
+        if let Some(requests) = &self.control_server.requests {
+            while let Ok(request) = requests.try_recv() {
+                pending.push(request);
+            }
+        }
+
+        for request in pending {
+            let slot = request.command.slot();
+            let result = match self.virtual_source_state(slot) {
+                Some(state) => {
+                    let new_state = request.command.apply(state);
+                    self.set_virtual_source_state(slot, new_state).map(|_| new_state)
+                }
+                None => Err(format!("Source {} is not a virtual source", slot + 1)),
+            };
+            let _ = request.reply.send(result); // Connection may already be gone
+        }
+    }
+}
@@ -1,6 +1,18 @@
-use vpc_shift_tool::config::{ConfigData, ShiftModifiers, ModifiersArray};
-use vpc_shift_tool::device::{SavedDevice, VpcDevice};
+use vpc_shift_tool::config::{BitEdge, ConfigData, HookConfig, HookEvent, JoystickSource, KeyBinding, ShiftModifiers, ModifiersArray};
+use vpc_shift_tool::config_file::{
+    apply_base, backup_files, read_config_file, recover_config_file, resolve_config_path, save_config_data, strip_base,
+    validate_config_text, CONFIG_FILE_NAME, MAX_CONFIG_BACKUPS,
+};
+use vpc_shift_tool::hid_worker::LiveSettings;
+use vpc_shift_tool::history::{EditHistory, RoutingSnapshot};
+use vpc_shift_tool::hooks::HookContext;
+use vpc_shift_tool::migration::{migrate_config_file, migrate_value, schema_version, CURRENT_SCHEMA_VERSION};
+use vpc_shift_tool::process_watcher::process_matches;
+use vpc_shift_tool::profile_io::{read_profile_file, resolve_device, DeviceMatch, ExportedDevice, PendingImport, ProfileFile};
+use vpc_shift_tool::virtual_source::VirtualCommand;
+use vpc_shift_tool::device::{SavedDevice, SlotMatcher, SourceKind, VpcDevice};
 use vpc_shift_tool::state::State;
+use vpc_shift_tool::util::FirmwareVersion;
 use std::rc::Rc;
 
 #[test]
@@ -18,6 +30,189 @@ fn test_config_data_default() {
     }
 }
 
+#[test]
+fn test_virtual_joystick_default() {
+    // The virtual joystick is opt-in and mirrors the combined result by default
+    let config = ConfigData::default();
+
+    assert!(!config.virtual_joystick.enabled);
+    assert_eq!(config.virtual_joystick.source, JoystickSource::Result);
+    assert_eq!(format!("{}", JoystickSource::Result), "Result");
+    assert_eq!(format!("{}", JoystickSource::Receiver(0)), "Receiver 1");
+}
+
+#[test]
+fn test_key_binding_default() {
+    // No key bindings are configured by default
+    let config = ConfigData::default();
+    assert!(config.key_bindings.is_empty());
+
+    let binding = KeyBinding::default();
+    assert_eq!(binding.bit, 0);
+    assert_eq!(binding.edge, BitEdge::Rising);
+    assert_eq!(binding.keys, "");
+
+    // Any of the 8 result bits can be bound; a config naming another is rejected
+    let config: ConfigData = serde_json::from_str(r#"{ "key_bindings": [ { "bit": 7, "keys": "F1" } ] }"#).unwrap();
+    assert_eq!(config.key_bindings[0].bit, 7);
+    let error = validate_config_text(r#"{ "key_bindings": [ { "bit": 8, "keys": "F1" } ] }"#).unwrap_err();
+    assert!(error.message.contains("out of range"), "{}", error.message);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_parse_key_sequence() {
+    use vpc_shift_tool::uinput::parse_key_sequence;
+
+    // Single chord with a modifier alias
+    let chords = parse_key_sequence("Ctrl+F3").unwrap();
+    assert_eq!(chords.len(), 1);
+    assert_eq!(chords[0].len(), 2);
+
+    // Macro of two chords, mixing plain and KEY_-prefixed names
+    let chords = parse_key_sequence("Esc KEY_LEFTCTRL+s").unwrap();
+    assert_eq!(chords.len(), 2);
+    assert_eq!(chords[1].len(), 2);
+
+    // Invalid input is rejected
+    assert!(parse_key_sequence("").is_err());
+    assert!(parse_key_sequence("Ctrl+").is_err());
+    assert!(parse_key_sequence("NotAKey").is_err());
+}
+
+#[test]
+fn test_hook_defaults() {
+    let config = ConfigData::default();
+    assert!(config.hooks.is_empty());
+
+    let hook = HookConfig::default();
+    assert_eq!(hook.event, HookEvent::ResultChanged);
+    assert_eq!(hook.command, "");
+    assert_eq!(hook.timeout_ms, 5000);
+}
+
+#[test]
+fn test_hook_context_env_vars() {
+    // Bits 0 and 5 (1 and DTNT) changed between the two states
+    let context = HookContext {
+        old_state: 0b0000_0001,
+        new_state: 0b0010_0000,
+        slot: Some(1),
+        serial_number: "123456".to_string(),
+        device_name: "Left grip".to_string(),
+    };
+    let env = context.env_vars(HookEvent::ResultChanged);
+    let get = |key: &str| env.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+
+    assert_eq!(get("SHIFT_TOOL_EVENT"), Some("result_changed"));
+    assert_eq!(get("SHIFT_TOOL_OLD_STATE"), Some("1"));
+    assert_eq!(get("SHIFT_TOOL_NEW_STATE"), Some("32"));
+    assert_eq!(get("SHIFT_TOOL_CHANGED_BITS"), Some("1,DTNT"));
+    assert_eq!(get("SHIFT_TOOL_SLOT"), Some("2"));
+    assert_eq!(get("SHIFT_TOOL_DEVICE_SERIAL"), Some("123456"));
+    assert_eq!(get("SHIFT_TOOL_DEVICE_NAME"), Some("Left grip"));
+}
+
+#[test]
+fn test_profile_store_and_load() {
+    let mut config = ConfigData::default();
+
+    // Save a profile with one source
+    config.sources.push(SavedDevice { vendor_id: 0x3344, product_id: 0x0001, ..Default::default() });
+    config.store_profile("DCS");
+    config.active_profile = "DCS".to_string();
+
+    // Save a second profile with different rules
+    config.sources.clear();
+    config.shift_modifiers[0] = ShiftModifiers::AND;
+    config.store_profile("MSFS");
+    assert_eq!(config.profiles.len(), 2);
+
+    // Loading MSFS leaves DCS as it was stored, even though the routing changed since
+    assert!(config.load_profile("MSFS"));
+    assert_eq!(config.active_profile, "MSFS");
+    assert_eq!(config.shift_modifiers[0], ShiftModifiers::AND);
+    assert_eq!(config.profiles[0].sources.len(), 1);
+
+    // Switching back restores it
+    assert!(config.load_profile("DCS"));
+    assert_eq!(config.sources.len(), 1);
+    assert_eq!(config.shift_modifiers[0], ShiftModifiers::OR);
+
+    // Unknown profiles leave the config untouched
+    assert!(!config.load_profile("Missing"));
+    assert_eq!(config.active_profile, "DCS");
+}
+
+#[test]
+fn test_process_matches() {
+    // Native process
+    assert!(process_matches("xplane", "xplane", &["/opt/X-Plane/xplane"]));
+    // Wine path in argv[0], case-insensitive
+    assert!(process_matches("DCS.exe", "wine64-preload", &["C:\\Games\\DCS World\\bin\\dcs.exe"]));
+    // Proton launch line
+    assert!(process_matches("DCS.exe", "python3", &["/usr/bin/python3", "proton", "run", "/home/u/games/DCS.exe"]));
+    // No match and empty rule
+    assert!(!process_matches("DCS.exe", "bash", &["/bin/bash"]));
+    assert!(!process_matches("", "bash", &["/bin/bash"]));
+}
+
+#[test]
+fn test_virtual_command_parse_and_apply() {
+    // Slots are 1-based on the wire, 0-based once parsed
+    assert_eq!(VirtualCommand::parse("set 1 5"), Ok(VirtualCommand::Set { slot: 0, state: 5 }));
+    assert_eq!(VirtualCommand::parse("TOGGLE 2 dtnt"), Ok(VirtualCommand::Toggle { slot: 1, bit: 5 }));
+    assert_eq!(VirtualCommand::parse("press 3 1"), Ok(VirtualCommand::Press { slot: 2, bit: 0 }));
+
+    // Malformed commands are rejected
+    assert!(VirtualCommand::parse("set 0 5").is_err());
+    assert!(VirtualCommand::parse("press 1 BIT9").is_err());
+    assert!(VirtualCommand::parse("jump 1 1").is_err());
+    assert!(VirtualCommand::parse("set 1").is_err());
+
+    // Applying commands
+    assert_eq!(VirtualCommand::Press { slot: 0, bit: 5 }.apply(0b0000_0001), 0b0010_0001);
+    assert_eq!(VirtualCommand::Release { slot: 0, bit: 0 }.apply(0b0000_0001), 0);
+    assert_eq!(VirtualCommand::Toggle { slot: 0, bit: 1 }.apply(0b0000_0010), 0);
+    assert_eq!(VirtualCommand::Set { slot: 0, state: 7 }.apply(0xFF), 7);
+}
+
+#[test]
+fn test_virtual_hotkeys() {
+    use vpc_shift_tool::hotkeys::HotkeyMatcher;
+
+    const CTRL: u16 = 29; // KEY_LEFTCTRL
+    const F1: u16 = 59; // KEY_F1
+    let toggle = VirtualCommand::Toggle { slot: 1, bit: 5 };
+    let mut matcher = HotkeyMatcher::new(vec![(vec![CTRL, F1], toggle)]);
+
+    // Fires when the last key of the chord goes down, once per press
+    assert!(matcher.key_event(F1, 1).is_empty());
+    assert!(matcher.key_event(F1, 0).is_empty());
+    assert!(matcher.key_event(CTRL, 1).is_empty());
+    assert_eq!(matcher.key_event(F1, 1), vec![toggle]);
+    assert!(matcher.key_event(F1, 2).is_empty()); // Key repeat
+    assert!(matcher.key_event(F1, 0).is_empty());
+    assert_eq!(matcher.key_event(F1, 1), vec![toggle]);
+    assert!(matcher.key_event(CTRL, 0).is_empty());
+    assert!(matcher.key_event(F1, 1).is_empty());
+
+    // Hotkeys come from the config with the key binding and control port syntax
+    let data: ConfigData =
+        serde_json::from_str(r#"{ "virtual_hotkeys": [ { "keys": "Ctrl+F1", "command": "toggle 2 DTNT" } ] }"#).unwrap();
+    assert_eq!(data.virtual_hotkeys[0].command, "toggle 2 DTNT");
+    #[cfg(target_os = "linux")]
+    {
+        use vpc_shift_tool::config::VirtualHotkey;
+        use vpc_shift_tool::hotkeys::parse_hotkey;
+
+        assert_eq!(parse_hotkey(&data.virtual_hotkeys[0]), Ok((vec![CTRL, F1], toggle)));
+        let hotkey = |keys: &str, command: &str| VirtualHotkey { keys: keys.to_string(), command: command.to_string() };
+        assert!(parse_hotkey(&hotkey("Ctrl+F1 F2", "toggle 2 DTNT")).is_err()); // Macro
+        assert!(parse_hotkey(&hotkey("Ctrl+F1", "jump 2 DTNT")).is_err());
+    }
+}
+
 #[test]
 fn test_shift_modifiers_display() {
     // Test the Display implementation for ShiftModifiers
@@ -35,6 +230,8 @@ fn test_saved_device_default() {
     assert_eq!(device.product_id, 0);
     assert_eq!(device.serial_number, "");
     assert_eq!(device.state_enabled, [true; 8]); // All bits enabled by default
+    assert_eq!(device.kind, SourceKind::Hid);
+    assert!(!device.is_virtual());
 }
 
 #[test]
@@ -66,6 +263,7 @@ fn test_config_with_devices() {
         product_id: 0x0001,
         serial_number: "123456".to_string(),
         state_enabled: [true, false, true, false, true, false, true, false],
+        ..Default::default()
     };
 
     let device2 = SavedDevice {
@@ -73,6 +271,7 @@ fn test_config_with_devices() {
         product_id: 0x0002,
         serial_number: "654321".to_string(),
         state_enabled: [false, true, false, true, false, true, false, true],
+        ..Default::default()
     };
 
     // Add devices to sources and receivers
@@ -128,7 +327,7 @@ fn test_vpc_device_default() {
 
     assert_eq!(device.full_name, "");
     assert_eq!(*device.name, "-NO CONNECTION (Select device from list)-");
-    assert_eq!(*device.firmware, "");
+    assert_eq!(device.firmware.raw, "");
     assert_eq!(device.vendor_id, 0);
     assert_eq!(device.product_id, 0);
     assert_eq!(device.serial_number, "");
@@ -148,46 +347,49 @@ fn test_vpc_device_display() {
     let device = VpcDevice {
         full_name: "3344:0001:123456".to_string(),
         name: Rc::new("VPC MongoosT-50CM3".to_string()),
-        firmware: Rc::new("VIRPIL Controls 20240101".to_string()),
+        firmware: FirmwareVersion::parse("VIRPIL Controls 20240101"),
         vendor_id: 0x3344,
         product_id: 0x0001,
         serial_number: "123456".to_string(),
         usage: 0,
         active: false,
+        ..Default::default()
     };
 
     assert_eq!(
         format!("{}", device),
-        "VID:3344 PID:0001 VPC MongoosT-50CM3 (SN:123456 FW:VIRPIL Controls 20240101)"
+        "VID:3344 PID:0001 VPC MongoosT-50CM3 (SN:123456 FW:VIRPIL Controls 2024-01-01)"
     );
 
     // Test a device with empty serial number
     let device = VpcDevice {
         full_name: "3344:0001:no_sn".to_string(),
         name: Rc::new("VPC MongoosT-50CM3".to_string()),
-        firmware: Rc::new("VIRPIL Controls 20240101".to_string()),
+        firmware: FirmwareVersion::parse("VIRPIL Controls 20240101"),
         vendor_id: 0x3344,
         product_id: 0x0001,
         serial_number: "".to_string(),
         usage: 0,
         active: false,
+        ..Default::default()
     };
 
     assert_eq!(
         format!("{}", device),
-        "VID:3344 PID:0001 VPC MongoosT-50CM3 (SN:N/A FW:VIRPIL Controls 20240101)"
+        "VID:3344 PID:0001 VPC MongoosT-50CM3 (SN:N/A FW:VIRPIL Controls 2024-01-01)"
     );
 
     // Test a device with empty firmware
     let device = VpcDevice {
         full_name: "3344:0001:123456".to_string(),
         name: Rc::new("VPC MongoosT-50CM3".to_string()),
-        firmware: Rc::new("".to_string()),
+        firmware: FirmwareVersion::default(),
         vendor_id: 0x3344,
         product_id: 0x0001,
         serial_number: "123456".to_string(),
         usage: 0,
         active: false,
+        ..Default::default()
     };
 
     assert_eq!(
@@ -195,3 +397,992 @@ fn test_vpc_device_display() {
         "VID:3344 PID:0001 VPC MongoosT-50CM3 (SN:123456 FW:N/A)"
     );
 }
+
+#[test]
+fn test_default_config_uses_current_schema() {
+    let config = ConfigData::default();
+    assert_eq!(config.schema_version, CURRENT_SCHEMA_VERSION);
+}
+
+#[test]
+fn test_migrate_v0_4_0_fixture() {
+    // Configs written by 0.4.0 have no schema_version and must keep all their routing
+    let mut value: serde_json::Value =
+        json5::from_str(include_str!("fixtures/config_v0.4.0.json")).unwrap();
+    assert_eq!(schema_version(&value), 0);
+
+    let from_version = migrate_value(&mut value).unwrap();
+    assert_eq!(from_version, 0);
+    assert_eq!(schema_version(&value), CURRENT_SCHEMA_VERSION);
+
+    let config: ConfigData = serde_json::from_value(value).unwrap();
+    assert_eq!(config.schema_version, CURRENT_SCHEMA_VERSION);
+    assert_eq!(config.sources.len(), 2);
+    assert_eq!(config.sources[0].serial_number, "FF0A7B1C");
+    assert_eq!(config.sources[0].kind, SourceKind::Hid);
+    assert!(config.sources[1].state_enabled[7]);
+    assert_eq!(config.receivers.len(), 1);
+    assert_eq!(config.receivers[0].product_id, 17215);
+    assert_eq!(config.shift_modifiers[1], ShiftModifiers::AND);
+    assert_eq!(config.shift_modifiers[2], ShiftModifiers::XOR);
+    assert!(!config.virtual_joystick.enabled);
+}
+
+#[test]
+fn test_migrate_current_schema_fixture_is_unchanged() {
+    let original: serde_json::Value =
+        json5::from_str(include_str!("fixtures/config_schema_3.json")).unwrap();
+    let mut value = original.clone();
+
+    assert_eq!(migrate_value(&mut value).unwrap(), CURRENT_SCHEMA_VERSION);
+    assert_eq!(value, original);
+
+    let config: ConfigData = serde_json::from_value(value).unwrap();
+    assert_eq!(config.sources[1].kind, SourceKind::Virtual);
+    assert_eq!(config.virtual_joystick.source, JoystickSource::Receiver(0));
+    assert_eq!(config.key_bindings[0].keys, "Ctrl+F3");
+    assert_eq!(config.hooks[0].timeout_ms, 2000);
+    assert_eq!(config.control_port, 7654);
+}
+
+#[test]
+fn test_migrate_schema_1_drops_default_sections() {
+    // Schema 1 files carry the sections 0 -> 1 wrote out; those still at
+    // their defaults are dropped so a base config can fill them in
+    let mut value: serde_json::Value =
+        json5::from_str(include_str!("fixtures/config_schema_1.json")).unwrap();
+    assert_eq!(migrate_value(&mut value).unwrap(), 1);
+    assert_eq!(schema_version(&value), CURRENT_SCHEMA_VERSION);
+    for key in ["profiles", "active_profile", "default_profile", "game_rules"] {
+        assert!(value.get(key).is_none(), "{} should be dropped", key);
+    }
+
+    // Anything the user set is kept
+    let config: ConfigData = serde_json::from_value(value).unwrap();
+    assert_eq!(config.sources.len(), 2);
+    assert!(config.virtual_joystick.enabled);
+    assert_eq!(config.key_bindings[0].keys, "Ctrl+F3");
+    assert_eq!(config.control_port, 7654);
+}
+
+#[test]
+fn test_migrate_schema_2_interface_overrides() {
+    // Bare interface numbers become overrides for any collection of that interface
+    let mut value: serde_json::Value =
+        json5::from_str(include_str!("fixtures/config_schema_2.json")).unwrap();
+    assert_eq!(migrate_value(&mut value).unwrap(), 2);
+
+    let config: ConfigData = serde_json::from_value(value).unwrap();
+    let chosen = config.interface_overrides["3344:8126:FF0A7B1C"];
+    assert_eq!(chosen.interface_number, 2);
+    assert_eq!(chosen.usage_page, None);
+    assert_eq!(chosen.usage, None);
+}
+
+#[test]
+fn test_migrate_config_file_writes_backup() {
+    let dir = std::env::temp_dir().join(format!("shift_tool_migration_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("shift_tool.json");
+    let original = include_str!("fixtures/config_v0.4.0.json");
+    std::fs::write(&path, original).unwrap();
+
+    // The original file is kept byte for byte next to the migrated one
+    let backup = migrate_config_file(&path).unwrap().expect("a backup should be written");
+    assert_eq!(backup, dir.join("shift_tool.json.v0.bak"));
+    assert_eq!(std::fs::read_to_string(&backup).unwrap(), original);
+
+    let migrated: serde_json::Value =
+        json5::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(schema_version(&migrated), CURRENT_SCHEMA_VERSION);
+
+    // Already current: nothing to do
+    assert_eq!(migrate_config_file(&path).unwrap(), None);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_validate_config_text_reports_position() {
+    assert!(validate_config_text(include_str!("fixtures/config_schema_1.json")).is_ok());
+
+    // A missing closing bracket is reported where parsing stopped
+    let error = validate_config_text("{\n  sources: [\n  control_port: 5\n}").unwrap_err();
+    assert_eq!(error.line, Some(3));
+    assert_eq!(error.column, Some(3));
+    assert!(error.to_string().starts_with("line 3, column 3: expected"));
+
+    // Values of the wrong type are rejected too
+    let error = validate_config_text("{ control_port: \"abc\" }").unwrap_err();
+    assert_eq!(error.line, Some(1));
+}
+
+#[test]
+fn test_recover_broken_config_file() {
+    let dir = std::env::temp_dir().join(format!("shift_tool_recovery_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("shift_tool.json");
+
+    // Valid files are left alone
+    std::fs::write(&path, include_str!("fixtures/config_schema_1.json")).unwrap();
+    assert!(recover_config_file(&path).is_none());
+
+    // Without a backup the broken file is moved aside and defaults are used
+    std::fs::write(&path, "{ sources: [ }").unwrap();
+    let recovery = recover_config_file(&path).expect("broken file should be recovered");
+    assert!(!path.exists());
+    assert!(recovery.restored_from.is_none());
+    let moved_to = recovery.moved_to.expect("broken file should be moved aside");
+    assert_eq!(std::fs::read_to_string(&moved_to).unwrap(), "{ sources: [ }");
+    std::fs::remove_file(&moved_to).unwrap();
+
+    // With a good backup it is restored instead
+    let backup = dir.join("shift_tool.json.v0.bak");
+    std::fs::write(&backup, include_str!("fixtures/config_v0.4.0.json")).unwrap();
+    std::fs::write(&path, "{ sources: [ }").unwrap();
+    let recovery = recover_config_file(&path).unwrap();
+    assert_eq!(recovery.restored_from, Some(backup));
+    assert!(validate_config_text(&std::fs::read_to_string(&path).unwrap()).is_ok());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_broken_config_left_in_place_starts_with_defaults() {
+    let dir = std::env::temp_dir().join(format!("shift_tool_stuck_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("shift_tool.json");
+    std::fs::write(&path, "{ control_port: 9000, sources: [ }").unwrap();
+
+    // Non-empty directories where the broken file would be moved make the rename fail
+    let now = chrono::Local::now();
+    for seconds in 0..5 {
+        let stamp = (now + chrono::Duration::seconds(seconds)).format("%Y%m%d-%H%M%S");
+        std::fs::create_dir_all(dir.join(format!("shift_tool.json.broken-{}", stamp)).join("taken")).unwrap();
+    }
+
+    let loaded = vpc_shift_tool::config_file::load_config(&path).expect("startup must not fail");
+    let recovery = loaded.recovery.expect("the broken file should be reported");
+    assert!(recovery.left_in_place());
+    assert!(recovery.to_string().contains("left untouched"), "{}", recovery);
+    assert_eq!(loaded.config.data, ConfigData::default());
+    assert_eq!(loaded.config.path, path);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "{ control_port: 9000, sources: [ }");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_save_config_keeps_rolling_backups() {
+    let dir = std::env::temp_dir().join(format!("shift_tool_backups_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("shift_tool.json");
+    let migration_backup = dir.join("shift_tool.json.v0.bak");
+    std::fs::write(&migration_backup, include_str!("fixtures/config_v0.4.0.json")).unwrap();
+
+    let mut config = ConfigData::default();
+    for port in 1..=(MAX_CONFIG_BACKUPS as u16 + 3) {
+        config.control_port = port;
+        save_config_data(&path, &config, None).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(5)); // Distinct backup timestamps
+    }
+
+    // Saving unchanged data doesn't rotate
+    save_config_data(&path, &config, None).unwrap();
+
+    let saved: ConfigData = json5::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(saved.control_port, MAX_CONFIG_BACKUPS as u16 + 3);
+    assert!(!dir.join(".shift_tool.json.tmp").exists());
+
+    // Rolling backups are capped, the migration backup is never pruned
+    let backups = backup_files(&path);
+    assert_eq!(backups.len(), MAX_CONFIG_BACKUPS + 1);
+    assert!(backups.contains(&migration_backup));
+
+    // The newest backup holds the previous save
+    let newest = backups.iter().find(|b| **b != migration_backup).unwrap();
+    assert_eq!(read_config_file(newest, None).unwrap().control_port, MAX_CONFIG_BACKUPS as u16 + 2);
+
+    // Old-schema backups are migrated when read
+    let restored = read_config_file(&migration_backup, None).unwrap();
+    assert_eq!(restored.schema_version, CURRENT_SCHEMA_VERSION);
+    assert_eq!(restored.sources.len(), 2);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_config_layers_over_system_base() {
+    // The lab base sets up routing and a control port; the user only changes the port
+    let base: serde_json::Value =
+        json5::from_str(include_str!("fixtures/config_schema_3.json")).unwrap();
+    let user = serde_json::json!({ "schema_version": CURRENT_SCHEMA_VERSION, "control_port": 9000 });
+
+    let config = apply_base(&base, user).unwrap();
+    assert_eq!(config.control_port, 9000);
+    assert_eq!(config.sources.len(), 2);
+    assert_eq!(config.key_bindings[0].keys, "Ctrl+F3");
+
+    // Saving relative to the base keeps only the user's own changes
+    let saved = strip_base(serde_json::to_value(&config).unwrap(), &base);
+    assert_eq!(
+        saved,
+        serde_json::json!({ "schema_version": CURRENT_SCHEMA_VERSION, "control_port": 9000 })
+    );
+}
+
+#[test]
+fn test_resolve_config_path_precedence() {
+    let cli_path = std::path::Path::new("/tmp/cli_shift_tool.json");
+    let env_path = || Some(std::ffi::OsString::from("/tmp/env_shift_tool.json"));
+
+    assert_eq!(resolve_config_path(Some(cli_path), env_path()), cli_path);
+    assert_eq!(resolve_config_path(None, env_path()), std::path::Path::new("/tmp/env_shift_tool.json"));
+
+    // An empty variable counts as unset
+    assert!(resolve_config_path(None, Some(Default::default())).ends_with(CONFIG_FILE_NAME));
+    assert!(resolve_config_path(None, None).ends_with(CONFIG_FILE_NAME));
+}
+
+#[test]
+fn test_worker_settings_changed() {
+    let running = ConfigData::default();
+
+    // Profiles, game rules and the control port don't affect a running worker
+    let mut edited = ConfigData {
+        control_port: 7654,
+        default_profile: "Flight".to_string(),
+        ..Default::default()
+    };
+    assert!(!running.worker_settings_changed(&edited));
+
+    // Rules are pushed to the worker live
+    edited.shift_modifiers[3] = ShiftModifiers::XOR;
+    assert!(!running.worker_settings_changed(&edited));
+
+    // Output changes need a restart
+    let mut edited = ConfigData::default();
+    edited.key_bindings.push(KeyBinding::default());
+    assert!(running.worker_settings_changed(&edited));
+}
+
+#[test]
+fn test_live_settings_track_masks_but_not_devices() {
+    let mut config = ConfigData::default();
+    config.sources.push(SavedDevice::default());
+    config.receivers.push(SavedDevice::default());
+    let running = LiveSettings::from_config(&config);
+
+    // Toggling a mask changes the live settings without requiring a restart
+    let mut edited = ConfigData::default();
+    edited.sources.push(SavedDevice::default());
+    edited.receivers.push(SavedDevice::default());
+    edited.receivers[0].state_enabled[5] = false;
+    assert_ne!(LiveSettings::from_config(&edited), running);
+    assert!(!LiveSettings::from_config(&edited).receiver_masks[0][5]);
+    assert!(!config.worker_settings_changed(&edited));
+
+    // So does renaming a device, for the names in logs and hooks
+    let mut renamed = config.clone();
+    renamed.device_nicknames.insert(renamed.sources[0].device_key(), "Left throttle".to_string());
+    assert_eq!(LiveSettings::from_config(&renamed).nicknames.len(), 1);
+    assert_ne!(LiveSettings::from_config(&renamed), running);
+    assert!(!config.worker_settings_changed(&renamed));
+
+    // Picking another device is not a live change
+    edited.receivers[0].serial_number = "12AB34CD".to_string();
+    assert!(config.worker_settings_changed(&edited));
+}
+
+#[test]
+fn test_device_nicknames() {
+    let mut config = ConfigData::default();
+    let grip = SavedDevice { vendor_id: 0x3344, product_id: 0x43F4, serial_number: "FF0A7B1C".to_string(), ..Default::default() };
+    assert_eq!(grip.device_key(), "3344:43F4:FF0A7B1C");
+    assert_eq!(config.nickname_for(&grip), None);
+
+    config.device_nicknames.insert(grip.device_key(), "Left grip".to_string());
+    assert_eq!(config.nickname_for(&grip), Some("Left grip"));
+    assert_eq!(config.nickname_suffix(&grip), ", 'Left grip'");
+
+    // A nicknamed device shows the nickname first in the device list
+    let mut device = connected_device(0x43F4, "FF0A7B1C", "R-VPC Stick MT-50CM2");
+    device.nickname = "Left grip".to_string();
+    assert_eq!(format!("{}", device), "Left grip (R-VPC Stick MT-50CM2, SN:FF0A7B1C)");
+
+    // Notes and nicknames never make the worker restart
+    let mut edited = config.clone();
+    edited.sources.push(grip.clone());
+    config.sources.push(grip);
+    edited.sources[0].note = "Throttle side".to_string();
+    edited.device_nicknames.insert("3344:43F4:FF0A7B1C".to_string(), "Right grip".to_string());
+    assert!(!config.worker_settings_changed(&edited));
+}
+
+#[test]
+fn test_receiver_slot_edits_keep_joystick_mirror() {
+    let mut config = ConfigData::default();
+    for serial in ["A", "B", "C"] {
+        config.receivers.push(SavedDevice { serial_number: serial.to_string(), ..Default::default() });
+    }
+    config.virtual_joystick.source = JoystickSource::Receiver(2);
+
+    // Moving the mirrored receiver up keeps the joystick on it
+    config.swap_receivers(1, 2);
+    assert_eq!(config.receivers[1].serial_number, "C");
+    assert_eq!(config.virtual_joystick.source, JoystickSource::Receiver(1));
+
+    // Removing an earlier slot shifts the index down
+    config.remove_receiver(0);
+    assert_eq!(config.receivers.len(), 2);
+    assert_eq!(config.virtual_joystick.source, JoystickSource::Receiver(0));
+
+    // Removing the mirrored slot falls back to the result
+    config.remove_receiver(0);
+    assert_eq!(config.receivers[0].serial_number, "B");
+    assert_eq!(config.virtual_joystick.source, JoystickSource::Result);
+}
+
+#[test]
+fn test_slots_from_older_files_are_enabled() {
+    let config: ConfigData = json5::from_str(include_str!("fixtures/config_schema_1.json")).unwrap();
+    assert!(config.sources.iter().chain(&config.receivers).all(|slot| slot.enabled));
+
+    // A disabled slot is a device change for the worker, not a live one
+    let mut disabled = config.clone();
+    disabled.sources[0].enabled = false;
+    assert!(config.worker_settings_changed(&disabled));
+}
+
+#[test]
+fn test_edit_history_undo_redo() {
+    let mut config = ConfigData::default();
+    config.sources.push(SavedDevice::default());
+    let mut history = EditHistory::default();
+    history.record(&config); // First frame only sets the baseline
+    assert!(history.undo_target().is_none());
+
+    // One frame with a mask toggle, one with a modifier change
+    config.sources[0].state_enabled[2] = false;
+    history.record(&config);
+    config.shift_modifiers[0] = ShiftModifiers::XOR;
+    history.record(&config);
+    history.record(&config); // Unchanged frames add nothing
+
+    assert!(history.undo(&mut config));
+    assert_eq!(config.shift_modifiers[0], ShiftModifiers::OR);
+    assert!(!config.sources[0].state_enabled[2]);
+    assert!(history.undo(&mut config));
+    assert!(config.sources[0].state_enabled[2]);
+    assert!(!history.undo(&mut config));
+
+    assert!(history.redo(&mut config));
+    assert!(!config.sources[0].state_enabled[2]);
+
+    // A new edit drops what could still be redone
+    config.receivers.push(SavedDevice::default());
+    history.record(&config);
+    assert!(history.redo_target().is_none());
+    assert!(history.undo(&mut config));
+    assert!(config.receivers.is_empty());
+}
+
+#[test]
+fn test_routing_snapshot_same_devices_ignores_masks() {
+    let mut config = ConfigData::default();
+    config.sources.push(SavedDevice::default());
+    let before = RoutingSnapshot::from_config(&config);
+
+    config.sources[0].state_enabled[7] = false;
+    config.shift_modifiers[3] = ShiftModifiers::AND;
+    assert!(before.same_devices(&RoutingSnapshot::from_config(&config)));
+
+    config.sources.push(SavedDevice::default());
+    assert!(!before.same_devices(&RoutingSnapshot::from_config(&config)));
+}
+
+// A connected device as the device list reports it
+fn connected_device(product_id: u16, serial_number: &str, name: &str) -> VpcDevice {
+    VpcDevice {
+        full_name: format!("3344:{:04X}:{}", product_id, serial_number),
+        name: Rc::new(name.to_string()),
+        firmware: FirmwareVersion::parse("VIRPIL Controls 20240101"),
+        vendor_id: 0x3344,
+        product_id,
+        serial_number: serial_number.to_string(),
+        usage: 0,
+        active: false,
+        ..Default::default()
+    }
+}
+
+fn exported_device(product_id: u16, serial_number: &str, name: &str) -> ExportedDevice {
+    ExportedDevice {
+        device: SavedDevice {
+            vendor_id: 0x3344,
+            product_id,
+            serial_number: serial_number.to_string(),
+            state_enabled: [true, false, true, false, true, false, true, false],
+            ..Default::default()
+        },
+        product_name: name.to_string(),
+    }
+}
+
+#[test]
+fn test_resolve_imported_devices() {
+    let device_list = vec![
+        VpcDevice::default(), // "No connection" entry
+        connected_device(0x8126, "AAAA", "VPC Throttle CM3"),
+        connected_device(0x4130, "BBBB", "VPC Stick MT-50CM3"),
+    ];
+
+    let same_serial = exported_device(0x8126, "AAAA", "VPC Throttle CM3");
+    assert_eq!(resolve_device(&same_serial, &device_list), (1, DeviceMatch::Exact));
+
+    let other_serial = exported_device(0x8126, "ZZZZ", "VPC Throttle CM3");
+    assert_eq!(resolve_device(&other_serial, &device_list), (1, DeviceMatch::SameModel));
+
+    // A different PID (e.g. firmware with another interface layout) still matches by name
+    let other_pid = exported_device(0x4131, "ZZZZ", "vpc stick mt-50cm3");
+    assert_eq!(resolve_device(&other_pid, &device_list), (2, DeviceMatch::SameName));
+
+    let missing = exported_device(0x0200, "ZZZZ", "VPC Rudder Pedals");
+    assert_eq!(resolve_device(&missing, &device_list), (0, DeviceMatch::NotFound));
+
+    let mut virtual_slot = exported_device(0, "", "");
+    virtual_slot.device.kind = SourceKind::Virtual;
+    assert_eq!(resolve_device(&virtual_slot, &device_list), (0, DeviceMatch::Virtual));
+}
+
+#[test]
+fn test_profile_file_import_rebinds_slots() {
+    let dir = std::env::temp_dir().join(format!("shift_tool_profile_io_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("flight.json");
+
+    let mut profile = ProfileFile {
+        format_version: 1,
+        name: "Flight".to_string(),
+        sources: vec![exported_device(0x8126, "AAAA", "VPC Throttle CM3")],
+        receivers: vec![exported_device(0x0200, "CCCC", "VPC Rudder Pedals")],
+        shift_modifiers: ModifiersArray::default(),
+    };
+    profile.shift_modifiers[6] = ShiftModifiers::XOR;
+    vpc_shift_tool::profile_io::export_profile(&path, &profile).unwrap();
+    let read_back = read_profile_file(&path).unwrap();
+    assert_eq!(read_back, profile);
+
+    // On the other PC the throttle has another serial and the pedals are missing
+    let device_list = vec![VpcDevice::default(), connected_device(0x8126, "DDDD", "VPC Throttle CM3")];
+    let pending = PendingImport::new(read_back, &device_list);
+    assert_eq!(pending.source_choices, vec![1]);
+    assert_eq!(pending.receiver_matches, vec![DeviceMatch::NotFound]);
+
+    let imported = pending.finish(&device_list);
+    assert_eq!(imported.name, "Flight");
+    assert_eq!(imported.sources[0].serial_number, "DDDD");
+    assert_eq!(imported.sources[0].state_enabled, profile.sources[0].device.state_enabled);
+    assert_eq!(imported.receivers[0].serial_number, "CCCC"); // Unbound slots keep their IDs
+    assert_eq!(imported.shift_modifiers[6], ShiftModifiers::XOR);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_import_binds_each_device_once() {
+    // Two throttles whose serials both changed, plus one whose serial didn't
+    let profile = ProfileFile {
+        format_version: 1,
+        name: "Twin".to_string(),
+        sources: vec![
+            exported_device(0x8126, "AAAA", "VPC Throttle CM3"),
+            exported_device(0x8126, "BBBB", "VPC Throttle CM3"),
+        ],
+        receivers: vec![exported_device(0x8126, "FFFF", "VPC Throttle CM3")],
+        shift_modifiers: ModifiersArray::default(),
+    };
+    let device_list = vec![
+        VpcDevice::default(),
+        connected_device(0x8126, "FFFF", "VPC Throttle CM3"),
+        connected_device(0x8126, "DDDD", "VPC Throttle CM3"),
+        connected_device(0x8126, "EEEE", "VPC Throttle CM3"),
+    ];
+    let pending = PendingImport::new(profile, &device_list);
+    // The exact match keeps its device; the others get one each
+    assert_eq!(pending.receiver_choices, vec![1]);
+    assert_eq!(pending.receiver_matches, vec![DeviceMatch::Exact]);
+    assert_eq!(pending.source_choices, vec![2, 3]);
+    assert_eq!(pending.source_matches, vec![DeviceMatch::SameModel, DeviceMatch::SameModel]);
+}
+
+#[test]
+fn test_device_catalog_lookup() {
+    use vpc_shift_tool::catalog::{lookup, CatalogEntry, DeviceFamily, DeviceRole, ShiftFormat};
+
+    // Built-in entries match the product string, ignoring case
+    let base = lookup(&[], 0x4130, "r-vpc stick mt-50cm2").unwrap();
+    assert_eq!(base.family, DeviceFamily::Base);
+    assert_eq!(base.role, DeviceRole::Receiver);
+    assert_eq!(lookup(&[], 0x4130, "Some other vendor").unwrap().model, "VPC MongoosT-50CM2 Base");
+
+    // A changed PID falls back to whole words of the name
+    assert_eq!(lookup(&[], 0x1234, "L-VPC Throttle CM3").unwrap().model, "VPC Throttle CM3");
+    assert!(lookup(&[], 0x1234, "L-VPC Throttle CM3X").is_none());
+    assert!(lookup(&[], 0x1234, "VPC Interface Board").is_none());
+    assert!(lookup(&[], 0x1234, "VPC Custom Panel").is_none());
+    assert!(lookup(&[], 0x1234, "Some other vendor").is_none());
+
+    // Models without a shift report can't be receivers
+    assert!(!lookup(&[], 0x8258, "").unwrap().can_receive());
+    assert!(!lookup(&[], 0x1234, "VPC ACE Flight Rudder Pedals").unwrap().can_receive());
+    assert!(lookup(&[], 0x8194, "").unwrap().can_receive());
+
+    // A config entry pinning the PID beats a built-in name match
+    let extra = vec![CatalogEntry {
+        product_id: Some(0x4130),
+        name_pattern: String::new(),
+        family: DeviceFamily::Panel,
+        model: "Button box".to_string(),
+        format: ShiftFormat::Unsupported,
+        role: DeviceRole::Source,
+    }];
+    let entry = lookup(&extra, 0x4130, "R-VPC Stick MT-50CM2").unwrap();
+    assert_eq!(entry.model, "Button box");
+    assert!(!entry.can_receive());
+    assert_eq!(lookup(&extra, 0x4131, "R-VPC Stick MT-50CM2").unwrap().family, DeviceFamily::Base);
+
+    // Entries from the config only need the fields they change
+    let data: ConfigData = serde_json::from_str(r#"{ "device_catalog": [ { "product_id": 513, "model": "Pedals" } ] }"#).unwrap();
+    assert_eq!(data.device_catalog[0].format, ShiftFormat::Auto);
+}
+
+#[test]
+fn test_firmware_policy() {
+    use vpc_shift_tool::config::FirmwarePolicy;
+
+    let version = FirmwareVersion::parse(" VIRPIL Controls 20241226 ");
+    assert_eq!(version.vendor, "VIRPIL Controls");
+    assert_eq!(version.date, chrono::NaiveDate::from_ymd_opt(2024, 12, 26));
+    assert_eq!(FirmwareVersion::parse("VIRPIL Controls").date, None);
+
+    // Default: no minimum, so every firmware passes
+    let mut policy = FirmwarePolicy::default();
+    assert!(policy.check(&FirmwareVersion::parse("VIRPIL Controls 20000101")).is_ok());
+
+    // With a minimum, anything built on or after it passes
+    policy.minimum_date = "2022-07-20".to_string();
+    assert!(policy.check(&FirmwareVersion::parse("VIRPIL Controls 20220720")).is_ok());
+    assert!(policy.check(&FirmwareVersion::parse("VIRPIL Controls 20210101")).is_err());
+    assert!(policy.check(&FirmwareVersion::parse("VIRPIL Controls")).is_ok()); // No date to compare
+
+    // The lists win over the minimum, and the bad list over the good one
+    policy.known_good.push("virpil controls 20210101".to_string());
+    assert!(policy.check(&FirmwareVersion::parse("VIRPIL Controls 20210101")).is_ok());
+    policy.known_bad.push("VIRPIL Controls 20241226".to_string());
+    policy.known_good.push("VIRPIL Controls 20241226".to_string());
+    let reason = policy.check(&FirmwareVersion::parse("VIRPIL Controls 20241226")).unwrap_err();
+    assert!(reason.contains("known-bad"));
+
+    // Minimums are accepted in either date style, "" disables them
+    policy.minimum_date = "20250101".to_string();
+    assert!(policy.check(&FirmwareVersion::parse("VIRPIL Controls 20241227")).is_err());
+    policy.minimum_date.clear();
+    assert!(policy.check(&FirmwareVersion::parse("VIRPIL Controls 20000101")).is_ok());
+
+    // A config with an unreadable minimum is rejected when it loads
+    assert!(validate_config_text(r#"{ firmware_policy: { minimum_date: "20250101" } }"#).is_ok());
+    let error = validate_config_text(r#"{ firmware_policy: { minimum_date: "2025-13-01" } }"#).unwrap_err();
+    assert!(error.message.contains("minimum_date"), "{}", error.message);
+}
+
+#[test]
+fn test_firmware_version_parsing() {
+    let version = FirmwareVersion::parse("VIRPIL Controls 20241226");
+    assert_eq!(version.raw, "VIRPIL Controls 20241226");
+    assert_eq!(version.to_string(), "VIRPIL Controls 2024-12-26");
+    assert_eq!(version.diagnostic(), None);
+
+    // A bare date still parses
+    let version = FirmwareVersion::parse("20230328");
+    assert_eq!(version.vendor, "");
+    assert_eq!(version.to_string(), "2023-03-28");
+
+    // Strings we don't recognise are kept as reported, with a reason
+    let version = FirmwareVersion::parse("VIRPIL Controls 2024.12");
+    assert_eq!(version.vendor, "VIRPIL Controls 2024.12");
+    assert_eq!(version.date, None);
+    assert_eq!(version.to_string(), "VIRPIL Controls 2024.12");
+    assert!(version.diagnostic().unwrap().contains("build date"));
+    assert!(FirmwareVersion::parse("Acme 20240101").diagnostic().unwrap().contains("VIRPIL"));
+    assert!(FirmwareVersion::parse("Unknown Firmware").is_unknown());
+    assert!(FirmwareVersion::default().diagnostic().is_some());
+    // Impossible dates aren't dates
+    assert_eq!(FirmwareVersion::parse("VIRPIL Controls 20241332").date, None);
+}
+
+#[test]
+fn test_pick_interface() {
+    use vpc_shift_tool::device::{pick_interface, HidInterface, InterfaceOverride};
+
+    let interface = |number: i32, usage_page: u16, usage: u16| HidInterface {
+        path: std::ffi::CString::new(format!("/dev/hidraw{}", number)).unwrap(),
+        usage_page,
+        usage,
+        interface_number: number,
+        shift_report: None,
+    };
+    // Keyboard emulation listed first, joystick collection second; no descriptors read
+    let mut interfaces = vec![interface(0, 0x01, 0x06), interface(2, 0xFF00, 0x01), interface(1, 0x01, 0x04)];
+    assert_eq!(pick_interface(&interfaces, None), 2);
+    assert_eq!(interfaces[2].to_string(), "#1 joystick (usage 0001:0004)");
+
+    // Without a game controller collection the vendor page wins
+    assert_eq!(pick_interface(&interfaces[..2], None), 1);
+
+    // The descriptor beats the usage: here only the vendor interface declares report 4
+    interfaces[1].shift_report = Some(true);
+    interfaces[2].shift_report = Some(false);
+    assert_eq!(pick_interface(&interfaces, None), 1);
+    assert_eq!(interfaces[1].to_string(), "#2 vendor-defined (usage FF00:0001), shift report");
+
+    // A manual override wins if the device has that interface
+    let keyboard = InterfaceOverride::of(&interfaces[0]);
+    assert_eq!(pick_interface(&interfaces, Some(&keyboard)), 0);
+    let missing = InterfaceOverride { interface_number: 7, usage_page: None, usage: None };
+    assert_eq!(pick_interface(&interfaces, Some(&missing)), 1);
+    assert_eq!(pick_interface(&[], None), 0);
+
+    // Collections sharing an interface number (Windows) are told apart by usage
+    let shared = vec![interface(0, 0x01, 0x06), interface(0, 0x01, 0x04)];
+    assert_eq!(pick_interface(&shared, Some(&InterfaceOverride::of(&shared[0]))), 0);
+    assert_eq!(pick_interface(&shared, Some(&InterfaceOverride::of(&shared[1]))), 1);
+    // Migrated overrides carry no usage and pick the best collection of the interface
+    let number_only = InterfaceOverride { interface_number: 0, usage_page: None, usage: None };
+    assert_eq!(pick_interface(&shared, Some(&number_only)), 1);
+}
+
+#[test]
+fn test_report_descriptor_parsing() {
+    use vpc_shift_tool::descriptor::{ReportDescriptor, ReportKind};
+
+    // Joystick collection with 32 buttons (input report 1) and an 18 byte
+    // vendor feature report 4, like the newer shift report
+    let raw = [
+        0x05, 0x01, // Usage Page (Generic Desktop)
+        0x09, 0x04, // Usage (Joystick)
+        0xA1, 0x01, // Collection (Application)
+        0x85, 0x01, //   Report ID (1)
+        0x05, 0x09, //   Usage Page (Button)
+        0x19, 0x01, 0x29, 0x20, // Usage Minimum (1), Usage Maximum (32)
+        0x75, 0x01, 0x95, 0x20, //   Report Size (1), Report Count (32)
+        0x81, 0x02, //   Input (Data, Var, Abs)
+        0xA4,       //   Push
+        0x85, 0x04, //   Report ID (4)
+        0x06, 0x00, 0xFF, // Usage Page (Vendor)
+        0x75, 0x08, 0x95, 0x12, // Report Size (8), Report Count (18)
+        0xB1, 0x02, //   Feature (Data, Var, Abs)
+        0xB4,       //   Pop
+        0x95, 0x01, //   Report Count (1), still Report ID 1
+        0x81, 0x03, //   Input (Const)
+        0xC0,       // End Collection
+    ];
+    let descriptor = ReportDescriptor::parse(&raw).unwrap();
+    assert_eq!(descriptor.collections, vec![(0x01, 0x04)]);
+    assert_eq!(descriptor.report(ReportKind::Input, 1).unwrap().bits, 33);
+    let shift = descriptor.shift_report().unwrap();
+    assert_eq!(shift.size(), 19);
+    assert_eq!(shift.to_string(), "feature report 4: 19 bytes");
+    assert!(descriptor.hex_dump().starts_with("05 01 09 04"));
+
+    // Without the feature report there's no shift report, and cut-off items are errors
+    let without_feature = ReportDescriptor::parse(&raw[..21]).unwrap();
+    assert!(without_feature.shift_report().is_none());
+    assert!(ReportDescriptor::parse(&[0x05]).is_err());
+
+    // The page comes from the Usage item's own size, not the Collection's
+    let extended_usage = [
+        0x05, 0x09,                   // Usage Page (Button), overridden below
+        0x0B, 0x04, 0x00, 0x01, 0x00, // Usage (Generic Desktop: Joystick), extended
+        0xA1, 0x01,                   // Collection (Application)
+        0xC0,
+    ];
+    assert_eq!(ReportDescriptor::parse(&extended_usage).unwrap().collections, vec![(0x01, 0x04)]);
+    let long_collection = [
+        0x05, 0x01,                   // Usage Page (Generic Desktop)
+        0x09, 0x04,                   // Usage (Joystick)
+        0xA3, 0x01, 0x00, 0x00, 0x00, // Collection (Application), 4 byte data
+        0xC0,
+    ];
+    assert_eq!(ReportDescriptor::parse(&long_collection).unwrap().collections, vec![(0x01, 0x04)]);
+}
+
+#[test]
+fn test_rebind_after_serial_change() {
+    use vpc_shift_tool::config::Profile;
+    use vpc_shift_tool::rebind::{find_rebind_offers, rebind_device};
+
+    let slot = |product_id: u16, serial: &str| SavedDevice {
+        vendor_id: 0x3344,
+        product_id,
+        serial_number: serial.to_string(),
+        ..Default::default()
+    };
+    let mut data = ConfigData {
+        sources: vec![slot(0x8126, "OLD"), slot(0x4130, "GRIP")],
+        receivers: vec![slot(0x8126, "OLD")],
+        profiles: vec![Profile {
+            name: "Other".to_string(),
+            sources: vec![slot(0x8126, "OLD")],
+            receivers: vec![],
+            shift_modifiers: ModifiersArray::default(),
+        }],
+        ..Default::default()
+    };
+    data.device_nicknames.insert("3344:8126:OLD".to_string(), "Throttle".to_string());
+
+    // The throttle came back from repair with a new serial
+    let device_list = vec![
+        VpcDevice::default(),
+        connected_device(0x8126, "NEW", "VPC Throttle CM3"),
+        connected_device(0x4130, "GRIP", "VPC Stick MT-50CM2"),
+    ];
+    let offers = find_rebind_offers(&device_list, &data);
+    assert_eq!(offers.len(), 1); // One offer for both slots using the throttle
+    assert_eq!(offers[0].slot_label(), "Source 1");
+    assert_eq!(offers[0].candidates, vec![1]);
+    assert!(offers[0].unambiguous);
+
+    let changed = rebind_device(&mut data, &offers[0].missing, &device_list[1]);
+    assert_eq!(changed, 3);
+    assert_eq!(data.sources[0].serial_number, "NEW");
+    assert_eq!(data.receivers[0].serial_number, "NEW");
+    assert_eq!(data.profiles[0].sources[0].serial_number, "NEW");
+    assert_eq!(data.device_nicknames.get("3344:8126:NEW").map(String::as_str), Some("Throttle"));
+    assert!(find_rebind_offers(&device_list, &data).is_empty());
+
+    // Two missing throttles and one spare: offered, but not automatically
+    data.sources[0].serial_number = "A".to_string();
+    data.receivers[0].serial_number = "B".to_string();
+    let offers = find_rebind_offers(&device_list, &data);
+    assert_eq!(offers.len(), 2);
+    assert!(offers.iter().all(|offer| !offer.unambiguous));
+}
+
+#[test]
+fn test_slot_matchers() {
+    use vpc_shift_tool::device::resolve_slot_devices;
+
+    let mut rejected = connected_device(0x8126, "OLDFW", "VPC Throttle CM3");
+    rejected.unsupported = Some("Firmware too old".to_string());
+    let device_list = vec![
+        VpcDevice::default(),
+        connected_device(0x8126, "T1", "VPC Throttle CM3"),
+        connected_device(0x8126, "T2", "VPC Throttle CM3 (left)"),
+        connected_device(0x4130, "GRIP", "VPC Stick MT-50CM2"),
+        rejected,
+    ];
+    let mut slot = SavedDevice {
+        vendor_id: 0x3344,
+        product_id: 0x8126,
+        serial_number: "T2".to_string(),
+        ..Default::default()
+    };
+
+    // Exact is the default, also for configs written before matchers existed
+    let parsed: SavedDevice = serde_json::from_str(
+        r#"{"vendor_id":13124,"product_id":33062,"serial_number":"T2","state_enabled":[true,true,true,true,true,true,true,true]}"#,
+    )
+    .unwrap();
+    assert_eq!(parsed.matcher, SlotMatcher::Exact);
+    assert_eq!(resolve_slot_devices(&device_list, &slot), vec![2]);
+    slot.serial_number = "GONE".to_string();
+    assert!(resolve_slot_devices(&device_list, &slot).is_empty());
+
+    // Same model: the saved serial if connected, otherwise the first usable one
+    slot.matcher = SlotMatcher::SameModel;
+    assert_eq!(resolve_slot_devices(&device_list, &slot), vec![1]);
+    slot.serial_number = "T2".to_string();
+    assert_eq!(resolve_slot_devices(&device_list, &slot), vec![2]);
+
+    // Name pattern fans out to every usable match, ignoring case
+    slot.matcher = SlotMatcher::NamePattern;
+    slot.name_pattern = "throttle cm3".to_string();
+    assert_eq!(resolve_slot_devices(&device_list, &slot), vec![1, 2]);
+    slot.name_pattern = "  ".to_string();
+    assert!(resolve_slot_devices(&device_list, &slot).is_empty());
+
+    // Matcher slots aren't offered for rebinding
+    let mut data = ConfigData::default();
+    slot.matcher = SlotMatcher::SameModel;
+    slot.serial_number = "GONE".to_string();
+    data.receivers.push(slot);
+    assert!(vpc_shift_tool::rebind::find_rebind_offers(&device_list, &data).is_empty());
+}
+
+#[test]
+fn test_matcher_slots_skip_devices_other_slots_use() {
+    use vpc_shift_tool::device::resolve_all_slots;
+
+    let device_list = vec![
+        VpcDevice::default(),
+        connected_device(0x8126, "T1", "VPC Throttle CM3"),
+        connected_device(0x8126, "T2", "VPC Throttle CM3 (left)"),
+        connected_device(0x4130, "GRIP", "VPC Stick MT-50CM2"),
+    ];
+    let exact = |product_id: u16, serial: &str| SavedDevice {
+        vendor_id: 0x3344,
+        product_id,
+        serial_number: serial.to_string(),
+        ..Default::default()
+    };
+    let pattern = |name: &str| SavedDevice {
+        matcher: SlotMatcher::NamePattern,
+        name_pattern: name.to_string(),
+        ..Default::default()
+    };
+
+    // A receiver pattern that also matches the source leaves it out
+    let sources = vec![exact(0x8126, "T1")];
+    let receivers = vec![pattern("VPC")];
+    let (s, r) = resolve_all_slots(&device_list, &sources, &receivers);
+    assert_eq!(s, vec![vec![1]]);
+    assert_eq!(r, vec![vec![2, 3]]);
+
+    // Overlapping patterns: the earlier slot keeps the shared devices
+    let sources = vec![pattern("throttle"), pattern("cm3")];
+    let (s, r) = resolve_all_slots(&device_list, &sources, &[]);
+    assert_eq!(s, vec![vec![1, 2], vec![]]);
+    assert!(r.is_empty());
+
+    // Same model falls back to another device when the saved one is taken
+    let mut same_model = exact(0x8126, "T1");
+    same_model.matcher = SlotMatcher::SameModel;
+    let (s, r) = resolve_all_slots(&device_list, &[exact(0x8126, "T1")], &[same_model.clone()]);
+    assert_eq!(s, vec![vec![1]]);
+    assert_eq!(r, vec![vec![2]]);
+
+    // A disabled slot doesn't claim its device
+    let mut disabled = exact(0x8126, "T1");
+    disabled.enabled = false;
+    let (_, r) = resolve_all_slots(&device_list, &[disabled], &[same_model]);
+    assert_eq!(r, vec![vec![1]]);
+}
+
+#[test]
+fn test_report_console_input() {
+    use vpc_shift_tool::report_console::{
+        format_hex, parse_hex, ConsoleEntry, ConsoleRequest, ReportConsole, MAX_CONSOLE_HISTORY,
+    };
+
+    assert_eq!(parse_hex("04 00 1f").unwrap(), vec![0x04, 0x00, 0x1F]);
+    assert_eq!(parse_hex("0x04,0x00, 0X1F").unwrap(), vec![0x04, 0x00, 0x1F]);
+    assert_eq!(parse_hex("04001F").unwrap(), vec![0x04, 0x00, 0x1F]);
+    assert_eq!(parse_hex("4").unwrap(), vec![0x04]);
+    assert!(parse_hex("").unwrap().is_empty());
+    assert!(parse_hex("123").is_err());
+    assert!(parse_hex("zz").is_err());
+    assert_eq!(format_hex(&[0x04, 0x00, 0x1F]), "04 00 1F");
+
+    let mut console = ReportConsole::default();
+    assert_eq!(console.get_request(), Ok(ConsoleRequest::Get { report_id: 4, length: 19 }));
+    console.report_id = "04 05".to_string();
+    assert!(console.get_request().is_err());
+    assert!(console.send_request().is_err()); // Nothing entered
+    console.payload = "04 00 01".to_string();
+    let send = console.send_request().unwrap();
+    assert_eq!(send.to_string(), "SEND 04 00 01");
+
+    // History keeps the newest entries and can refill the inputs
+    for i in 0..MAX_CONSOLE_HISTORY + 5 {
+        console.record(ConsoleEntry {
+            time: "12:00:00".to_string(),
+            device: "3344:8126:T1".to_string(),
+            request: ConsoleRequest::Get { report_id: i as u8, length: 2 },
+            result: Ok(vec![i as u8, 1]),
+        });
+    }
+    assert_eq!(console.history.len(), MAX_CONSOLE_HISTORY);
+    assert_eq!(console.history[0].to_string(), "12:00:00 GET 68 (2 bytes) → 68 01");
+    let request = console.history[0].request.clone();
+    console.recall(&request);
+    assert_eq!((console.report_id.as_str(), console.length), ("68", 2));
+}
+
+#[test]
+fn test_trace_replay() {
+    use vpc_shift_tool::hid_worker::replay_trace;
+    use vpc_shift_tool::trace::{read_trace_file, TraceKind, TraceLine, TraceWriter};
+
+    // Recorded traces from bug reports live in tests/fixtures and must keep replaying cleanly
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/trace_two_sources.jsonl");
+    let trace = read_trace_file(&path).unwrap();
+    let report = replay_trace(&trace).unwrap();
+    assert_eq!(report.cycles, 3);
+    assert!(report.passed(), "{}", report);
+
+    // A different rule changes what the receivers get
+    let mut changed = trace.clone();
+    if let TraceLine::Settings(settings) = &mut changed.lines[0] {
+        settings.shift_modifiers[1] = ShiftModifiers::OR;
+    }
+    let report = replay_trace(&changed).unwrap();
+    assert!(!report.passed());
+    assert!(report.mismatches[0].starts_with("Cycle 1: receiver 0 was sent"), "{}", report);
+
+    // What the writer records reads back the same
+    let dir = std::env::temp_dir().join(format!("shift_tool_trace_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let out = dir.join("trace.jsonl");
+    let settings = match &trace.lines[0] {
+        TraceLine::Settings(settings) => settings.clone(),
+        _ => unreachable!(),
+    };
+    let mut writer = TraceWriter::create(&out, settings.clone()).unwrap();
+    writer.record(TraceKind::SourceRead, 1, Ok(&[0x04, 0x01]), Some(1));
+    writer.record(TraceKind::ReceiverWrite, 0, Err("Broken pipe"), Some(0));
+    writer.end_cycle(1);
+    drop(writer);
+    let written = read_trace_file(&out).unwrap();
+    assert_eq!(written.lines.len(), 4);
+    assert_eq!(written.lines[0], TraceLine::Settings(settings));
+    match &written.lines[2] {
+        TraceLine::Event(event) => {
+            assert_eq!(event.kind, TraceKind::ReceiverWrite);
+            assert_eq!(event.error.as_deref(), Some("Broken pipe"));
+        }
+        other => panic!("unexpected line {:?}", other),
+    }
+    assert!(read_trace_file(&dir.join("missing.jsonl")).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_matcher_slot_counts_once_in_rules() {
+    use vpc_shift_tool::hid_worker::replay_trace;
+    use vpc_shift_tool::trace::{Trace, TraceLine};
+
+    // Two devices matched by source slot 0 under an AND rule: only one has
+    // bit 0 set, but the slot is their OR, so the result has it too
+    let all = "[true, true, true, true, true, true, true, true]";
+    let lines = [
+        format!(
+            r#"{{"type": "settings", "shift_modifiers": {{"data": ["AND", "OR", "OR", "OR", "OR", "OR", "OR", "OR"]}},
+                "sources": [{{"slot": 0, "report_size": 2, "enabled": true, "state_enabled": {all}}},
+                            {{"slot": 0, "report_size": 2, "enabled": true, "state_enabled": {all}}}],
+                "receivers": [{{"slot": 0, "report_size": 2, "enabled": true, "state_enabled": {all}}}]}}"#
+        ),
+        r#"{"type": "event", "t_ms": 0, "kind": "source_read", "slot": 0, "report": "04 01", "state": 1}"#.to_string(),
+        r#"{"type": "event", "t_ms": 0, "kind": "source_read", "slot": 1, "report": "04 00", "state": 0}"#.to_string(),
+        r#"{"type": "event", "t_ms": 0, "kind": "receiver_write", "slot": 0, "report": "04 00", "state": 0}"#.to_string(),
+        r#"{"type": "event", "t_ms": 0, "kind": "receiver_read", "slot": 0, "report": "04 00", "state": 0}"#.to_string(),
+        r#"{"type": "event", "t_ms": 0, "kind": "receiver_write", "slot": 0, "report": "04 01", "state": 1}"#.to_string(),
+        r#"{"type": "event", "t_ms": 0, "kind": "cycle", "state": 1}"#.to_string(),
+    ];
+    let trace = Trace {
+        lines: lines.iter().map(|line| serde_json::from_str::<TraceLine>(line).unwrap()).collect(),
+    };
+    let report = replay_trace(&trace).unwrap();
+    assert_eq!(report.cycles, 1);
+    assert!(report.passed(), "{}", report);
+}
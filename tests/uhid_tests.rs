@@ -0,0 +1,337 @@
+// End-to-end tests against virtual Virpil devices created through Linux uhid.
+//
+// Each `VirtualVirpil` is a real HID device as far as the kernel, hidraw and
+// hidapi are concerned; a thread answers its feature report requests. The
+// tests need read/write access to /dev/uhid and to the hidraw nodes it
+// creates (in practice: root), so they're ignored by default; run them with
+// `cargo test --test uhid_tests -- --ignored`, where they fail if uhid can't
+// be used.
+#![cfg(target_os = "linux")]
+
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use vpc_shift_tool::descriptor::ReportDescriptor;
+use vpc_shift_tool::device::SavedDevice;
+use vpc_shift_tool::config::FirmwarePolicy;
+use vpc_shift_tool::util::FirmwareVersion;
+use vpc_shift_tool::ShiftTool;
+
+// From linux/uhid.h
+const UHID_PATH: &str = "/dev/uhid";
+const UHID_GET_REPORT: u32 = 9;
+const UHID_GET_REPORT_REPLY: u32 = 10;
+const UHID_CREATE2: u32 = 11;
+const UHID_SET_REPORT: u32 = 13;
+const UHID_SET_REPORT_REPLY: u32 = 14;
+const UHID_FEATURE_REPORT: u8 = 0;
+const UHID_EVENT_SIZE: usize = 4 + 4372; // Type, then the largest request (create2)
+const BUS_USB: u16 = 0x03;
+const EIO: u16 = 5;
+const O_NONBLOCK: i32 = 0o4000;
+
+const VIRPIL_VID: u16 = 0x3344;
+const SHIFT_REPORT_ID: u8 = 4;
+const ORIGINAL_SIZE: usize = 2; // Report ID, state
+const NEW_SIZE: usize = 19; // Report ID, high byte, low byte, 16 unused
+const WAIT: Duration = Duration::from_secs(5);
+
+/// Joystick with 8 buttons (input report 1) and a vendor feature report 4
+/// of `report_size` bytes, report ID included.
+fn virpil_descriptor(report_size: usize) -> Vec<u8> {
+    vec![
+        0x05, 0x01, // Usage Page (Generic Desktop)
+        0x09, 0x04, // Usage (Joystick)
+        0xA1, 0x01, // Collection (Application)
+        0x85, 0x01, //   Report ID (1)
+        0x05, 0x09, //   Usage Page (Button)
+        0x19, 0x01, 0x29, 0x08, // Usage Minimum (1), Usage Maximum (8)
+        0x15, 0x00, 0x25, 0x01, // Logical Minimum (0), Logical Maximum (1)
+        0x75, 0x01, 0x95, 0x08, // Report Size (1), Report Count (8)
+        0x81, 0x02, //   Input (Data, Var, Abs)
+        0x85, SHIFT_REPORT_ID, // Report ID (4)
+        0x06, 0x00, 0xFF, // Usage Page (Vendor)
+        0x09, 0x01, //   Usage (1)
+        0x15, 0x00, 0x26, 0xFF, 0x00, // Logical Minimum (0), Logical Maximum (255)
+        0x75, 0x08, 0x95, (report_size - 1) as u8, // Report Size (8), Report Count
+        0xB1, 0x02, //   Feature (Data, Var, Abs)
+        0xC0,       // End Collection
+    ]
+}
+
+/// A fake Virpil device that lives until dropped.
+struct VirtualVirpil {
+    product_id: u16,
+    serial: String,
+    report: Arc<Mutex<Vec<u8>>>, // Current feature report 4, report ID first
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl VirtualVirpil {
+    /// Creates the device; `report_size` picks the shift report layout.
+    fn create(name: &str, product_id: u16, serial: &str, report_size: usize) -> std::io::Result<Self> {
+        let mut file = open_uhid()?;
+
+        let descriptor = virpil_descriptor(report_size);
+        let mut event = vec![0u8; UHID_EVENT_SIZE];
+        event[0..4].copy_from_slice(&UHID_CREATE2.to_ne_bytes());
+        put_str(&mut event[4..132], name);
+        put_str(&mut event[196..260], serial); // uniq, which hidraw reports as the serial number
+        event[260..262].copy_from_slice(&(descriptor.len() as u16).to_ne_bytes());
+        event[262..264].copy_from_slice(&BUS_USB.to_ne_bytes());
+        event[264..268].copy_from_slice(&u32::from(VIRPIL_VID).to_ne_bytes());
+        event[268..272].copy_from_slice(&u32::from(product_id).to_ne_bytes());
+        event[280..280 + descriptor.len()].copy_from_slice(&descriptor);
+        file.write_all(&event)?;
+
+        let mut initial = vec![0u8; report_size];
+        initial[0] = SHIFT_REPORT_ID;
+        let report = Arc::new(Mutex::new(initial));
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let (report, stop) = (report.clone(), stop.clone());
+            thread::spawn(move || serve(file, &report, &stop))
+        };
+        Ok(Self { product_id, serial: serial.to_string(), report, stop, thread: Some(thread) })
+    }
+
+    /// The slot config for this device.
+    fn slot(&self) -> SavedDevice {
+        SavedDevice {
+            vendor_id: VIRPIL_VID,
+            product_id: self.product_id,
+            serial_number: self.serial.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Sets the shift state the device reports, in its own layout.
+    fn set_state(&self, state: u16) {
+        let mut report = self.report.lock().unwrap();
+        if report.len() == ORIGINAL_SIZE {
+            report[1] = state as u8;
+        } else {
+            report[1] = (state >> 8) as u8;
+            report[2] = state as u8;
+        }
+    }
+
+    /// The shift state last written to the device.
+    fn state(&self) -> u16 {
+        let report = self.report.lock().unwrap();
+        if report.len() == ORIGINAL_SIZE {
+            u16::from(report[1])
+        } else {
+            u16::from(report[1]) << 8 | u16::from(report[2])
+        }
+    }
+}
+
+// Stopping the thread closes the uhid file, which removes the device
+impl Drop for VirtualVirpil {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn open_uhid() -> std::io::Result<File> {
+    OpenOptions::new().read(true).write(true).custom_flags(O_NONBLOCK).open(UHID_PATH)
+}
+
+fn put_str(field: &mut [u8], text: &str) {
+    let len = text.len().min(field.len() - 1); // Keep the NUL terminator
+    field[..len].copy_from_slice(&text.as_bytes()[..len]);
+}
+
+// Answers the kernel's feature report requests until told to stop
+fn serve(mut file: File, report: &Mutex<Vec<u8>>, stop: &AtomicBool) {
+    let mut event = vec![0u8; UHID_EVENT_SIZE];
+    while !stop.load(Ordering::Relaxed) {
+        match file.read(&mut event) {
+            Ok(len) if len >= 4 => {}
+            Ok(_) => continue,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(1));
+                continue;
+            }
+            Err(_) => return,
+        }
+        let kind = u32::from_ne_bytes(event[0..4].try_into().unwrap());
+        let (id, rnum, rtype) = (&event[4..8], event[8], event[9]);
+        let ours = rnum == SHIFT_REPORT_ID && rtype == UHID_FEATURE_REPORT;
+        let mut reply = vec![0u8; UHID_EVENT_SIZE];
+        reply[4..8].copy_from_slice(id);
+        match kind {
+            UHID_GET_REPORT => {
+                reply[0..4].copy_from_slice(&UHID_GET_REPORT_REPLY.to_ne_bytes());
+                if ours {
+                    let report = report.lock().unwrap();
+                    reply[10..12].copy_from_slice(&(report.len() as u16).to_ne_bytes());
+                    reply[12..12 + report.len()].copy_from_slice(&report);
+                } else {
+                    reply[8..10].copy_from_slice(&EIO.to_ne_bytes());
+                }
+            }
+            UHID_SET_REPORT => {
+                reply[0..4].copy_from_slice(&UHID_SET_REPORT_REPLY.to_ne_bytes());
+                let size = usize::from(u16::from_ne_bytes([event[10], event[11]]));
+                let mut report = report.lock().unwrap();
+                // Like the firmware, only a report of the declared length is taken
+                if ours && size == report.len() {
+                    report.copy_from_slice(&event[12..12 + size]);
+                } else {
+                    reply[8..10].copy_from_slice(&EIO.to_ne_bytes());
+                }
+            }
+            _ => continue, // Start, open, close, ...: nothing to answer
+        }
+        if file.write_all(&reply).is_err() {
+            return;
+        }
+    }
+}
+
+// Polls `done` until it holds or WAIT runs out
+fn wait_for(mut done: impl FnMut() -> bool) -> bool {
+    let started = Instant::now();
+    while started.elapsed() < WAIT {
+        if done() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    done()
+}
+
+// Fails the test if this machine can't create uhid devices
+fn require_uhid() {
+    if let Err(e) = open_uhid() {
+        panic!("can't open {} ({}); run the ignored uhid tests as root on Linux", UHID_PATH, e);
+    }
+}
+
+fn listed(tool: &ShiftTool, device: &VirtualVirpil) -> bool {
+    tool.device_list.iter().any(|d| d.serial_number == device.serial)
+}
+
+#[test]
+fn test_uhid_descriptors() {
+    // The harness descriptors declare the shift report in both layouts
+    for size in [ORIGINAL_SIZE, NEW_SIZE] {
+        let descriptor = ReportDescriptor::parse(&virpil_descriptor(size)).unwrap();
+        assert_eq!(descriptor.collections, vec![(0x01, 0x04)]);
+        assert_eq!(descriptor.shift_report().unwrap().size(), size);
+    }
+}
+
+#[test]
+#[ignore = "needs /dev/uhid; run with --ignored"]
+fn test_uhid_worker_end_to_end() {
+    require_uhid();
+    let id = std::process::id();
+    let source = VirtualVirpil::create("VPC Virtual Throttle", 0x8101, &format!("UHID{}S", id), ORIGINAL_SIZE).unwrap();
+    let receiver_new = VirtualVirpil::create("VPC Virtual Stick", 0x8102, &format!("UHID{}N", id), NEW_SIZE).unwrap();
+    let receiver_old = VirtualVirpil::create("VPC Virtual Panel", 0x8103, &format!("UHID{}O", id), ORIGINAL_SIZE).unwrap();
+
+    // The firmware policy is on, with a minimum date the devices could fail
+    let dir = std::env::temp_dir().join(format!("shift_tool_uhid_{}", id));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut tool = ShiftTool::new(&dir.join("config.json"), false, None).unwrap();
+    tool.config.data.firmware_policy = FirmwarePolicy {
+        minimum_date: "2022-07-20".to_string(),
+        known_bad: vec!["VIRPIL Controls 20230328".to_string()],
+        ..Default::default()
+    };
+    tool.config.data.sources = vec![source.slot()];
+    tool.config.data.receivers = vec![receiver_new.slot(), receiver_old.slot()];
+    tool.add_source_state();
+    tool.add_receiver_state();
+    tool.add_receiver_state();
+
+    // hidapi finds them once the hidraw nodes exist
+    let devices = [&source, &receiver_new, &receiver_old];
+    assert!(
+        wait_for(|| {
+            tool.refresh_devices();
+            devices.iter().all(|d| listed(&tool, d))
+        }),
+        "virtual devices never showed up in the device list"
+    );
+    // hidraw has no manufacturer string for uhid devices: no firmware, which
+    // the policy accepts rather than locking the device out
+    for device in devices {
+        let entry = tool.device_list.iter().find(|d| d.serial_number == device.serial).unwrap();
+        assert_eq!(entry.vendor_id, VIRPIL_VID);
+        assert_eq!(entry.product_id, device.product_id);
+        assert!(entry.firmware.is_unknown());
+        assert!(entry.unsupported.is_none());
+    }
+
+    // Give the listed devices the firmware a real one would report, so the
+    // worker's first guess goes through the firmware date rules: original for
+    // the source, new for both receivers
+    for (device, firmware) in [
+        (&source, "VIRPIL Controls 20240101"),
+        (&receiver_new, "VIRPIL Controls 20250101"),
+        (&receiver_old, "VIRPIL Controls 20250101"),
+    ] {
+        let entry = tool.device_list.iter_mut().find(|d| d.serial_number == device.serial).unwrap();
+        entry.firmware = FirmwareVersion::parse(firmware);
+    }
+
+    // The worker reads the source and writes the state to both receivers
+    source.set_state(0b0000_0101);
+    *tool.thread_state.0.lock().unwrap() = true;
+    assert!(tool.spawn_worker());
+    assert!(
+        wait_for(|| receiver_new.state() == 0b0000_0101 && receiver_old.state() == 0b0000_0101),
+        "receivers never got the source state"
+    );
+    assert_eq!(*tool.shift_state.lock().unwrap(), 0b0000_0101);
+
+    // The descriptor overrides the firmware guess for the old-layout receiver
+    {
+        let descriptors = tool.descriptors.lock().unwrap();
+        for (device, size) in [(&source, ORIGINAL_SIZE), (&receiver_new, NEW_SIZE), (&receiver_old, ORIGINAL_SIZE)] {
+            let descriptor = descriptors[&device.slot().device_key()].as_ref().unwrap();
+            assert_eq!(descriptor.shift_report().unwrap().size(), size);
+        }
+    }
+
+    // Changes on the source follow through
+    source.set_state(0b0000_0010);
+    assert!(
+        wait_for(|| receiver_new.state() == 0b0000_0010 && receiver_old.state() == 0b0000_0010),
+        "receivers never got the new source state"
+    );
+
+    // Stopping the worker resets the receivers
+    {
+        let (lock, cvar) = &*tool.thread_state;
+        *lock.lock().unwrap() = false;
+        cvar.notify_all();
+    }
+    tool.worker_handle.take().unwrap().join().unwrap();
+    tool.stop_worker_cleanup();
+    assert_eq!(receiver_new.state(), 0);
+    assert_eq!(receiver_old.state(), 0);
+    assert!(tool.receiver_states.iter().all(|state| *state.lock().unwrap() == 0));
+
+    // An unplugged device drops out of the list
+    let gone = receiver_old.serial.clone();
+    drop(receiver_old);
+    assert!(wait_for(|| {
+        tool.refresh_devices();
+        !tool.device_list.iter().any(|d| d.serial_number == gone)
+    }));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}